@@ -0,0 +1,79 @@
+//! Benchmarks counting the blocks of a single full region: one chunk after
+//! another against in parallel (`ScanOptions::parallel_chunks`), and with the
+//! options that change how block names are worked out. Run with
+//! `cargo bench --bench chunk_counting`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+// The fixtures take the DataVersion of their chunks from the crate root.
+use region_scanner::{count_blocks, ScanOptions, DATA_VERSION_1_21};
+
+#[allow(dead_code)]
+#[path = "../src/fixtures.rs"]
+mod fixtures;
+use fixtures::*;
+
+/// A region with all 1024 chunks present, each with 8 sections of stone
+/// sprinkled with ores, one of which has a property.
+fn full_region() -> Vec<((usize, usize), Vec<u8>)> {
+    let ores = [
+        "minecraft:stone",
+        "minecraft:iron_ore",
+        "minecraft:redstone_ore[lit=false]",
+    ];
+    let chunk = ChunkNbt::full(
+        (-4..4)
+            .map(|y| SectionNbt::with_blocks(y, &ores, |x, y, z| (x * 7 + y * 3 + z) % 23 % 3))
+            .collect(),
+    )
+    .to_bytes();
+    itertools::iproduct!(0..32, 0..32)
+        .map(|pos| (pos, chunk.clone()))
+        .collect()
+}
+
+fn bench_chunk_counting(c: &mut Criterion) {
+    let mut region = region_with(&full_region());
+    let mut group = c.benchmark_group("single region");
+    group.sample_size(10);
+    for (name, parallel_chunks) in [("serial", false), ("parallel", true)] {
+        let options = ScanOptions {
+            parallel_chunks,
+            ..Default::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| count_blocks(&mut region, (0, 0), false, "test:dim", &options))
+        });
+    }
+    group.finish();
+}
+
+fn bench_block_names(c: &mut Criterion) {
+    let mut region = region_with(&full_region());
+    let mut group = c.benchmark_group("block names");
+    group.sample_size(10);
+    for (name, options) in [
+        ("plain", ScanOptions::default()),
+        (
+            "normalized",
+            ScanOptions {
+                normalize_names: true,
+                ..Default::default()
+            },
+        ),
+        (
+            "with properties",
+            ScanOptions {
+                track_properties: true,
+                ..Default::default()
+            },
+        ),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| count_blocks(&mut region, (0, 0), false, "test:dim", &options))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_chunk_counting, bench_block_names);
+criterion_main!(benches);
@@ -0,0 +1,127 @@
+//! Player activity, as recorded by the InhabitedTime of chunks.
+
+use std::{
+    fmt::Write,
+    io::{Read, Seek},
+};
+
+use fastanvil::Region;
+use serde::Deserialize;
+
+use crate::{is_full_status, readable_chunks, ProtoOption};
+
+#[derive(Deserialize)]
+struct ChunkActivity {
+    #[serde(rename = "InhabitedTime")]
+    inhabited_time: Option<i64>,
+    #[serde(rename = "Status")]
+    status: Option<String>,
+    /// Before 1.18, everything was in a Level compound.
+    #[serde(rename = "Level")]
+    level: Option<Box<ChunkActivity>>,
+}
+
+/// Reads the InhabitedTime of a chunk, which is the total number of ticks
+/// players have spent near it, along with whether the chunk is fully
+/// generated. This only deserializes the two fields, so it's much cheaper than
+/// parsing the whole chunk.
+pub fn chunk_inhabited_time(data: &[u8]) -> Option<(i64, bool)> {
+    let chunk: ChunkActivity = fastnbt::from_bytes(data).ok()?;
+    let chunk = match chunk.level {
+        Some(level) => *level,
+        None => chunk,
+    };
+    // Pre-1.13 chunks have no status, and are always complete.
+    let full = chunk.status.as_deref().is_none_or(is_full_status);
+    Some((chunk.inhabited_time?, full))
+}
+
+/// The activity in a single region.
+pub struct RegionActivity {
+    pub dimension: String,
+    pub x: isize,
+    pub z: isize,
+    /// The sum of the InhabitedTime of all chunks of the region.
+    pub inhabited_ticks: u64,
+    pub chunks_counted: usize,
+}
+
+/// Sums up the InhabitedTime of the chunks in a region that pass the
+/// protochunk filter. Returns the total and the number of chunks summed.
+pub fn region_inhabited_time<R: Read + Seek>(
+    region: &mut Region<R>,
+    proto: ProtoOption,
+) -> (u64, usize) {
+    let mut total = 0;
+    let mut chunks_counted = 0;
+    for data in readable_chunks(region) {
+        let Some((ticks, full)) = chunk_inhabited_time(&data.data) else {
+            continue;
+        };
+        if proto.accepts(full) {
+            total += ticks.max(0) as u64;
+            chunks_counted += 1;
+        }
+    }
+    (total, chunks_counted)
+}
+
+/// Generates a CSV with a row per region, usable as a player activity heatmap.
+pub fn generate_inhabited_time_csv(activity: &[RegionActivity]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,region_x,region_z,total_inhabited_ticks,chunks\n")
+        .unwrap();
+    for region in activity {
+        writeln!(
+            res,
+            "{},{},{},{},{}",
+            region.dimension, region.x, region.z, region.inhabited_ticks, region.chunks_counted
+        )
+        .expect("Error when assembling CSV");
+    }
+    res
+}
+
+#[test]
+fn test_region_inhabited_time() {
+    use crate::fixtures::*;
+    let chunk = |inhabited_time, status: &str| {
+        ChunkNbt {
+            inhabited_time,
+            status: status.to_owned(),
+            ..ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")])
+        }
+        .to_bytes()
+    };
+    let mut region = region_with(&[
+        ((0, 0), chunk(1200, "minecraft:full")),
+        ((1, 0), chunk(34, "minecraft:full")),
+        // Negative times don't take anything away from the total.
+        ((2, 0), chunk(-5, "minecraft:full")),
+        ((0, 1), chunk(700, "minecraft:features")),
+    ]);
+    assert_eq!(
+        region_inhabited_time(&mut region, ProtoOption::Skip),
+        (1234, 3)
+    );
+    assert_eq!(
+        region_inhabited_time(&mut region, ProtoOption::Include),
+        (1934, 4)
+    );
+    assert_eq!(
+        region_inhabited_time(&mut region, ProtoOption::OnlyProto),
+        (700, 1)
+    );
+
+    let csv = generate_inhabited_time_csv(&[RegionActivity {
+        dimension: "minecraft:overworld".to_owned(),
+        x: -1,
+        z: 2,
+        inhabited_ticks: 1234,
+        chunks_counted: 3,
+    }]);
+    assert_eq!(
+        csv,
+        "dim,region_x,region_z,total_inhabited_ticks,chunks\nminecraft:overworld,-1,2,1234,3\n"
+    );
+}
@@ -0,0 +1,272 @@
+//! Scanning worlds inside zip backups, reading the region files straight out
+//! of the archive instead of extracting it.
+
+use std::{
+    fs::File,
+    io::{self, Cursor, Read},
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+
+use fastanvil::{RCoord, Region};
+use zip::{result::ZipError, ZipArchive};
+
+use crate::{
+    readable_region, scan_each_dimension, scan_source, RegionSource, RegionStream, ScanHooks,
+    ScanOptions, ScanResults, Zone,
+};
+
+/// A world in a zip archive. The world can be at the root of the archive or in
+/// a folder of it, which is found by its `level.dat`, or by its `region`
+/// folder if there's none.
+pub struct ZipWorld {
+    archive: Mutex<ZipArchive<File>>,
+    /// The folder of the world in the archive, like `MyWorld/`, or empty if
+    /// it's at the root.
+    root: String,
+    /// The names and sizes of the region files in the archive.
+    region_files: Vec<(String, u64)>,
+}
+
+impl ZipWorld {
+    /// Opens the world in the folder `world` of the archive at `path` (empty
+    /// for its root), or without it, the only world in the archive. An archive
+    /// with several worlds, like a backup of a whole saves folder, is an error
+    /// listing them.
+    pub fn open(path: &Path, world: Option<&str>) -> io::Result<Self> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let mut region_files = vec![];
+        let mut level_dats = vec![];
+        for i in 0..archive.len() {
+            // Only reads the metadata of the entry, without decompressing it.
+            let entry = archive.by_index_raw(i)?;
+            if !entry.is_file() {
+                continue;
+            }
+            let name = entry.name();
+            if name.ends_with(".mca") {
+                region_files.push((name.to_owned(), entry.size()));
+            } else if name == "level.dat" || name.ends_with("/level.dat") {
+                level_dats.push(name.trim_end_matches("level.dat").to_owned());
+            }
+        }
+        // Without a level.dat, the world is wherever the overworld regions are.
+        if level_dats.is_empty() {
+            level_dats = region_files
+                .iter()
+                .filter_map(|(name, _)| Some(name[..name.rfind("region/")?].to_owned()))
+                .filter(|root| root.is_empty() || root.ends_with('/'))
+                .collect();
+        }
+        level_dats.sort_unstable();
+        level_dats.dedup();
+        let worlds = || {
+            level_dats
+                .iter()
+                .map(|root| match root.as_str() {
+                    "" => "'' (the root)".to_owned(),
+                    root => format!("'{}'", root.trim_end_matches('/')),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let root = match world {
+            Some(world) => {
+                let world = world.trim_matches('/');
+                let root = if world.is_empty() {
+                    String::new()
+                } else {
+                    format!("{world}/")
+                };
+                if !level_dats.contains(&root) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!(
+                            "there's no world in the folder '{world}' of the archive, the worlds \
+                             found are: {}",
+                            worlds()
+                        ),
+                    ));
+                }
+                root
+            }
+            None if level_dats.len() > 1 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "the archive holds several worlds, pick one of them with --world: {}",
+                        worlds()
+                    ),
+                ))
+            }
+            None => level_dats.pop().unwrap_or_default(),
+        };
+        Ok(Self {
+            archive: Mutex::new(archive),
+            root,
+            region_files,
+        })
+    }
+
+    /// The folder of the world in the archive, empty if it's at the root.
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// The regions in `folder`, a path relative to the world like
+    /// `DIM-1/region`.
+    pub fn region_folder(&self, folder: &Path) -> ZipRegionFolder<'_> {
+        let mut prefix = self.root.clone();
+        for component in folder.components() {
+            if let Component::Normal(part) = component {
+                prefix.push_str(&part.to_string_lossy());
+                prefix.push('/');
+            }
+        }
+        ZipRegionFolder {
+            world: self,
+            prefix,
+        }
+    }
+}
+
+/// A region folder in a [`ZipWorld`]. Regions are decompressed into memory
+/// one at a time, as the entries of an archive can only be read one after the
+/// other.
+pub struct ZipRegionFolder<'a> {
+    world: &'a ZipWorld,
+    /// The path of the folder in the archive, ending with a slash.
+    prefix: String,
+}
+
+impl ZipRegionFolder<'_> {
+    /// Whether the folder has any region files.
+    pub fn exists(&self) -> bool {
+        self.world
+            .region_files
+            .iter()
+            .any(|(name, _)| name.starts_with(&self.prefix))
+    }
+}
+
+/// The coordinates of a region file named like `r.-1.2.mca`.
+fn parse_region_name(name: &str) -> Option<(RCoord, RCoord)> {
+    let (x, z) = name
+        .strip_prefix("r.")?
+        .strip_suffix(".mca")?
+        .split_once('.')?;
+    Some((RCoord(x.parse().ok()?), RCoord(z.parse().ok()?)))
+}
+
+impl RegionSource for ZipRegionFolder<'_> {
+    type Stream = RegionStream<Cursor<Vec<u8>>>;
+
+    fn region(&self, x: RCoord, z: RCoord) -> fastanvil::Result<Option<Region<Self::Stream>>> {
+        let name = format!("{}r.{}.{}.mca", self.prefix, x.0, z.0);
+        let data = {
+            let mut archive = self.world.archive.lock().unwrap();
+            let mut entry = match archive.by_name(&name) {
+                Ok(entry) => entry,
+                Err(ZipError::FileNotFound) => return Ok(None),
+                Err(e) => return Err(io::Error::from(e).into()),
+            };
+            let mut data = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut data)?;
+            data
+        };
+        readable_region(Cursor::new(data), (x.0, z.0)).map(Some)
+    }
+
+    fn list(&self) -> io::Result<Vec<(RCoord, RCoord)>> {
+        // Like RegionFileLoader, empty region files are left out.
+        Ok(self
+            .world
+            .region_files
+            .iter()
+            .filter(|(_, size)| *size > 0)
+            .filter_map(|(name, _)| parse_region_name(name.strip_prefix(&self.prefix)?))
+            .collect())
+    }
+}
+
+/// Scans the dimensions of a world in a zip archive like
+/// [`scan_dimensions`](crate::scan_dimensions) does those of a world on disk.
+/// The paths of `dim_paths` are those of the region folders relative to the
+/// world, like `DIM-1/region`.
+pub fn scan_archive(
+    world: &ZipWorld,
+    dim_paths: &[(&str, PathBuf)],
+    zone: Option<Zone>,
+    options: &ScanOptions,
+    hooks: &ScanHooks,
+) -> ScanResults {
+    scan_each_dimension(dim_paths, hooks, |dim, path, manifest| {
+        let source = world.region_folder(path);
+        scan_source(&source, path, zone, dim, options, manifest, hooks)
+    })
+}
+
+#[test]
+fn test_scan_archive() {
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+
+    use crate::fixtures::*;
+
+    let dir = TempDir::new("archive");
+    let path = dir.join("world.zip");
+    let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+    let mut add = |name: &str, data: &[u8]| {
+        writer
+            .start_file(name, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(data).unwrap();
+    };
+    add("saves/World/level.dat", b"");
+    add(
+        "saves/World/region/r.0.0.mca",
+        &uniform_region("minecraft:stone"),
+    );
+    add(
+        "saves/World/region/r.-1.0.mca",
+        &uniform_region("minecraft:stone"),
+    );
+    add("saves/World/region/r.1.0.mca", b"");
+    add(
+        "saves/World/DIM-1/region/r.0.0.mca",
+        &uniform_region("minecraft:netherrack"),
+    );
+    add("saves/World/backup/other/level.dat", b"");
+    writer.finish().unwrap();
+
+    let error = ZipWorld::open(&path, None).err().unwrap();
+    assert!(error
+        .to_string()
+        .ends_with("'saves/World', 'saves/World/backup/other'"));
+    assert!(ZipWorld::open(&path, Some("saves")).is_err());
+    let world = ZipWorld::open(&path, Some("saves/World/")).unwrap();
+    assert_eq!(world.root(), "saves/World/");
+    let overworld = world.region_folder(Path::new("region"));
+    let mut coords = overworld.list().unwrap();
+    coords.sort_unstable();
+    assert_eq!(coords, [(RCoord(-1), RCoord(0)), (RCoord(0), RCoord(0))]);
+    assert!(!world.region_folder(Path::new("DIM1/region")).exists());
+
+    let results = scan_archive(
+        &world,
+        &[
+            ("minecraft:overworld", PathBuf::from("region")),
+            ("minecraft:the_nether", PathBuf::from("DIM-1/region")),
+        ],
+        None,
+        &ScanOptions::default(),
+        &ScanHooks::default(),
+    );
+    let [(overworld, _), (nether, _)] = &results.frequencies[..] else {
+        panic!("expected both dimensions to be scanned");
+    };
+    assert_eq!(overworld.chunks_counted, 2);
+    assert_eq!(overworld.frequencies["minecraft:stone"][&0], 1.);
+    assert_eq!(nether.frequencies["minecraft:netherrack"][&0], 1.);
+}
@@ -0,0 +1,87 @@
+//! Exporting the results as an Apache Arrow IPC file, for loading them into
+//! dataframe libraries like pandas or polars.
+
+use std::sync::Arc;
+
+use arrow_array::{Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+
+use crate::{BlockFrequencies, RegionVersion};
+
+fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new("dim", DataType::Utf8, false),
+        Field::new("block", DataType::Utf8, false),
+        Field::new("y", DataType::Int64, false),
+        Field::new("freq", DataType::Float64, false),
+    ])
+}
+
+/// Renders the results as an Arrow IPC file, with a `dim, block, y, freq` row
+/// per level of each block. Like in
+/// the tall CSV, the levels between the lowest and highest one of a block are
+/// all there, with a frequency of 0 where it wasn't found. Every dimension is a
+/// record batch of its own.
+pub fn generate_arrow(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+) -> Result<Vec<u8>, ArrowError> {
+    let schema = Arc::new(schema());
+    let mut writer = FileWriter::try_new(vec![], &schema)?;
+    for (freq_data, _version) in frequency_data {
+        let (mut blocks, mut levels, mut freqs) = (vec![], vec![], vec![]);
+        for (name, block_freqs) in freq_data.sorted_blocks() {
+            let (Some(&min_y), Some(&max_y)) = (block_freqs.keys().min(), block_freqs.keys().max())
+            else {
+                continue;
+            };
+            for y in min_y..=max_y {
+                blocks.push(name.as_str());
+                levels.push(y as i64);
+                freqs.push(*block_freqs.get(&y).unwrap_or(&0.));
+            }
+        }
+        let dims = vec![freq_data.dimension.as_str(); blocks.len()];
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(dims)),
+                Arc::new(StringArray::from(blocks)),
+                Arc::new(Int64Array::from(levels)),
+                Arc::new(Float64Array::from(freqs)),
+            ],
+        )?;
+        writer.write(&batch)?;
+    }
+    writer.into_inner()
+}
+
+#[test]
+fn test_generate_arrow() {
+    use std::collections::HashMap;
+
+    use arrow_array::Array;
+    use arrow_ipc::reader::FileReader;
+
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.frequencies.insert(
+        "minecraft:diamond_ore".to_owned(),
+        HashMap::from([(-59, 0.25), (-57, 0.125)]),
+    );
+    let data = generate_arrow(&[(freqs, RegionVersion::AtLeast118)]).unwrap();
+
+    let reader = FileReader::try_new(std::io::Cursor::new(data), None).unwrap();
+    assert_eq!(*reader.schema(), schema());
+    let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+    assert_eq!(batches.len(), 1);
+    let batch = &batches[0];
+    assert_eq!(batch.num_rows(), 3);
+    let column = |i: usize| batch.column(i).clone();
+    let levels = column(2);
+    let levels = levels.as_any().downcast_ref::<Int64Array>().unwrap();
+    assert_eq!(levels.values(), &[-59, -58, -57]);
+    let freqs = column(3);
+    let freqs = freqs.as_any().downcast_ref::<Float64Array>().unwrap();
+    // The level without any is there as 0, like in the tall CSV.
+    assert_eq!(freqs.values(), &[0.25, 0., 0.125]);
+}
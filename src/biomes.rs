@@ -0,0 +1,258 @@
+//! Biome coverage, by the number of columns each biome is found in.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io::{Read, Seek},
+};
+
+use fastanvil::{biome::Biome, Chunk, JavaChunk, Region};
+use fastnbt::LongArray;
+
+use itertools::iproduct;
+use serde::Deserialize;
+
+use crate::{catch_chunk_panic, for_each_chunk, ProtoOption, ScanOptions};
+
+/// Biomes are stored in cells of 4x4x4 blocks since 1.15 (and in 3D sections
+/// since 1.18), so sampling one block per cell sees every biome.
+const BIOME_CELL: usize = 4;
+
+/// The number of columns each biome is found in.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BiomeCounts {
+    /// Columns by biome ID. A column is counted for every biome found at any
+    /// height in it, so cave biomes are counted along with the surface ones.
+    pub columns: BTreeMap<String, u64>,
+    pub columns_counted: u64,
+    /// The chunks whose biome data couldn't be read.
+    pub chunks_failed: u64,
+}
+
+impl BiomeCounts {
+    pub fn merge(&mut self, other: BiomeCounts) {
+        for (biome, count) in other.columns {
+            *self.columns.entry(biome).or_default() += count;
+        }
+        self.columns_counted += other.columns_counted;
+        self.chunks_failed += other.chunks_failed;
+    }
+}
+
+/// The ID of a biome known to fastanvil, like `minecraft:dark_forest`. Only
+/// needed for chunks from before 1.18, which store biomes by number; biomes
+/// unknown to fastanvil, such as modded ones, are all called `unknown` there.
+pub fn biome_id(biome: Biome) -> String {
+    if biome == Biome::Unknown {
+        return "unknown".to_owned();
+    }
+    let mut id = "minecraft:".to_owned();
+    for (i, c) in format!("{biome:?}").chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            id.push('_');
+        }
+        id.push(c.to_ascii_lowercase());
+    }
+    id
+}
+
+/// The biomes of a chunk from 1.18 on, read straight from its NBT, as
+/// fastanvil only knows the vanilla ones by name.
+#[derive(Deserialize)]
+struct BiomeSectionsNbt {
+    #[serde(default)]
+    sections: Vec<BiomeSectionNbt>,
+}
+
+#[derive(Deserialize)]
+struct BiomeSectionNbt {
+    biomes: Option<BiomesNbt>,
+}
+
+#[derive(Deserialize)]
+struct BiomesNbt {
+    palette: Vec<String>,
+    data: Option<LongArray>,
+}
+
+impl BiomesNbt {
+    /// The biome of the 4x4x4 cell at section-relative cell coordinates, which
+    /// are packed like block states, but with no minimum number of bits.
+    fn at(&self, x: usize, y: usize, z: usize) -> Option<&str> {
+        let Some(data) = &self.data else {
+            return self.palette.first().map(String::as_str);
+        };
+        let bits = usize::max(
+            1,
+            (usize::BITS - (self.palette.len().max(1) - 1).leading_zeros()) as usize,
+        );
+        let per_long = 64 / bits;
+        let index = (y * 4 + z) * 4 + x;
+        let long = *data.get(index / per_long)? as u64;
+        let value = (long >> ((index % per_long) * bits)) & ((1 << bits) - 1);
+        self.palette.get(value as usize).map(String::as_str)
+    }
+}
+
+/// The biomes found in each 4x4 cell of columns of a chunk, sampled once per
+/// 4x4x4 cell of every section. `None` if the biomes couldn't be read.
+fn chunk_biomes(chunk: &JavaChunk, data: &[u8]) -> Option<Vec<Vec<String>>> {
+    let mut cells: Vec<Vec<String>> = vec![vec![]; 16];
+    let mut add = |x: usize, z: usize, biome: &str| {
+        let cell = &mut cells[z / BIOME_CELL * 4 + x / BIOME_CELL];
+        if !cell.iter().any(|known| known == biome) {
+            cell.push(biome.to_owned());
+        }
+    };
+    let positions = || {
+        iproduct!(
+            (0..16).step_by(BIOME_CELL),
+            (0..16).step_by(BIOME_CELL),
+            (0..16).step_by(BIOME_CELL)
+        )
+    };
+    match chunk {
+        // Since 1.18, every section has its own palette of biome IDs. Sections
+        // saved without biomes have none.
+        JavaChunk::Post18(_) => {
+            let nbt: BiomeSectionsNbt = fastnbt::from_bytes(data).ok()?;
+            for biomes in nbt
+                .sections
+                .iter()
+                .filter_map(|section| section.biomes.as_ref())
+            {
+                for (cell_y, cell_z, cell_x) in iproduct!(0..4, 0..4, 0..4) {
+                    if let Some(biome) = biomes.at(cell_x, cell_y, cell_z) {
+                        add(cell_x * BIOME_CELL, cell_z * BIOME_CELL, biome);
+                    }
+                }
+            }
+        }
+        // Reading corrupt biome data can panic inside fastanvil.
+        _ => catch_chunk_panic(|| {
+            for (y, (sec_y, z, x)) in iproduct!(chunk.y_range().step_by(16), positions()) {
+                if let Some(biome) = chunk.biome(x, y + sec_y as isize, z) {
+                    add(x, z, &biome_id(biome));
+                }
+            }
+        })?,
+    }
+    Some(cells)
+}
+
+/// Counts the columns of every biome in the chunks of a region that pass the
+/// protochunk filter. Worlds from before 1.15 store biomes per column, which
+/// are sampled at the same 4x4 resolution as the later ones.
+pub fn count_biomes<R: Read + Seek>(region: &mut Region<R>, proto: ProtoOption) -> BiomeCounts {
+    let mut counts = BiomeCounts::default();
+    let options = ScanOptions {
+        proto,
+        ..Default::default()
+    };
+    for_each_chunk(region, &options, |chunk, data, _| {
+        let Some(cells) = chunk_biomes(chunk, &data.data) else {
+            counts.chunks_failed += 1;
+            return;
+        };
+        for biome in cells.into_iter().flatten() {
+            *counts.columns.entry(biome).or_default() += (BIOME_CELL * BIOME_CELL) as u64;
+        }
+        counts.columns_counted += 16 * 16;
+    });
+    counts
+}
+
+/// Generates a CSV with the share of the columns of each dimension every biome
+/// is found in. As a column can hold several biomes, the fractions of a
+/// dimension can add up to more than 1.
+pub fn generate_biomes_csv(counts: &[(String, BiomeCounts)]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,biome,column_count,fraction\n").unwrap();
+    for (dim, counts) in counts {
+        for (biome, &columns) in &counts.columns {
+            writeln!(
+                res,
+                "{},{},{},{}",
+                dim,
+                biome,
+                columns,
+                columns as f64 / counts.columns_counted as f64
+            )
+            .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+#[test]
+fn test_count_biomes() {
+    use crate::fixtures::{region_with, ChunkNbt, SectionNbt};
+
+    assert_eq!(
+        biome_id(Biome::WindsweptGravellyHills),
+        "minecraft:windswept_gravelly_hills"
+    );
+    assert_eq!(biome_id(Biome::Unknown), "unknown");
+
+    // A plains surface over lush caves, and a chunk all in a forest.
+    let caves = ChunkNbt::full(vec![
+        SectionNbt::uniform(-1, "minecraft:stone").in_biome("minecraft:lush_caves"),
+        SectionNbt::uniform(0, "minecraft:air").in_biome("minecraft:plains"),
+    ]);
+    let forest = ChunkNbt::full(vec![
+        SectionNbt::uniform(0, "minecraft:air").in_biome("minecraft:forest")
+    ]);
+    let mut region = region_with(&[((0, 0), caves.to_bytes()), ((1, 0), forest.to_bytes())]);
+    let counts = count_biomes(&mut region, ProtoOption::Skip);
+    assert_eq!(counts.columns_counted, 512);
+    assert_eq!(counts.chunks_failed, 0);
+    assert_eq!(
+        counts.columns,
+        BTreeMap::from([
+            ("minecraft:forest".to_owned(), 256),
+            ("minecraft:lush_caves".to_owned(), 256),
+            ("minecraft:plains".to_owned(), 256),
+        ])
+    );
+
+    let csv = generate_biomes_csv(&[("minecraft:overworld".to_owned(), counts)]);
+    assert_eq!(
+        csv,
+        "dim,biome,column_count,fraction\n\
+         minecraft:overworld,minecraft:forest,256,0.5\n\
+         minecraft:overworld,minecraft:lush_caves,256,0.5\n\
+         minecraft:overworld,minecraft:plains,256,0.5\n"
+    );
+}
+
+#[test]
+fn test_count_modded_biomes() {
+    use crate::fixtures::{region_with, ChunkNbt, SectionNbt};
+
+    // A modded biome unknown to fastanvil, with a vanilla one in its western
+    // half, and in the bottom cells of the chunk only.
+    let section = SectionNbt::uniform(0, "minecraft:air").with_biomes(
+        &[
+            "minecraft:plains",
+            "biomesoplenty:lavender_field",
+            "minecraft:river",
+        ],
+        |x, y, _| match (x, y) {
+            (0 | 1, 0) => 0,
+            (_, 0) => 1,
+            _ => 2,
+        },
+    );
+    let chunk = ChunkNbt::full(vec![section]);
+    let mut region = region_with(&[((0, 0), chunk.to_bytes())]);
+    let counts = count_biomes(&mut region, ProtoOption::Skip);
+    assert_eq!(counts.chunks_failed, 0);
+    assert_eq!(
+        counts.columns,
+        BTreeMap::from([
+            ("biomesoplenty:lavender_field".to_owned(), 128),
+            ("minecraft:plains".to_owned(), 128),
+            ("minecraft:river".to_owned(), 256),
+        ])
+    );
+}
@@ -0,0 +1,206 @@
+//! Block entity counts, such as of chests and spawners, which are stored in the
+//! chunks next to the blocks.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io::{Read, Seek},
+};
+
+use fastanvil::Region;
+use serde::Deserialize;
+
+use crate::{is_full_status, readable_chunks, ProtoOption};
+
+#[derive(Deserialize)]
+struct BlockEntityChunk {
+    /// Since 1.18.
+    #[serde(default)]
+    block_entities: Vec<BlockEntity>,
+    /// Before 1.18, in the Level compound.
+    #[serde(rename = "TileEntities", default)]
+    tile_entities: Vec<BlockEntity>,
+    #[serde(rename = "Status")]
+    status: Option<String>,
+    /// Before 1.18, everything was in a Level compound.
+    #[serde(rename = "Level")]
+    level: Option<Box<BlockEntityChunk>>,
+}
+
+#[derive(Deserialize)]
+struct BlockEntity {
+    id: Option<String>,
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+/// A block entity found with [`count_block_entities`], with its position in
+/// block coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocatedBlockEntity {
+    pub id: String,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// Reads the block entities of a chunk, along with whether the chunk is fully
+/// generated. Only their IDs and positions are deserialized.
+fn chunk_block_entities(data: &[u8]) -> Option<(Vec<BlockEntity>, bool)> {
+    let chunk: BlockEntityChunk = fastnbt::from_bytes(data).ok()?;
+    let chunk = match chunk.level {
+        Some(level) => *level,
+        None => chunk,
+    };
+    // Pre-1.13 chunks have no status, and are always complete.
+    let full = chunk.status.as_deref().is_none_or(is_full_status);
+    let mut entities = chunk.block_entities;
+    entities.extend(chunk.tile_entities);
+    Some((entities, full))
+}
+
+/// The number of block entities of each kind in a dimension.
+pub struct BlockEntityCounts {
+    pub dimension: String,
+    pub counts: BTreeMap<String, u64>,
+    /// The block entities whose position was asked for.
+    pub located: Vec<LocatedBlockEntity>,
+    pub chunks_counted: usize,
+}
+
+impl BlockEntityCounts {
+    pub fn empty(dimension: String) -> Self {
+        Self {
+            dimension,
+            counts: BTreeMap::new(),
+            located: vec![],
+            chunks_counted: 0,
+        }
+    }
+
+    pub fn merge(&mut self, other: BlockEntityCounts) {
+        for (id, count) in other.counts {
+            *self.counts.entry(id).or_default() += count;
+        }
+        self.located.extend(other.located);
+        self.chunks_counted += other.chunks_counted;
+    }
+}
+
+/// Counts the block entities in the chunks of a region that pass the
+/// protochunk filter. The positions of the ones `locate` returns true for are
+/// recorded as well.
+pub fn count_block_entities<R: Read + Seek>(
+    region: &mut Region<R>,
+    dimension: &str,
+    proto: ProtoOption,
+    locate: impl Fn(&str) -> bool,
+) -> BlockEntityCounts {
+    let mut counts = BlockEntityCounts::empty(dimension.to_owned());
+    for data in readable_chunks(region) {
+        let Some((entities, full)) = chunk_block_entities(&data.data) else {
+            continue;
+        };
+        if !proto.accepts(full) {
+            continue;
+        }
+        for entity in entities {
+            let Some(id) = entity.id else {
+                continue;
+            };
+            if locate(&id) {
+                counts.located.push(LocatedBlockEntity {
+                    id: id.clone(),
+                    x: entity.x,
+                    y: entity.y,
+                    z: entity.z,
+                });
+            }
+            *counts.counts.entry(id).or_default() += 1;
+        }
+        counts.chunks_counted += 1;
+    }
+    counts
+}
+
+/// Generates a CSV with a row per dimension and kind of block entity, most
+/// common first.
+pub fn generate_block_entities_csv(counts: &[BlockEntityCounts]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block_entity_id,count\n").unwrap();
+    for dim_counts in counts {
+        let mut entities: Vec<_> = dim_counts.counts.iter().collect();
+        entities.sort_by(|(id1, count1), (id2, count2)| count2.cmp(count1).then(id1.cmp(id2)));
+        for (id, count) in entities {
+            writeln!(res, "{},{},{}", dim_counts.dimension, id, count)
+                .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+/// Generates a CSV with the position of every located block entity, sorted by
+/// kind and then position.
+pub fn generate_block_entity_locations_csv(counts: &[BlockEntityCounts]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block_entity_id,x,y,z\n").unwrap();
+    for dim_counts in counts {
+        let mut located: Vec<_> = dim_counts.located.iter().collect();
+        located.sort_by_key(|e| (&e.id, e.x, e.z, e.y));
+        for e in located {
+            writeln!(
+                res,
+                "{},{},{},{},{}",
+                dim_counts.dimension, e.id, e.x, e.y, e.z
+            )
+            .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+#[test]
+fn test_count_block_entities() {
+    use crate::fixtures::*;
+    let entity = |id: &str, x, y, z| BlockEntityNbt {
+        id: id.to_owned(),
+        x,
+        y,
+        z,
+    };
+    let chunk = ChunkNbt {
+        block_entities: vec![
+            entity("minecraft:chest", 1, 64, 2),
+            entity("minecraft:mob_spawner", 3, -20, 4),
+            entity("minecraft:chest", 5, 64, 6),
+        ],
+        ..ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")])
+    };
+    let mut proto = ChunkNbt {
+        block_entities: vec![entity("minecraft:chest", 20, 64, 0)],
+        ..ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")])
+    };
+    proto.status = "minecraft:features".to_owned();
+    let mut region = region_with(&[((0, 0), chunk.to_bytes()), ((1, 0), proto.to_bytes())]);
+    let counts = count_block_entities(&mut region, "test:dim", ProtoOption::Skip, |id| {
+        id == "minecraft:mob_spawner"
+    });
+    assert_eq!(counts.chunks_counted, 1);
+    assert_eq!(
+        counts.counts,
+        BTreeMap::from([
+            ("minecraft:chest".to_owned(), 2),
+            ("minecraft:mob_spawner".to_owned(), 1)
+        ])
+    );
+    let counts = [counts];
+    assert_eq!(
+        generate_block_entities_csv(&counts).lines().nth(1),
+        Some("test:dim,minecraft:chest,2")
+    );
+    assert_eq!(
+        generate_block_entity_locations_csv(&counts),
+        "dim,block_entity_id,x,y,z\ntest:dim,minecraft:mob_spawner,3,-20,4\n"
+    );
+}
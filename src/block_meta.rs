@@ -0,0 +1,200 @@
+//! How hard blocks are to mine and what with, to add to the per-block exports
+//! for planning where to mine.
+
+use std::collections::HashMap;
+
+use crate::normalize_block_name;
+
+/// The columns added to a CSV row by [`BlockMetaTable::csv_columns`].
+pub const BLOCK_META_HEADER: &str = "hardness,tool,tool_level";
+
+/// How a block is mined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockMeta {
+    /// The hardness, as in the game; -1 for unbreakable blocks like bedrock.
+    pub hardness: f64,
+    /// The kind of tool that mines the block the fastest, like `pickaxe`.
+    pub tool: Option<String>,
+    /// The lowest tier of the tool that makes the block drop anything, like
+    /// `iron`, if a tool is needed at all.
+    pub tool_level: Option<String>,
+}
+
+/// The vanilla blocks found in notable amounts in generated worlds, as (ID,
+/// hardness, tool, tool level).
+const VANILLA_BLOCKS: &[(&str, f64, &str, &str)] = &[
+    ("stone", 1.5, "pickaxe", "wood"),
+    ("deepslate", 3., "pickaxe", "wood"),
+    ("cobblestone", 2., "pickaxe", "wood"),
+    ("cobbled_deepslate", 3.5, "pickaxe", "wood"),
+    ("granite", 1.5, "pickaxe", "wood"),
+    ("diorite", 1.5, "pickaxe", "wood"),
+    ("andesite", 1.5, "pickaxe", "wood"),
+    ("tuff", 1.5, "pickaxe", "wood"),
+    ("calcite", 0.75, "pickaxe", "wood"),
+    ("dripstone_block", 1.5, "pickaxe", "wood"),
+    ("smooth_basalt", 1.25, "pickaxe", "wood"),
+    ("sandstone", 0.8, "pickaxe", "wood"),
+    ("red_sandstone", 0.8, "pickaxe", "wood"),
+    ("terracotta", 1.25, "pickaxe", "wood"),
+    ("obsidian", 50., "pickaxe", "diamond"),
+    ("bedrock", -1., "", ""),
+    ("dirt", 0.5, "shovel", ""),
+    ("grass_block", 0.6, "shovel", ""),
+    ("coarse_dirt", 0.5, "shovel", ""),
+    ("podzol", 0.5, "shovel", ""),
+    ("mycelium", 0.6, "shovel", ""),
+    ("gravel", 0.6, "shovel", ""),
+    ("sand", 0.5, "shovel", ""),
+    ("red_sand", 0.5, "shovel", ""),
+    ("clay", 0.6, "shovel", ""),
+    ("snow_block", 0.2, "shovel", "wood"),
+    ("ice", 0.5, "pickaxe", ""),
+    ("packed_ice", 0.5, "pickaxe", ""),
+    ("coal_ore", 3., "pickaxe", "wood"),
+    ("deepslate_coal_ore", 4.5, "pickaxe", "wood"),
+    ("iron_ore", 3., "pickaxe", "stone"),
+    ("deepslate_iron_ore", 4.5, "pickaxe", "stone"),
+    ("copper_ore", 3., "pickaxe", "stone"),
+    ("deepslate_copper_ore", 4.5, "pickaxe", "stone"),
+    ("lapis_ore", 3., "pickaxe", "stone"),
+    ("deepslate_lapis_ore", 4.5, "pickaxe", "stone"),
+    ("gold_ore", 3., "pickaxe", "iron"),
+    ("deepslate_gold_ore", 4.5, "pickaxe", "iron"),
+    ("redstone_ore", 3., "pickaxe", "iron"),
+    ("deepslate_redstone_ore", 4.5, "pickaxe", "iron"),
+    ("diamond_ore", 3., "pickaxe", "iron"),
+    ("deepslate_diamond_ore", 4.5, "pickaxe", "iron"),
+    ("emerald_ore", 3., "pickaxe", "iron"),
+    ("deepslate_emerald_ore", 4.5, "pickaxe", "iron"),
+    ("raw_iron_block", 5., "pickaxe", "stone"),
+    ("raw_copper_block", 5., "pickaxe", "stone"),
+    ("raw_gold_block", 5., "pickaxe", "iron"),
+    ("amethyst_block", 1.5, "pickaxe", "wood"),
+    ("budding_amethyst", 1.5, "pickaxe", ""),
+    ("netherrack", 0.4, "pickaxe", "wood"),
+    ("nether_gold_ore", 3., "pickaxe", "wood"),
+    ("nether_quartz_ore", 3., "pickaxe", "wood"),
+    ("ancient_debris", 30., "pickaxe", "diamond"),
+    ("basalt", 1.25, "pickaxe", "wood"),
+    ("blackstone", 1.5, "pickaxe", "wood"),
+    ("gilded_blackstone", 1.5, "pickaxe", "wood"),
+    ("magma_block", 0.5, "pickaxe", "wood"),
+    ("soul_sand", 0.5, "shovel", ""),
+    ("soul_soil", 0.5, "shovel", ""),
+    ("glowstone", 0.3, "", ""),
+    ("end_stone", 3., "pickaxe", "wood"),
+    ("oak_log", 2., "axe", ""),
+    ("spruce_log", 2., "axe", ""),
+    ("birch_log", 2., "axe", ""),
+    ("jungle_log", 2., "axe", ""),
+    ("acacia_log", 2., "axe", ""),
+    ("dark_oak_log", 2., "axe", ""),
+    ("mangrove_log", 2., "axe", ""),
+    ("cherry_log", 2., "axe", ""),
+];
+
+/// Hardness and tool metadata by block ID.
+#[derive(Debug, Clone, Default)]
+pub struct BlockMetaTable {
+    blocks: HashMap<String, BlockMeta>,
+}
+
+impl BlockMetaTable {
+    /// The built-in table of common vanilla blocks.
+    pub fn vanilla() -> Self {
+        let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_owned());
+        let blocks = VANILLA_BLOCKS
+            .iter()
+            .map(|&(id, hardness, tool, tool_level)| {
+                (
+                    format!("minecraft:{id}"),
+                    BlockMeta {
+                        hardness,
+                        tool: non_empty(tool),
+                        tool_level: non_empty(tool_level),
+                    },
+                )
+            })
+            .collect();
+        Self { blocks }
+    }
+
+    /// Adds the blocks of a CSV with a `block,hardness,tool,tool_level` header
+    /// (the last two may be left empty) to the table, replacing the entries of
+    /// blocks already in it.
+    pub fn extend_from_csv(&mut self, csv: &str) -> Result<(), String> {
+        let mut lines = csv
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+        match lines.next() {
+            Some((_, header)) if header.trim() == "block,hardness,tool,tool_level" => {}
+            _ => return Err("expected a 'block,hardness,tool,tool_level' header".to_owned()),
+        }
+        for (i, line) in lines {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let &[block, hardness, tool, tool_level] = fields.as_slice() else {
+                return Err(format!("line {} doesn't have 4 fields: '{line}'", i + 1));
+            };
+            let hardness = hardness
+                .parse()
+                .map_err(|e| format!("invalid hardness on line {}: {e}", i + 1))?;
+            let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_owned());
+            self.blocks.insert(
+                normalize_block_name(block).into_owned(),
+                BlockMeta {
+                    hardness,
+                    tool: non_empty(tool),
+                    tool_level: non_empty(tool_level),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, block: &str) -> Option<&BlockMeta> {
+        self.blocks.get(block)
+    }
+
+    /// The values of the [`BLOCK_META_HEADER`] columns for a block, with a
+    /// leading comma, or just the commas for blocks missing from the table.
+    pub fn csv_columns(&self, block: &str) -> String {
+        match self.get(block) {
+            Some(meta) => format!(
+                ",{},{},{}",
+                meta.hardness,
+                meta.tool.as_deref().unwrap_or(""),
+                meta.tool_level.as_deref().unwrap_or("")
+            ),
+            None => ",,,".to_owned(),
+        }
+    }
+}
+
+#[test]
+fn test_block_meta_table() {
+    let mut table = BlockMetaTable::vanilla();
+    assert_eq!(
+        table.csv_columns("minecraft:diamond_ore"),
+        ",3,pickaxe,iron"
+    );
+    assert_eq!(table.csv_columns("minecraft:dirt"), ",0.5,shovel,");
+    assert_eq!(table.csv_columns("mekanism:osmium_ore"), ",,,");
+
+    table
+        .extend_from_csv(
+            "# Mekanism\nblock,hardness,tool,tool_level\nmekanism:osmium_ore,3,pickaxe,stone\n\
+             minecraft:DIRT,0.6,,\n",
+        )
+        .unwrap();
+    assert_eq!(table.csv_columns("mekanism:osmium_ore"), ",3,pickaxe,stone");
+    assert_eq!(table.csv_columns("minecraft:dirt"), ",0.6,,");
+
+    assert!(table
+        .extend_from_csv("mekanism:osmium_ore,3,pickaxe,stone")
+        .is_err());
+    assert!(table
+        .extend_from_csv("block,hardness,tool,tool_level\nmekanism:osmium_ore,hard,,")
+        .is_err());
+}
@@ -0,0 +1,217 @@
+//! Charts of the scan results, drawn with plotters: frequency by level, and
+//! heatmaps of where a block is found.
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use plotters::prelude::*;
+
+use crate::{is_air, sanitize_file_name, BlockFrequencies, BlockHeatmap, RegionVersion};
+
+/// Renders a PNG per dimension into `folder`, with a line chart of frequency
+/// by level for each of the `top` most common blocks (air excluded). Returns
+/// the paths of the written files.
+pub fn generate_charts(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+    folder: &Path,
+    top: usize,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut written = vec![];
+    for (freq_data, _version) in frequency_data {
+        let mut blocks: Vec<(&String, f64)> = freq_data
+            .frequencies
+            .iter()
+            .filter(|(name, freqs)| !freqs.is_empty() && !is_air(name))
+            .map(|(name, freqs)| (name, freqs.values().sum()))
+            .collect();
+        blocks.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        blocks.truncate(top);
+        if blocks.is_empty() {
+            continue;
+        }
+        let freqs_of = |name: &String| &freq_data.frequencies[name];
+        let min_y = blocks
+            .iter()
+            .flat_map(|(name, _)| freqs_of(name).keys())
+            .min()
+            .copied()
+            .unwrap();
+        let max_y = blocks
+            .iter()
+            .flat_map(|(name, _)| freqs_of(name).keys())
+            .max()
+            .copied()
+            .unwrap();
+        let max_freq = blocks
+            .iter()
+            .flat_map(|(name, _)| freqs_of(name).values())
+            .copied()
+            .fold(0., f64::max);
+
+        let path = folder.join(format!(
+            "chart-{}.png",
+            sanitize_file_name(&freq_data.dimension)
+        ));
+        let root = BitMapBackend::new(&path, (1280, 800)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(&freq_data.dimension, ("sans-serif", 28))
+            .margin(15)
+            .x_label_area_size(40)
+            .y_label_area_size(80)
+            .build_cartesian_2d(min_y..max_y + 1, 0f64..max_freq * 1.05)?;
+        chart
+            .configure_mesh()
+            .x_desc("y")
+            .y_desc("frequency")
+            .draw()?;
+        for (i, (name, _)) in blocks.iter().enumerate() {
+            let color = Palette99::pick(i).stroke_width(2);
+            let freqs = freqs_of(name);
+            chart
+                .draw_series(LineSeries::new(
+                    (min_y..=max_y).map(|y| (y, *freqs.get(&y).unwrap_or(&0.))),
+                    color,
+                ))?
+                .label(name.as_str())
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+        written.push(path.clone());
+    }
+    Ok(written)
+}
+
+/// Renders an SVG per dimension and block into a subfolder of `folder` for
+/// each dimension, with a line chart of the block's frequency by level like
+/// the ones of JER. Levels without the block are plotted as 0, and blocks
+/// found on no level are skipped. Returns the paths of the written files.
+pub fn generate_block_plots(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+    folder: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut written = vec![];
+    for (freq_data, _version) in frequency_data {
+        let dim_folder = folder.join(sanitize_file_name(&freq_data.dimension));
+        std::fs::create_dir_all(&dim_folder)?;
+        for (name, freqs) in freq_data.sorted_blocks() {
+            let (Some(&min_y), Some(&max_y)) = (freqs.keys().min(), freqs.keys().max()) else {
+                continue;
+            };
+            let max_freq = freqs.values().copied().fold(0., f64::max);
+            let path = dim_folder.join(format!("{}.svg", sanitize_file_name(name)));
+            let root = SVGBackend::new(&path, (800, 500)).into_drawing_area();
+            root.fill(&WHITE)?;
+            let mut chart = ChartBuilder::on(&root)
+                .caption(name, ("sans-serif", 24))
+                .margin(15)
+                .x_label_area_size(40)
+                .y_label_area_size(80)
+                .build_cartesian_2d(min_y..max_y + 1, 0f64..max_freq.max(f64::EPSILON) * 1.05)?;
+            chart
+                .configure_mesh()
+                .x_desc("y")
+                .y_desc("frequency")
+                .draw()?;
+            chart.draw_series(LineSeries::new(
+                (min_y..=max_y).map(|y| (y, *freqs.get(&y).unwrap_or(&0.))),
+                BLUE.stroke_width(2),
+            ))?;
+            root.present()?;
+            written.push(path.clone());
+        }
+    }
+    Ok(written)
+}
+
+/// Renders a heatmap as a PNG at `path`, with a square per chunk: white where
+/// the block wasn't found, and from yellow to dark red up to the chunk with the
+/// most of it. Chunks that weren't counted are grey. Small maps are scaled up
+/// so that the chunks can be told apart, and large ones drawn in cells of
+/// several chunks (see [`BlockHeatmap::binned`]).
+pub fn generate_heatmap_png(heatmap: &BlockHeatmap, path: &Path) -> Result<(), Box<dyn Error>> {
+    // Large heatmaps are drawn with a pixel per cell of several chunks.
+    let heatmap = &heatmap.binned();
+    let Some(((min_x, min_z), (max_x, max_z))) = heatmap.bounds() else {
+        return Err("no chunks were counted".into());
+    };
+    let (width, height) = ((max_x - min_x + 1) as u32, (max_z - min_z + 1) as u32);
+    let scale = (512 / width.max(height)).max(1);
+    let root = BitMapBackend::new(path, (width * scale, height * scale)).into_drawing_area();
+    root.fill(&RGBColor(200, 200, 200))?;
+    let max_count = heatmap.max_count().max(1) as f64;
+    for (&(x, z), &count) in &heatmap.counts {
+        let color = if count == 0 {
+            WHITE
+        } else {
+            // The square root keeps the chunks with a little of the block visible
+            // next to a few with a lot.
+            let heat = (count as f64 / max_count).sqrt();
+            RGBColor(
+                255 - (115. * heat) as u8,
+                230 - (230. * heat) as u8,
+                60 - (60. * heat) as u8,
+            )
+        };
+        let (left, top) = (
+            (x - min_x) as i32 * scale as i32,
+            (z - min_z) as i32 * scale as i32,
+        );
+        root.draw(&Rectangle::new(
+            [(left, top), (left + scale as i32, top + scale as i32)],
+            color.filled(),
+        ))?;
+    }
+    root.present()?;
+    Ok(())
+}
+
+#[test]
+fn test_generate_block_plots() {
+    use std::collections::HashMap;
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.frequencies = HashMap::from([
+        (
+            "minecraft:iron_ore".to_owned(),
+            HashMap::from([(-10, 0.01), (12, 0.02)]),
+        ),
+        ("minecraft:nothing".to_owned(), HashMap::new()),
+    ]);
+    let dir = crate::fixtures::TempDir::new("block_plots");
+    let written = generate_block_plots(&[(freqs, RegionVersion::AtLeast118)], &dir).unwrap();
+    assert_eq!(
+        written,
+        [dir.join("minecraft_overworld")
+            .join("minecraft_iron_ore.svg")]
+    );
+    assert!(std::fs::read_to_string(&written[0])
+        .unwrap()
+        .starts_with("<svg"));
+}
+
+#[test]
+fn test_generate_heatmap_png() {
+    let mut heatmap = BlockHeatmap::empty("test:dim".to_owned(), "minecraft:stone".to_owned());
+    heatmap
+        .counts
+        .extend([((-3, 2), 0), ((0, 2), 10), ((0, 3), 40)]);
+    let dir = crate::fixtures::TempDir::new("heatmap_png");
+    let path = dir.join("heatmap.png");
+    generate_heatmap_png(&heatmap, &path).unwrap();
+    // 4 chunks wide and 2 high, scaled up by 128.
+    let png = std::fs::read(&path).unwrap();
+    assert_eq!(&png[16..24], &[0, 0, 2, 0, 0, 0, 1, 0]);
+
+    // Chunks millions of chunks apart are drawn in cells of several chunks.
+    heatmap.counts.insert((3_000_000, -1_000_000), 5);
+    generate_heatmap_png(&heatmap, &path).unwrap();
+    let png = std::fs::read(&path).unwrap();
+    assert_eq!(&png[16..24], &[0, 0, 4, 0, 0, 0, 1, 86]);
+}
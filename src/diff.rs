@@ -0,0 +1,258 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use serde::Serialize;
+
+use crate::{BlockFrequencies, RegionVersion};
+
+/// A block whose distribution in the scanned world differs from the baseline.
+pub struct BlockAnomaly {
+    pub dimension: String,
+    pub block: String,
+    /// Sum of the absolute per-level differences divided by the sum of both
+    /// distributions. 0 means identical distributions, 1 means that the block
+    /// never occurs on the same level in both.
+    pub deviation: f64,
+    /// `(y, scanned frequency, baseline frequency)` for every level from the
+    /// lowest to the highest one the block was seen on in either world.
+    pub levels: Vec<(isize, f64, f64)>,
+}
+
+/// Compares the scanned frequencies to a known-good baseline (e.g. a vanilla
+/// world) and returns the blocks whose deviation is at least `threshold`,
+/// most deviating first. Only dimensions present in the baseline are compared.
+/// Blocks missing from one side count as fully deviating.
+pub fn find_anomalies(
+    scanned: &[(BlockFrequencies, RegionVersion)],
+    baseline: &[BlockFrequencies],
+    threshold: f64,
+) -> Vec<BlockAnomaly> {
+    let empty = HashMap::new();
+    let mut anomalies = vec![];
+    for (freq_data, _version) in scanned {
+        let Some(base_data) = baseline.iter().find(|b| b.dimension == freq_data.dimension) else {
+            soft_warn!(
+                "The baseline has no data for dimension {}, not comparing it.",
+                freq_data.dimension
+            );
+            continue;
+        };
+        let mut blocks: Vec<&String> = freq_data
+            .frequencies
+            .keys()
+            .chain(base_data.frequencies.keys())
+            .collect();
+        blocks.sort_unstable();
+        blocks.dedup();
+        for block in blocks {
+            let freqs = freq_data.frequencies.get(block).unwrap_or(&empty);
+            let base_freqs = base_data.frequencies.get(block).unwrap_or(&empty);
+            let Some(min_y) = freqs.keys().chain(base_freqs.keys()).min().copied() else {
+                continue;
+            };
+            let max_y = *freqs.keys().chain(base_freqs.keys()).max().unwrap();
+            let levels: Vec<(isize, f64, f64)> = (min_y..=max_y)
+                .map(|y| {
+                    (
+                        y,
+                        *freqs.get(&y).unwrap_or(&0.),
+                        *base_freqs.get(&y).unwrap_or(&0.),
+                    )
+                })
+                .collect();
+            let difference: f64 = levels.iter().map(|(_, a, b)| (a - b).abs()).sum();
+            let total: f64 = levels.iter().map(|(_, a, b)| a + b).sum();
+            if total <= 0. {
+                continue;
+            }
+            let deviation = difference / total;
+            if deviation >= threshold {
+                anomalies.push(BlockAnomaly {
+                    dimension: freq_data.dimension.clone(),
+                    block: block.clone(),
+                    deviation,
+                    levels,
+                });
+            }
+        }
+    }
+    // Ties are broken by block, then dimension, so that the output is
+    // reproducible.
+    anomalies.sort_by(|a, b| {
+        b.deviation
+            .total_cmp(&a.deviation)
+            .then_with(|| a.block.cmp(&b.block))
+            .then_with(|| a.dimension.cmp(&b.dimension))
+    });
+    anomalies
+}
+
+/// Renders anomalies as a CSV with a row per level of each anomalous block.
+pub fn generate_anomalies_csv(anomalies: &[BlockAnomaly]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block,deviation,level,freq,baseline_freq,difference\n")
+        .unwrap();
+    for anomaly in anomalies {
+        for (y, freq, base_freq) in &anomaly.levels {
+            writeln!(
+                res,
+                "{},{},{},{},{},{},{}",
+                anomaly.dimension,
+                anomaly.block,
+                anomaly.deviation,
+                y,
+                freq,
+                base_freq,
+                freq - base_freq
+            )
+            .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+/// How the frequencies of the blocks of a dimension changed from one scan (`a`)
+/// to another (`b`), e.g. of a modpack before and after changing its configs.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BlockFrequencyDiff {
+    pub dimension: String,
+    /// For every block found in either scan, `(y, frequency in a, frequency
+    /// in b)` for every level from the lowest to the highest one it was seen
+    /// on in either.
+    pub blocks: BTreeMap<String, Vec<(isize, f64, f64)>>,
+}
+
+impl BlockFrequencyDiff {
+    /// Whether no frequency changed at all.
+    pub fn is_unchanged(&self) -> bool {
+        self.blocks
+            .values()
+            .flatten()
+            .all(|(_, freq_a, freq_b)| freq_a == freq_b)
+    }
+}
+
+/// Compares the frequencies of two scans of the same dimension, level by level.
+/// Blocks missing from one of them have a frequency of 0 there. The dimension
+/// of the result is the one of `a`.
+pub fn diff_frequencies(a: &BlockFrequencies, b: &BlockFrequencies) -> BlockFrequencyDiff {
+    let empty = HashMap::new();
+    let mut blocks = BTreeMap::new();
+    for block in a.frequencies.keys().chain(b.frequencies.keys()) {
+        if blocks.contains_key(block) {
+            continue;
+        }
+        let freqs_a = a.frequencies.get(block).unwrap_or(&empty);
+        let freqs_b = b.frequencies.get(block).unwrap_or(&empty);
+        let Some(min_y) = freqs_a.keys().chain(freqs_b.keys()).min().copied() else {
+            continue;
+        };
+        let max_y = *freqs_a.keys().chain(freqs_b.keys()).max().unwrap();
+        let levels = (min_y..=max_y)
+            .map(|y| {
+                (
+                    y,
+                    *freqs_a.get(&y).unwrap_or(&0.),
+                    *freqs_b.get(&y).unwrap_or(&0.),
+                )
+            })
+            .collect();
+        blocks.insert(block.clone(), levels);
+    }
+    BlockFrequencyDiff {
+        dimension: a.dimension.clone(),
+        blocks,
+    }
+}
+
+/// Renders diffs as a CSV with a row per level of each block, where `delta` is
+/// the frequency in the second scan minus the one in the first.
+pub fn generate_diff_csv(diffs: &[BlockFrequencyDiff]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block,y,freq_a,freq_b,delta\n").unwrap();
+    for diff in diffs {
+        for (block, levels) in &diff.blocks {
+            for (y, freq_a, freq_b) in levels {
+                writeln!(
+                    res,
+                    "{},{},{},{},{},{}",
+                    diff.dimension,
+                    block,
+                    y,
+                    freq_a,
+                    freq_b,
+                    freq_b - freq_a
+                )
+                .expect("Error when assembling CSV");
+            }
+        }
+    }
+    res
+}
+
+#[test]
+fn test_diff_frequencies() {
+    let mut a = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    a.frequencies = HashMap::from([
+        ("minecraft:stone".to_owned(), HashMap::from([(0, 0.5)])),
+        ("minecraft:iron_ore".to_owned(), HashMap::from([(1, 0.25)])),
+    ]);
+    let mut b = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    b.frequencies = HashMap::from([
+        ("minecraft:stone".to_owned(), HashMap::from([(0, 0.5)])),
+        ("minecraft:iron_ore".to_owned(), HashMap::from([(3, 0.5)])),
+    ]);
+    assert!(diff_frequencies(&a, &a).is_unchanged());
+    let diff = diff_frequencies(&a, &b);
+    assert!(!diff.is_unchanged());
+    assert_eq!(
+        diff.blocks["minecraft:iron_ore"],
+        [(1, 0.25, 0.), (2, 0., 0.), (3, 0., 0.5)]
+    );
+    assert_eq!(diff.blocks["minecraft:stone"], [(0, 0.5, 0.5)]);
+    let csv = generate_diff_csv(&[diff]);
+    assert_eq!(
+        csv.lines().nth(1),
+        Some("minecraft:overworld,minecraft:iron_ore,1,0.25,0,-0.25")
+    );
+}
+
+#[test]
+fn test_find_anomalies() {
+    let mut scanned = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    scanned.frequencies = HashMap::from([
+        ("minecraft:stone".to_owned(), HashMap::from([(0, 0.5)])),
+        ("minecraft:iron_ore".to_owned(), HashMap::from([(0, 0.25)])),
+        ("minecraft:gold_ore".to_owned(), HashMap::from([(0, 0.375)])),
+    ]);
+    let mut baseline = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    baseline.frequencies = HashMap::from([
+        ("minecraft:stone".to_owned(), HashMap::from([(0, 0.5)])),
+        ("minecraft:iron_ore".to_owned(), HashMap::from([(1, 0.25)])),
+        ("minecraft:gold_ore".to_owned(), HashMap::from([(0, 0.125)])),
+    ]);
+    let anomalies = find_anomalies(&[(scanned, RegionVersion::AtLeast118)], &[baseline], 0.5);
+    // Stone is found just as in the baseline, so it isn't an anomaly.
+    let found: Vec<(&str, f64)> = anomalies
+        .iter()
+        .map(|anomaly| (anomaly.block.as_str(), anomaly.deviation))
+        .collect();
+    assert_eq!(
+        found,
+        [("minecraft:iron_ore", 1.), ("minecraft:gold_ore", 0.5)]
+    );
+    assert_eq!(anomalies[0].levels, [(0, 0.25, 0.), (1, 0., 0.25)]);
+
+    let csv = generate_anomalies_csv(&anomalies);
+    assert_eq!(
+        csv.lines().collect::<Vec<_>>(),
+        [
+            "dim,block,deviation,level,freq,baseline_freq,difference",
+            "minecraft:overworld,minecraft:iron_ore,1,0,0.25,0,0.25",
+            "minecraft:overworld,minecraft:iron_ore,1,1,0,0.25,-0.25",
+            "minecraft:overworld,minecraft:gold_ore,0.5,0,0.375,0.125,0.25",
+        ]
+    );
+}
@@ -0,0 +1,177 @@
+//! Entity counts, from the entity region files split off from the chunks in
+//! 1.17.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io::{Read, Seek},
+};
+
+use fastanvil::Region;
+use serde::Deserialize;
+
+use crate::readable_chunks;
+
+#[derive(Deserialize)]
+struct EntityChunk {
+    #[serde(rename = "Entities", default)]
+    entities: Vec<Entity>,
+}
+
+#[derive(Deserialize)]
+struct Entity {
+    id: Option<String>,
+    /// Entities riding this one, which aren't listed separately.
+    #[serde(rename = "Passengers", default)]
+    passengers: Vec<Entity>,
+}
+
+fn collect_ids(entities: Vec<Entity>, ids: &mut Vec<String>) {
+    for entity in entities {
+        ids.extend(entity.id);
+        collect_ids(entity.passengers, ids);
+    }
+}
+
+/// Reads the IDs of all entities in a chunk of an entity region file,
+/// including passengers. Only the IDs are deserialized.
+pub fn chunk_entity_ids(data: &[u8]) -> Option<Vec<String>> {
+    let chunk: EntityChunk = fastnbt::from_bytes(data).ok()?;
+    let mut ids = vec![];
+    collect_ids(chunk.entities, &mut ids);
+    Some(ids)
+}
+
+/// The number of entities of each kind in a dimension.
+#[derive(Clone)]
+pub struct EntityCounts {
+    pub dimension: String,
+    pub counts: BTreeMap<String, u64>,
+    pub chunks_counted: usize,
+}
+
+impl EntityCounts {
+    pub fn empty(dimension: String) -> Self {
+        Self {
+            dimension,
+            counts: BTreeMap::new(),
+            chunks_counted: 0,
+        }
+    }
+
+    pub fn merge(&mut self, other: EntityCounts) {
+        for (id, count) in other.counts {
+            *self.counts.entry(id).or_default() += count;
+        }
+        self.chunks_counted += other.chunks_counted;
+    }
+}
+
+/// Counts the entities in an entity region file.
+pub fn count_entities<R: Read + Seek>(region: &mut Region<R>, dimension: &str) -> EntityCounts {
+    let mut counts = EntityCounts::empty(dimension.to_owned());
+    for data in readable_chunks(region) {
+        let Some(ids) = chunk_entity_ids(&data.data) else {
+            continue;
+        };
+        for id in ids {
+            *counts.counts.entry(id).or_default() += 1;
+        }
+        counts.chunks_counted += 1;
+    }
+    counts
+}
+
+/// Generates a CSV with a row per dimension and entity, most common first.
+pub fn generate_entities_csv(counts: &[EntityCounts]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,entity_id,count\n").unwrap();
+    for dim_counts in counts {
+        let mut entities: Vec<_> = dim_counts.counts.iter().collect();
+        entities.sort_by(|(id1, count1), (id2, count2)| count2.cmp(count1).then(id1.cmp(id2)));
+        for (id, count) in entities {
+            writeln!(res, "{},{},{}", dim_counts.dimension, id, count)
+                .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+/// The entity counts of a region, along with its coordinates.
+pub type RegionEntityCounts = ((isize, isize), EntityCounts);
+
+/// Generates a CSV with a row per region and entity, to find the regions where
+/// entities pile up. Regions are ordered from the one with the most entities,
+/// and within a region, entities from the most common.
+pub fn generate_region_entities_csv(regions: &[RegionEntityCounts]) -> String {
+    let mut regions: Vec<_> = regions.iter().collect();
+    regions.sort_by_key(|((x, z), counts)| {
+        (
+            std::cmp::Reverse(counts.counts.values().sum::<u64>()),
+            &counts.dimension,
+            *x,
+            *z,
+        )
+    });
+    let mut res = String::new();
+    res.write_str("dim,region_x,region_z,entity_id,count\n")
+        .unwrap();
+    for ((x, z), region_counts) in regions {
+        let mut entities: Vec<_> = region_counts.counts.iter().collect();
+        entities.sort_by(|(id1, count1), (id2, count2)| count2.cmp(count1).then(id1.cmp(id2)));
+        for (id, count) in entities {
+            writeln!(
+                res,
+                "{},{},{},{},{}",
+                region_counts.dimension, x, z, id, count
+            )
+            .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+#[test]
+fn test_count_entities() {
+    use crate::fixtures::region_with;
+
+    #[derive(serde::Serialize)]
+    struct EntityNbt {
+        id: &'static str,
+        #[serde(rename = "Passengers")]
+        passengers: Vec<EntityNbt>,
+    }
+    #[derive(serde::Serialize)]
+    struct EntityChunkNbt {
+        #[serde(rename = "Entities")]
+        entities: Vec<EntityNbt>,
+    }
+    let entity = |id, passengers| EntityNbt { id, passengers };
+    let chunk = |entities| fastnbt::to_bytes(&EntityChunkNbt { entities }).unwrap();
+
+    let mut region = region_with(&[
+        (
+            (0, 0),
+            chunk(vec![
+                entity("minecraft:item", vec![]),
+                entity(
+                    "minecraft:spider",
+                    vec![entity("minecraft:skeleton", vec![])],
+                ),
+            ]),
+        ),
+        ((3, 1), chunk(vec![entity("minecraft:item", vec![])])),
+    ]);
+    let dense = count_entities(&mut region, "test:dim");
+    assert_eq!(dense.chunks_counted, 2);
+    assert_eq!(dense.counts["minecraft:item"], 2);
+    assert_eq!(dense.counts["minecraft:skeleton"], 1);
+
+    let mut region = region_with(&[((0, 0), chunk(vec![entity("minecraft:cow", vec![])]))]);
+    let sparse = count_entities(&mut region, "test:dim");
+    let csv = generate_region_entities_csv(&[((5, -2), sparse), ((0, 0), dense)]);
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("dim,region_x,region_z,entity_id,count"));
+    assert_eq!(lines.next(), Some("test:dim,0,0,minecraft:item,2"));
+    assert_eq!(lines.nth(2), Some("test:dim,5,-2,minecraft:cow,1"));
+}
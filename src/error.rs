@@ -0,0 +1,63 @@
+use std::fmt;
+
+/// The ways the library's functions can fail on bad input, rather than
+/// panicking.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegionScannerError {
+    /// A zone with `to_x <= from_x` or `to_z <= from_z` (as told by `axis`),
+    /// which would be empty.
+    EmptyZone { axis: char, from: isize, to: isize },
+    /// A zone given as a list of coordinates that doesn't have exactly 4.
+    WrongZoneLength(Vec<isize>),
+    /// Not a single chunk in the regions searched could be read.
+    NoReadableChunks,
+    /// Frequencies of different dimensions were to be merged as one.
+    DimensionMismatch { main: String, other: String },
+    /// A weight for [`counts_add_weighted`](crate::counts_add_weighted)
+    /// outside of 0..=1.
+    WeightOutOfRange(f64),
+    /// A cutoff for [`remove_too_rare`](crate::remove_too_rare) that isn't
+    /// positive.
+    NonPositiveCutoff(f64),
+    /// A world height for
+    /// [`remove_too_rare_by_height`](crate::remove_too_rare_by_height) that
+    /// isn't positive.
+    NonPositiveHeight(f64),
+}
+
+impl fmt::Display for RegionScannerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionScannerError::EmptyZone { axis, from, to } => write!(
+                f,
+                "Tried to create a Zone with from_{axis}={from}, to_{axis}={to}. This is invalid - \
+                 to_{axis} must be larger than from_{axis} for the zone to be nonempty. Perhaps the \
+                 order of arguments is wrong?"
+            ),
+            RegionScannerError::WrongZoneLength(coords) => write!(
+                f,
+                "A zone needs exactly 4 coordinates (from_x, to_x, from_z, to_z), got {coords:?}"
+            ),
+            RegionScannerError::NoReadableChunks => write!(
+                f,
+                "Was unable to find a single chunk in a single region in the zone provided that \
+                 was readable!"
+            ),
+            RegionScannerError::DimensionMismatch { main, other } => write!(
+                f,
+                "Tried to merge frequencies of different dimensions ({main} and {other})!"
+            ),
+            RegionScannerError::WeightOutOfRange(weight) => {
+                write!(f, "Weight {weight} is not in the [0,1] range!")
+            }
+            RegionScannerError::NonPositiveCutoff(cutoff) => {
+                write!(f, "Cutoff must be positive, got {cutoff}")
+            }
+            RegionScannerError::NonPositiveHeight(height) => {
+                write!(f, "World height must be positive, got {height}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegionScannerError {}
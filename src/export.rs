@@ -0,0 +1,753 @@
+//! Exports that aggregate the frequencies further rather than listing them
+//! level by level.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use itertools::Itertools;
+
+use crate::{
+    is_air, normalize_block_name, BlockFrequencies, BlockMetaTable, RegionVersion,
+    BLOCK_META_HEADER,
+};
+
+/// Ends the header line of a CSV, with the block metadata columns if `meta`
+/// is given.
+fn write_meta_header(res: &mut String, meta: Option<&BlockMetaTable>) {
+    if meta.is_some() {
+        write!(res, ",{BLOCK_META_HEADER}").unwrap();
+    }
+    res.push('\n');
+}
+
+/// Ends the row of a block, with its metadata if `meta` is given.
+fn write_meta_columns(res: &mut String, meta: Option<&BlockMetaTable>, block: &str) {
+    if let Some(meta) = meta {
+        res.push_str(&meta.csv_columns(block));
+    }
+    res.push('\n');
+}
+
+/// Generates a CSV with the number of blocks of each kind in every 16 block
+/// tall section (subchunk), with `section_y` being the section's index as in
+/// the chunk format (so y=-64..-49 is section -4). Coarser than the tall CSV,
+/// but much smaller.
+pub fn generate_sections_csv(frequency_data: &[(BlockFrequencies, RegionVersion)]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block,section_y,count\n").unwrap();
+    for (freq_data, _version) in frequency_data {
+        for (name, freqs) in freq_data.sorted_blocks() {
+            let mut sections: BTreeMap<isize, f64> = BTreeMap::new();
+            for (y, freq) in freqs {
+                *sections.entry(y.div_euclid(16)).or_default() += freq * freq_data.area as f64;
+            }
+            for (section_y, count) in sections {
+                writeln!(
+                    res,
+                    "{},{},{},{}",
+                    freq_data.dimension,
+                    name,
+                    section_y,
+                    count.round() as u64
+                )
+                .expect("Error when assembling CSV");
+            }
+        }
+    }
+    res
+}
+
+#[test]
+fn test_sections_csv() {
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.area = 256;
+    freqs.frequencies.insert(
+        "minecraft:stone".to_owned(),
+        HashMap::from([
+            (-64, 0.5),
+            (-49, 0.25),
+            (-48, 1.),
+            (-1, 0.125),
+            (15, 0.5),
+            (16, 0.25),
+        ]),
+    );
+    let csv = generate_sections_csv(&[(freqs, RegionVersion::AtLeast118)]);
+    // Negative levels round down, so y=-1 is in section -1 rather than 0.
+    assert_eq!(
+        csv,
+        "dim,block,section_y,count\n\
+         minecraft:overworld,minecraft:stone,-4,192\n\
+         minecraft:overworld,minecraft:stone,-3,256\n\
+         minecraft:overworld,minecraft:stone,-1,32\n\
+         minecraft:overworld,minecraft:stone,0,128\n\
+         minecraft:overworld,minecraft:stone,1,64\n"
+    );
+}
+
+/// Generates a compact CSV with just the level where each block is the most
+/// common and its frequency there, i.e. the best level to mine it at. Rows are
+/// sorted by dimension and block. Ties are resolved to the lowest level.
+pub fn generate_peaks_csv(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+    meta: Option<&BlockMetaTable>,
+) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block,best_y,freq_at_best_y").unwrap();
+    write_meta_header(&mut res, meta);
+    let mut dims: Vec<&BlockFrequencies> = frequency_data.iter().map(|(f, _)| f).collect();
+    dims.sort_by_key(|f| &f.dimension);
+    for freq_data in dims {
+        for (name, freqs) in freq_data.sorted_blocks() {
+            let Some((best_y, best_freq)) = freqs
+                .iter()
+                .max_by(|(y1, f1), (y2, f2)| f1.total_cmp(f2).then(y2.cmp(y1)))
+            else {
+                continue;
+            };
+            write!(
+                res,
+                "{},{},{},{}",
+                freq_data.dimension, name, best_y, best_freq
+            )
+            .expect("Error when assembling CSV");
+            write_meta_columns(&mut res, meta, name);
+        }
+    }
+    res
+}
+
+#[test]
+fn test_peaks_csv() {
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.frequencies = HashMap::from([
+        (
+            "minecraft:iron_ore".to_owned(),
+            HashMap::from([(-10, 0.125), (16, 0.25), (40, 0.0625)]),
+        ),
+        // Tied between two levels, so the lowest one is the best.
+        (
+            "minecraft:diamond_ore".to_owned(),
+            HashMap::from([(20, 0.5), (-10, 0.5), (0, 0.25)]),
+        ),
+    ]);
+    let csv = generate_peaks_csv(&[(freqs, RegionVersion::AtLeast118)], None);
+    assert_eq!(
+        csv,
+        "dim,block,best_y,freq_at_best_y\n\
+         minecraft:overworld,minecraft:diamond_ore,-10,0.5\n\
+         minecraft:overworld,minecraft:iron_ore,16,0.25\n"
+    );
+}
+
+/// How the player mines, for estimating how many ores they find per hour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum MiningPattern {
+    /// A 1 wide, 2 high tunnel, with the player's feet at the Y
+    #[default]
+    Tunnel,
+    /// A 3 by 3 tunnel centered at the Y, as dug with a hammer
+    Hammer,
+}
+
+impl MiningPattern {
+    /// The number of blocks at each level relative to the Y that the player
+    /// gets to see for every block mined: the blocks mined themselves and the
+    /// walls, floor and ceiling of the tunnel.
+    pub fn exposure(self) -> Vec<(isize, f64)> {
+        let (width, height, bottom) = match self {
+            MiningPattern::Tunnel => (1, 2, 0),
+            MiningPattern::Hammer => (3, 3, -1),
+        };
+        let mined = (width * height) as f64;
+        let mut exposure = vec![(bottom - 1, width as f64 / mined)];
+        exposure.extend((bottom..bottom + height).map(|dy| (dy, (width + 2) as f64 / mined)));
+        exposure.push((bottom + height, width as f64 / mined));
+        exposure
+    }
+}
+
+/// The number of blocks of a kind found per hour of mining at level `y`, given
+/// its per-level frequencies.
+pub fn blocks_per_hour(
+    freqs: &HashMap<isize, f64>,
+    y: isize,
+    pattern: MiningPattern,
+    mined_per_hour: f64,
+) -> f64 {
+    let seen: f64 = pattern
+        .exposure()
+        .iter()
+        .map(|&(dy, blocks)| blocks * freqs.get(&(y + dy)).unwrap_or(&0.))
+        .sum();
+    seen * mined_per_hour
+}
+
+#[test]
+fn test_blocks_per_hour() {
+    // A 1x2 tunnel sees 8 blocks per 2 mined, a 3x3 one 21 per 9 mined.
+    for (pattern, seen) in [
+        (MiningPattern::Tunnel, 4.),
+        (MiningPattern::Hammer, 21. / 9.),
+    ] {
+        let total: f64 = pattern.exposure().iter().map(|(_, blocks)| blocks).sum();
+        assert!((total - seen).abs() < 1e-9);
+    }
+    let freqs = [(10, 0.01), (11, 0.02)].into();
+    let rate = blocks_per_hour(&freqs, 10, MiningPattern::Tunnel, 100.);
+    assert!((rate - (1.5 * 0.01 + 1.5 * 0.02) * 100.).abs() < 1e-9);
+    assert_eq!(blocks_per_hour(&freqs, 20, MiningPattern::Tunnel, 100.), 0.);
+}
+
+/// Generates a CSV with the level where mining finds the most of each block
+/// and how many of it are found per hour there, for the given mining pattern
+/// and speed (in blocks mined per hour). Rows are sorted by dimension and
+/// block, and ties are resolved to the lowest level.
+pub fn generate_mining_rate_csv(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+    pattern: MiningPattern,
+    mined_per_hour: f64,
+    meta: Option<&BlockMetaTable>,
+) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block,best_y,per_hour").unwrap();
+    write_meta_header(&mut res, meta);
+    let mut dims: Vec<&BlockFrequencies> = frequency_data.iter().map(|(f, _)| f).collect();
+    dims.sort_by_key(|f| &f.dimension);
+    let exposure = pattern.exposure();
+    for freq_data in dims {
+        for (name, freqs) in freq_data.sorted_blocks() {
+            let (Some(&min_y), Some(&max_y)) = (freqs.keys().min(), freqs.keys().max()) else {
+                continue;
+            };
+            // Only the levels from which some of the block can be seen.
+            let lowest = min_y - exposure.last().expect("never empty").0;
+            let highest = max_y - exposure[0].0;
+            let Some((best_y, rate)) = (lowest..=highest)
+                .map(|y| (y, blocks_per_hour(freqs, y, pattern, mined_per_hour)))
+                .max_by(|(y1, r1), (y2, r2)| r1.total_cmp(r2).then(y2.cmp(y1)))
+            else {
+                continue;
+            };
+            write!(res, "{},{},{},{}", freq_data.dimension, name, best_y, rate)
+                .expect("Error when assembling CSV");
+            write_meta_columns(&mut res, meta, name);
+        }
+    }
+    res
+}
+
+/// The mean and standard deviation of the levels a block occurs on, weighted
+/// by its frequency there. `None` if the block never occurs.
+pub fn level_spread(freqs: &HashMap<isize, f64>) -> Option<(f64, f64)> {
+    let total: f64 = freqs.values().sum();
+    if total <= 0. {
+        return None;
+    }
+    let mean = freqs.iter().map(|(&y, freq)| y as f64 * freq).sum::<f64>() / total;
+    let variance = freqs
+        .iter()
+        .map(|(&y, freq)| (y as f64 - mean).powi(2) * freq)
+        .sum::<f64>()
+        / total;
+    Some((mean, variance.sqrt()))
+}
+
+#[test]
+fn test_level_spread() {
+    assert_eq!(level_spread(&[(0, 0.5), (2, 0.5)].into()), Some((1., 1.)));
+    assert_eq!(level_spread(&[(-60, 0.01)].into()), Some((-60., 0.)));
+    assert_eq!(level_spread(&HashMap::new()), None);
+}
+
+/// Generates a CSV with the mean level of each block and the standard deviation
+/// of its levels, which tells ores generating in a narrow band apart from
+/// blocks found at every height.
+pub fn generate_spread_csv(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+    meta: Option<&BlockMetaTable>,
+) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block,mean_y,std_y").unwrap();
+    write_meta_header(&mut res, meta);
+    for (freq_data, _version) in frequency_data {
+        for (name, freqs) in freq_data.sorted_blocks() {
+            let Some((mean, std)) = level_spread(freqs) else {
+                continue;
+            };
+            write!(res, "{},{},{},{}", freq_data.dimension, name, mean, std)
+                .expect("Error when assembling CSV");
+            write_meta_columns(&mut res, meta, name);
+        }
+    }
+    res
+}
+
+/// Whether a block ID looks like an ore, like `minecraft:deepslate_iron_ore` or
+/// `immersiveengineering:ore_copper`.
+pub fn is_ore_name(name: &str) -> bool {
+    let path = name.split_once(':').map_or(name, |(_, path)| path);
+    path.split('_').any(|word| word == "ore")
+}
+
+/// Generates a CSV with the totals of the blocks of every namespace (mod) in
+/// each dimension: the number of kinds of blocks, the number of blocks and of
+/// ores among them, and the share of all the dimension's blocks.
+pub fn generate_namespaces_csv(frequency_data: &[(BlockFrequencies, RegionVersion)]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,namespace,kinds,count,ore_count,share\n")
+        .unwrap();
+    for (freq_data, _version) in frequency_data {
+        // (kinds, count, ore count) by namespace.
+        let mut namespaces: BTreeMap<&str, (usize, f64, f64)> = BTreeMap::new();
+        for (name, freqs) in &freq_data.frequencies {
+            let namespace = name.split_once(':').map_or("minecraft", |(ns, _)| ns);
+            let count = freqs.values().sum::<f64>() * freq_data.area as f64;
+            let totals = namespaces.entry(namespace).or_default();
+            totals.0 += 1;
+            totals.1 += count;
+            if is_ore_name(name) {
+                totals.2 += count;
+            }
+        }
+        let total: f64 = namespaces.values().map(|(_, count, _)| count).sum();
+        for (namespace, (kinds, count, ore_count)) in namespaces {
+            writeln!(
+                res,
+                "{},{},{},{},{},{}",
+                freq_data.dimension,
+                namespace,
+                kinds,
+                count.round() as u64,
+                ore_count.round() as u64,
+                // Without any blocks, every namespace has a share of 0 rather
+                // than NaN.
+                if total > 0. { count / total } else { 0. }
+            )
+            .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+#[test]
+fn test_namespaces_csv() {
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.area = 256;
+    freqs.frequencies = HashMap::from([
+        ("minecraft:stone".to_owned(), HashMap::from([(0, 0.5)])),
+        ("minecraft:iron_ore".to_owned(), HashMap::from([(0, 0.25)])),
+        ("mymod:ore_tin".to_owned(), HashMap::from([(0, 0.25)])),
+    ]);
+    let csv = generate_namespaces_csv(&[(freqs, RegionVersion::AtLeast118)]);
+    assert_eq!(
+        csv,
+        "dim,namespace,kinds,count,ore_count,share\n\
+         minecraft:overworld,minecraft,2,192,64,0.75\n\
+         minecraft:overworld,mymod,1,64,64,0.25\n"
+    );
+
+    // Without any area, there are no blocks to take a share of.
+    let mut empty = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    empty
+        .frequencies
+        .insert("minecraft:stone".to_owned(), HashMap::from([(0, 0.5)]));
+    let csv = generate_namespaces_csv(&[(empty, RegionVersion::AtLeast118)]);
+    assert_eq!(
+        csv.lines().nth(1),
+        Some("minecraft:overworld,minecraft,1,0,0,0")
+    );
+}
+
+#[test]
+fn test_is_ore_name() {
+    assert!(is_ore_name("minecraft:deepslate_iron_ore"));
+    assert!(is_ore_name("immersiveengineering:ore_copper"));
+    assert!(is_ore_name("mekanism:deepslate_ore_osmium"));
+    assert!(!is_ore_name("minecraft:stone"));
+    assert!(!is_ore_name("create:andesite_alloy_block"));
+    assert!(!is_ore_name("minecraft:core"));
+}
+
+/// Generates a CSV with the share of each block that's at or below every level,
+/// from the lowest level it occurs on to the highest. For example, the first
+/// level where `cumulative_freq` reaches 0.9 is the one below which 90% of the
+/// block is found.
+pub fn generate_cumulative_csv(frequency_data: &[(BlockFrequencies, RegionVersion)]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block,y,cumulative_freq\n").unwrap();
+    for (freq_data, _version) in frequency_data {
+        for (name, freqs) in freq_data.sorted_blocks() {
+            let total: f64 = freqs.values().sum();
+            let (Some(&min_y), Some(&max_y)) = (freqs.keys().min(), freqs.keys().max()) else {
+                continue;
+            };
+            if total <= 0. {
+                continue;
+            }
+            let mut cumulative = 0.;
+            for y in min_y..=max_y {
+                cumulative += freqs.get(&y).unwrap_or(&0.);
+                writeln!(
+                    res,
+                    "{},{},{},{}",
+                    freq_data.dimension,
+                    name,
+                    y,
+                    // Rounding errors could make the last level land just
+                    // below 1.
+                    if y == max_y { 1. } else { cumulative / total }
+                )
+                .expect("Error when assembling CSV");
+            }
+        }
+    }
+    res
+}
+
+#[test]
+fn test_cumulative_csv() {
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.frequencies = HashMap::from([
+        (
+            "minecraft:iron_ore".to_owned(),
+            HashMap::from([(0, 0.1), (2, 0.2), (3, 0.3)]),
+        ),
+        // Never found, so there's no share to spread.
+        (
+            "minecraft:diamond_ore".to_owned(),
+            HashMap::from([(0, 0.), (1, 0.)]),
+        ),
+    ]);
+    let csv = generate_cumulative_csv(&[(freqs, RegionVersion::AtLeast118)]);
+    let rows: Vec<(isize, f64)> = csv
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let fields: Vec<&str> = line.split(',').collect();
+            assert_eq!(fields[..2], ["minecraft:overworld", "minecraft:iron_ore"]);
+            (fields[2].parse().unwrap(), fields[3].parse().unwrap())
+        })
+        .collect();
+    let levels: Vec<isize> = rows.iter().map(|(y, _)| *y).collect();
+    assert_eq!(levels, [0, 1, 2, 3]);
+    assert!((rows[0].1 - 1. / 6.).abs() < 1e-12);
+    assert_eq!(rows[0].1, rows[1].1);
+    assert!((rows[2].1 - 0.5).abs() < 1e-12);
+    // The running sum could land just off the total, as the levels are added
+    // up in another order, but the last level is always exactly 1.
+    assert_eq!(rows[3].1, 1.);
+}
+
+/// Generates a CSV with the number of non-air blocks on every level of each
+/// dimension, densest level first, which shows where the bulk of the terrain
+/// is.
+pub fn generate_density_csv(frequency_data: &[(BlockFrequencies, RegionVersion)]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,y,non_air_count\n").unwrap();
+    for (freq_data, _version) in frequency_data {
+        let mut levels: HashMap<isize, f64> = HashMap::new();
+        for (name, freqs) in &freq_data.frequencies {
+            if is_air(name) {
+                continue;
+            }
+            for (&y, freq) in freqs {
+                *levels.entry(y).or_default() += freq * freq_data.area as f64;
+            }
+        }
+        let mut levels: Vec<(isize, u64)> = levels
+            .into_iter()
+            .map(|(y, count)| (y, count.round() as u64))
+            .collect();
+        levels.sort_unstable_by(|(y1, c1), (y2, c2)| c2.cmp(c1).then(y1.cmp(y2)));
+        for (y, count) in levels {
+            writeln!(res, "{},{},{}", freq_data.dimension, y, count)
+                .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+#[test]
+fn test_density_csv() {
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.area = 256;
+    freqs.frequencies = HashMap::from([
+        (
+            "minecraft:stone".to_owned(),
+            HashMap::from([(0, 0.5), (1, 0.25)]),
+        ),
+        ("minecraft:dirt".to_owned(), HashMap::from([(1, 0.5)])),
+        ("minecraft:gravel".to_owned(), HashMap::from([(2, 0.5)])),
+        (
+            "minecraft:air".to_owned(),
+            HashMap::from([(0, 0.5), (2, 0.5), (3, 1.)]),
+        ),
+    ]);
+    let csv = generate_density_csv(&[(freqs, RegionVersion::AtLeast118)]);
+    // Air isn't counted, and levels as dense as each other go from the bottom.
+    assert_eq!(
+        csv,
+        "dim,y,non_air_count\n\
+         minecraft:overworld,1,192\n\
+         minecraft:overworld,0,128\n\
+         minecraft:overworld,2,128\n"
+    );
+}
+
+/// Generates a CSV with the lowest and highest level each block was found on,
+/// and the number of it found on those levels. Handy for spotting blocks
+/// generating out of their intended range.
+pub fn generate_extremes_csv(frequency_data: &[(BlockFrequencies, RegionVersion)]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block,min_y,max_y,count_at_min_y,count_at_max_y\n")
+        .unwrap();
+    for (freq_data, _version) in frequency_data {
+        for (name, freqs) in freq_data.sorted_blocks() {
+            let levels = freqs.iter().filter(|(_, &freq)| freq > 0.).map(|(y, _)| y);
+            let Some((&min_y, &max_y)) = levels.minmax().into_option() else {
+                continue;
+            };
+            let count = |y: isize| (freqs[&y] * freq_data.area as f64).round() as u64;
+            writeln!(
+                res,
+                "{},{},{},{},{},{}",
+                freq_data.dimension,
+                name,
+                min_y,
+                max_y,
+                count(min_y),
+                count(max_y)
+            )
+            .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+/// Parses the weights of blocks for [`generate_value_csv`] from a CSV with a
+/// `block,value` header, like `minecraft:emerald_ore,10`.
+pub fn parse_block_values(csv: &str) -> Result<HashMap<String, f64>, String> {
+    let mut lines = csv
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+    match lines.next() {
+        Some((_, header)) if header.trim() == "block,value" => {}
+        _ => return Err("expected a 'block,value' header".to_owned()),
+    }
+    let mut values = HashMap::new();
+    for (i, line) in lines {
+        let (block, value) = line
+            .split_once(',')
+            .ok_or_else(|| format!("line {} isn't like 'block,value': '{line}'", i + 1))?;
+        let value: f64 = value
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid value on line {}: {e}", i + 1))?;
+        values.insert(normalize_block_name(block.trim()).into_owned(), value);
+    }
+    Ok(values)
+}
+
+/// Generates a CSV with the value density of every level: the sum of the
+/// frequencies of the blocks there weighted by their values, i.e. the value
+/// found per block mined at that level. Blocks without a value count as 0.
+pub fn generate_value_csv(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+    values: &HashMap<String, f64>,
+) -> String {
+    let mut res = String::new();
+    res.write_str("dim,y,value_density\n").unwrap();
+    for (freq_data, _version) in frequency_data {
+        let mut levels: BTreeMap<isize, f64> = BTreeMap::new();
+        for (name, freqs) in &freq_data.frequencies {
+            let value = values.get(name).copied().unwrap_or(0.);
+            for (&y, freq) in freqs {
+                *levels.entry(y).or_default() += freq * value;
+            }
+        }
+        for (y, density) in levels {
+            writeln!(res, "{},{},{}", freq_data.dimension, y, density)
+                .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+/// Modded blocks with a direct vanilla equivalent, used by
+/// [`canonicalize_blocks`] unless overridden.
+const DEFAULT_CANONICAL_BLOCKS: &[(&str, &str)] = &[
+    ("create:copper_ore", "minecraft:copper_ore"),
+    ("immersiveengineering:ore_copper", "minecraft:copper_ore"),
+    (
+        "immersiveengineering:deepslate_ore_copper",
+        "minecraft:deepslate_copper_ore",
+    ),
+    ("mekanism:copper_ore", "minecraft:copper_ore"),
+    (
+        "mekanism:deepslate_copper_ore",
+        "minecraft:deepslate_copper_ore",
+    ),
+    ("thermal:copper_ore", "minecraft:copper_ore"),
+    (
+        "thermal:deepslate_copper_ore",
+        "minecraft:deepslate_copper_ore",
+    ),
+    ("techreborn:copper_ore", "minecraft:copper_ore"),
+    (
+        "techreborn:deepslate_copper_ore",
+        "minecraft:deepslate_copper_ore",
+    ),
+];
+
+/// Modded blocks that only stand in for a vanilla one in some modpacks, but
+/// drop something else. Only folded in when asked for.
+const APPROXIMATE_CANONICAL_BLOCKS: &[(&str, &str)] = &[
+    ("thermal:apatite_ore", "minecraft:lapis_ore"),
+    ("byg:pendorite_ore", "minecraft:ancient_debris"),
+];
+
+/// The default mapping of modded blocks to their vanilla equivalents,
+/// including the approximate ones if `approximate` is set.
+pub fn default_canonical_blocks(approximate: bool) -> HashMap<String, String> {
+    let approximate = if approximate {
+        APPROXIMATE_CANONICAL_BLOCKS
+    } else {
+        &[]
+    };
+    DEFAULT_CANONICAL_BLOCKS
+        .iter()
+        .chain(approximate)
+        .map(|(from, to)| (from.to_string(), to.to_string()))
+        .collect()
+}
+
+/// Parses a mapping of blocks to their canonical equivalent, with lines of
+/// the form `modid:some_ore=minecraft:some_ore`. Empty lines and lines
+/// starting with `#` are ignored. Returns the line number of the first
+/// malformed line as the error.
+pub fn parse_canonical_blocks(contents: &str) -> Result<HashMap<String, String>, usize> {
+    let mut mapping = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (from, to) = line.split_once('=').ok_or(i + 1)?;
+        mapping.insert(from.trim().to_owned(), to.trim().to_owned());
+    }
+    Ok(mapping)
+}
+
+/// Folds blocks into their canonical equivalents according to `mapping` (e.g.
+/// modded copper ores into `minecraft:copper_ore`), summing up their
+/// frequencies, so that worlds with different ore mods can be compared. Meant
+/// to be applied when exporting, so that the scan itself keeps the details.
+pub fn canonicalize_blocks(
+    frequency_data: &mut [(BlockFrequencies, RegionVersion)],
+    mapping: &HashMap<String, String>,
+) {
+    for (freq_data, _version) in frequency_data {
+        let mapped: Vec<String> = freq_data
+            .frequencies
+            .keys()
+            .filter(|name| mapping.get(*name).is_some_and(|to| to != *name))
+            .cloned()
+            .collect();
+        for name in mapped {
+            let freqs = freq_data.frequencies.remove(&name).unwrap();
+            trace!(
+                "Folding {} into {} for {}.",
+                name,
+                mapping[&name],
+                freq_data.dimension
+            );
+            let target = freq_data
+                .frequencies
+                .entry(mapping[&name].clone())
+                .or_default();
+            // Frequencies of the same dimension are over the same area, so they
+            // can just be added up.
+            for (y, freq) in freqs {
+                *target.entry(y).or_default() += freq;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_canonicalize_blocks() {
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.frequencies = HashMap::from([
+        (
+            "minecraft:copper_ore".to_owned(),
+            HashMap::from([(0, 0.25)]),
+        ),
+        (
+            "thermal:copper_ore".to_owned(),
+            HashMap::from([(0, 0.5), (1, 0.125)]),
+        ),
+        ("thermal:apatite_ore".to_owned(), HashMap::from([(2, 0.5)])),
+    ]);
+    let mut results = [(freqs, RegionVersion::AtLeast118)];
+    canonicalize_blocks(&mut results, &default_canonical_blocks(false));
+    let frequencies = &results[0].0.frequencies;
+    assert_eq!(
+        frequencies["minecraft:copper_ore"],
+        HashMap::from([(0, 0.75), (1, 0.125)])
+    );
+    assert!(!frequencies.contains_key("thermal:copper_ore"));
+    // Apatite only turns into lapis when asked for.
+    assert!(frequencies.contains_key("thermal:apatite_ore"));
+    canonicalize_blocks(&mut results, &default_canonical_blocks(true));
+    assert_eq!(
+        results[0].0.frequencies["minecraft:lapis_ore"],
+        HashMap::from([(2, 0.5)])
+    );
+}
+
+#[test]
+fn test_extremes_csv() {
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.area = 256;
+    freqs.frequencies.insert(
+        "minecraft:diamond_ore".to_owned(),
+        HashMap::from([(-70, 0.), (-64, 0.5), (10, 0.25), (16, 1. / 256.)]),
+    );
+    let csv = generate_extremes_csv(&[(freqs, RegionVersion::AtLeast118)]);
+    assert_eq!(
+        csv,
+        "dim,block,min_y,max_y,count_at_min_y,count_at_max_y\n\
+         minecraft:overworld,minecraft:diamond_ore,-64,16,128,1\n"
+    );
+}
+
+#[test]
+fn test_value_csv() {
+    let values =
+        parse_block_values("block,value\nminecraft:emerald_ore,10\ncoal_ore, 1\n").unwrap();
+    assert_eq!(values["minecraft:coal_ore"], 1.);
+    assert!(parse_block_values("minecraft:emerald_ore,10").is_err());
+
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.frequencies.insert(
+        "minecraft:emerald_ore".to_owned(),
+        HashMap::from([(0, 0.5)]),
+    );
+    freqs.frequencies.insert(
+        "minecraft:coal_ore".to_owned(),
+        HashMap::from([(0, 0.25), (1, 0.5)]),
+    );
+    freqs
+        .frequencies
+        .insert("minecraft:stone".to_owned(), HashMap::from([(2, 1.)]));
+    let csv = generate_value_csv(&[(freqs, RegionVersion::AtLeast118)], &values);
+    assert_eq!(
+        csv,
+        "dim,y,value_density\nminecraft:overworld,0,5.25\nminecraft:overworld,1,0.5\n\
+         minecraft:overworld,2,0\n"
+    );
+}
@@ -0,0 +1,91 @@
+//! Support for counting only the blocks exposed to air, which needs the
+//! neighbouring chunks of the one being counted at hand.
+
+use std::collections::BTreeMap;
+
+use fastanvil::{Chunk, ChunkData, JavaChunk};
+
+use crate::parse_chunk;
+
+/// The blocks that count as air.
+pub const AIR_BLOCKS: [&str; 3] = ["minecraft:air", "minecraft:cave_air", "minecraft:void_air"];
+
+pub fn is_air(name: &str) -> bool {
+    AIR_BLOCKS.contains(&name)
+}
+
+/// The parsed chunks of a few consecutive rows (by z) of a region, so that the
+/// blocks at the edge of a chunk can be checked against the adjacent chunks
+/// without keeping the whole region in memory.
+#[derive(Default)]
+pub(crate) struct ChunkWindow {
+    /// Keyed by `(z, x)`, with the raw NBT kept alongside the parsed chunk.
+    chunks: BTreeMap<(usize, usize), (ChunkData, Option<JavaChunk>)>,
+}
+
+impl ChunkWindow {
+    pub fn insert(&mut self, data: ChunkData) {
+        let parsed = parse_chunk(&data.data).ok();
+        self.chunks.insert((data.z, data.x), (data, parsed));
+    }
+
+    /// Drops the chunks of the rows before `z`.
+    pub fn retain_rows_from(&mut self, z: usize) {
+        self.chunks = self.chunks.split_off(&(z, 0));
+    }
+
+    /// The chunks present in row `z`, in order of x.
+    pub fn row(&self, z: usize) -> impl Iterator<Item = &(ChunkData, Option<JavaChunk>)> {
+        self.chunks
+            .range((z, 0)..(z + 1, 0))
+            .map(|(_, chunk)| chunk)
+    }
+
+    /// The chunk at a region-relative position, if it's in the window and
+    /// parsed successfully.
+    fn chunk(&self, x: isize, z: isize) -> Option<&JavaChunk> {
+        let (x, z) = (usize::try_from(x).ok()?, usize::try_from(z).ok()?);
+        self.chunks.get(&(z, x))?.1.as_ref()
+    }
+}
+
+/// Whether any of the six neighbours of the block at chunk-relative `(x, z)`
+/// and height `y` in the chunk at `chunk_pos` is air. Missing sections count
+/// as air, while the bottom of the world, missing chunks and chunks in other
+/// regions don't.
+pub(crate) fn is_exposed(
+    window: &ChunkWindow,
+    chunk: &JavaChunk,
+    chunk_pos: (usize, usize),
+    (x, y, z): (usize, isize, usize),
+) -> bool {
+    const OFFSETS: [(isize, isize, isize); 6] = [
+        (-1, 0, 0),
+        (1, 0, 0),
+        (0, -1, 0),
+        (0, 1, 0),
+        (0, 0, -1),
+        (0, 0, 1),
+    ];
+    let bottom = chunk.y_range().start;
+    OFFSETS.iter().any(|&(dx, dy, dz)| {
+        let (nx, ny, nz) = (x as isize + dx, y + dy, z as isize + dz);
+        if ny < bottom {
+            return false;
+        }
+        let neighbour = if (0..16).contains(&nx) && (0..16).contains(&nz) {
+            Some(chunk)
+        } else {
+            window.chunk(
+                chunk_pos.0 as isize + nx.div_euclid(16),
+                chunk_pos.1 as isize + nz.div_euclid(16),
+            )
+        };
+        let Some(neighbour) = neighbour else {
+            return false;
+        };
+        neighbour
+            .block(nx.rem_euclid(16) as usize, ny, nz.rem_euclid(16) as usize)
+            .is_none_or(|block| is_air(block.name()))
+    })
+}
@@ -0,0 +1,297 @@
+//! Builders for synthetic chunks and regions used by the tests.
+
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use fastanvil::Region;
+use fastnbt::LongArray;
+use serde::Serialize;
+
+use crate::DATA_VERSION_1_21;
+
+#[derive(Serialize)]
+pub struct ChunkNbt {
+    #[serde(rename = "DataVersion")]
+    pub data_version: i32,
+    #[serde(rename = "Status")]
+    pub status: String,
+    #[serde(rename = "InhabitedTime")]
+    pub inhabited_time: i64,
+    pub sections: Vec<SectionNbt>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub block_entities: Vec<BlockEntityNbt>,
+}
+
+#[derive(Serialize)]
+pub struct BlockEntityNbt {
+    pub id: String,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+#[derive(Serialize)]
+pub struct SectionNbt {
+    #[serde(rename = "Y")]
+    pub y: i8,
+    pub block_states: BlockStatesNbt,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub biomes: Option<BiomesNbt>,
+}
+
+#[derive(Serialize)]
+pub struct BlockStatesNbt {
+    pub palette: Vec<PaletteEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<LongArray>,
+}
+
+#[derive(Serialize)]
+pub struct BiomesNbt {
+    pub palette: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<LongArray>,
+}
+
+#[derive(Serialize)]
+pub struct PaletteEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Properties", skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, String>,
+}
+
+impl PaletteEntry {
+    /// An entry for a block state written like `minecraft:oak_log[axis=x]`.
+    pub fn new(state: &str) -> Self {
+        let (name, properties) = match state.strip_suffix(']').and_then(|s| s.split_once('[')) {
+            Some((name, properties)) => (
+                name,
+                properties
+                    .split(',')
+                    .filter_map(|property| property.split_once('='))
+                    .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                    .collect(),
+            ),
+            None => (state, BTreeMap::new()),
+        };
+        Self {
+            name: name.to_owned(),
+            properties,
+        }
+    }
+}
+
+impl SectionNbt {
+    /// A section consisting of a single kind of block.
+    pub fn uniform(y: i8, block: &str) -> Self {
+        Self {
+            y,
+            block_states: BlockStatesNbt {
+                palette: vec![PaletteEntry::new(block)],
+                data: None,
+            },
+            biomes: None,
+        }
+    }
+
+    /// A section where the block at section-relative `(x, y, z)` is
+    /// `palette[index(x, y, z)]`.
+    pub fn with_blocks(
+        y: i8,
+        palette: &[&str],
+        index: impl Fn(usize, usize, usize) -> usize,
+    ) -> Self {
+        let bits = usize::max(
+            4,
+            (usize::BITS - (palette.len() - 1).leading_zeros()) as usize,
+        );
+        let per_long = 64 / bits;
+        let mut data = vec![0i64; 4096usize.div_ceil(per_long)];
+        for i in 0..4096 {
+            let (x, z, sec_y) = (i & 0xF, (i >> 4) & 0xF, i >> 8);
+            let value = index(x, sec_y, z) as u64;
+            data[i / per_long] |= (value << ((i % per_long) * bits)) as i64;
+        }
+        Self {
+            y,
+            block_states: BlockStatesNbt {
+                palette: palette
+                    .iter()
+                    .map(|state| PaletteEntry::new(state))
+                    .collect(),
+                data: Some(LongArray::new(data)),
+            },
+            biomes: None,
+        }
+    }
+
+    /// Sets the whole section to a single biome.
+    pub fn in_biome(mut self, biome: &str) -> Self {
+        self.biomes = Some(BiomesNbt {
+            palette: vec![biome.to_owned()],
+            data: None,
+        });
+        self
+    }
+
+    /// Sets the biome of the 4x4x4 cell at section-relative cell coordinates
+    /// `(x, y, z)` to `palette[index(x, y, z)]`.
+    pub fn with_biomes(
+        mut self,
+        palette: &[&str],
+        index: impl Fn(usize, usize, usize) -> usize,
+    ) -> Self {
+        let bits = usize::max(
+            1,
+            (usize::BITS - (palette.len() - 1).leading_zeros()) as usize,
+        );
+        let per_long = 64 / bits;
+        let mut data = vec![0i64; 64usize.div_ceil(per_long)];
+        for i in 0..64 {
+            let (x, z, cell_y) = (i & 0x3, (i >> 2) & 0x3, i >> 4);
+            let value = index(x, cell_y, z) as u64;
+            data[i / per_long] |= (value << ((i % per_long) * bits)) as i64;
+        }
+        self.biomes = Some(BiomesNbt {
+            palette: palette.iter().map(|&biome| biome.to_owned()).collect(),
+            data: Some(LongArray::new(data)),
+        });
+        self
+    }
+}
+
+impl ChunkNbt {
+    /// A fully generated 1.21 chunk with the given sections.
+    pub fn full(sections: Vec<SectionNbt>) -> Self {
+        Self {
+            data_version: DATA_VERSION_1_21,
+            status: "minecraft:full".to_owned(),
+            inhabited_time: 0,
+            sections,
+            block_entities: vec![],
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fastnbt::to_bytes(self).unwrap()
+    }
+}
+
+/// Builds an in-memory region containing the given chunks, which are given by
+/// their region-relative position and uncompressed NBT.
+pub fn region_with(chunks: &[((usize, usize), Vec<u8>)]) -> Region<Cursor<Vec<u8>>> {
+    let mut region = Region::new(Cursor::new(vec![])).unwrap();
+    for ((x, z), data) in chunks {
+        region.write_chunk(*x, *z, data).unwrap();
+    }
+    region
+}
+
+/// The bytes of a region file containing the given chunks, as given to
+/// [`region_with`].
+pub fn region_bytes(chunks: &[((usize, usize), Vec<u8>)]) -> Vec<u8> {
+    region_with(chunks).into_inner().unwrap().into_inner()
+}
+
+/// The bytes of a region file with a single full chunk, at its corner, whose
+/// section at y 0 is all `block`.
+pub fn uniform_region(block: &str) -> Vec<u8> {
+    let chunk = ChunkNbt::full(vec![SectionNbt::uniform(0, block)]).to_bytes();
+    region_bytes(&[((0, 0), chunk)])
+}
+
+/// A folder in the temporary directory for a test to write files into, which
+/// is deleted with everything in it when dropped, even if the test fails.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates an empty folder named after `name`, which has to be unique
+    /// among the tests, as they run at the same time.
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("region_scanner_{name}_{}", std::process::id()));
+        // Left over by a run that was killed.
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Compresses a chunk with the given compression scheme byte of the region
+/// format: 1 for gzip, 2 for zlib, 3 for none and 4 for LZ4, in lz4-java's
+/// block stream format like Minecraft writes it.
+pub fn compress_chunk(scheme: u8, data: &[u8]) -> Vec<u8> {
+    use flate2::{
+        write::{GzEncoder, ZlibEncoder},
+        Compression,
+    };
+    match scheme {
+        1 => {
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+        2 => {
+            let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+        3 => data.to_vec(),
+        4 => {
+            let block = |method: u8, compressed: &[u8], len: usize| {
+                let mut res = b"LZ4Block".to_vec();
+                res.push(method);
+                res.extend((compressed.len() as u32).to_le_bytes());
+                res.extend((len as u32).to_le_bytes());
+                // The checksum, which isn't checked.
+                res.extend([0; 4]);
+                res.extend(compressed);
+                res
+            };
+            let mut res = block(0x20, &lz4_flex::block::compress(data), data.len());
+            res.extend(block(0x10, &[], 0));
+            res
+        }
+        _ => panic!("unknown compression scheme {scheme}"),
+    }
+}
+
+/// Builds the bytes of a region file containing the given chunks, which are
+/// given by their region-relative position, compression scheme (see
+/// [`compress_chunk`]) and uncompressed NBT. Unlike [`region_with`], this can
+/// use the schemes fastanvil can't write.
+pub fn raw_region_with(chunks: &[((usize, usize), u8, Vec<u8>)]) -> Vec<u8> {
+    let mut res = vec![0; 8192];
+    for ((x, z), scheme, data) in chunks {
+        let compressed = compress_chunk(*scheme, data);
+        let sector = res.len() / 4096;
+        let sectors = (compressed.len() + 5).div_ceil(4096);
+        let entry = 4 * (x + z * 32);
+        res[entry..entry + 3].copy_from_slice(&(sector as u32).to_be_bytes()[1..]);
+        res[entry + 3] = sectors as u8;
+        res.extend((compressed.len() as u32 + 1).to_be_bytes());
+        res.push(*scheme);
+        res.extend(compressed);
+        res.resize((sector + sectors) * 4096, 0);
+    }
+    res
+}
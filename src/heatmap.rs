@@ -0,0 +1,231 @@
+//! Where a block concentrates across a dimension, counted per chunk.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    io::{Read, Seek},
+};
+
+use fastanvil::Region;
+
+use crate::{chunk_block_count, countable_chunk, readable_chunks, ScanOptions};
+
+/// The most cells on each side of the grid of a heatmap. A heatmap spanning
+/// more chunks than that adds them up into cells of several chunks, so that a
+/// few chunks far apart don't make a grid of billions of cells.
+pub const MAX_HEATMAP_SIDE: isize = 1024;
+
+/// The number of a block in every counted chunk of a dimension, keyed by the
+/// chunk coordinates (block coordinates divided by 16).
+pub struct BlockHeatmap {
+    pub dimension: String,
+    pub block: String,
+    pub counts: HashMap<(isize, isize), u64>,
+}
+
+impl BlockHeatmap {
+    pub fn empty(dimension: String, block: String) -> Self {
+        Self {
+            dimension,
+            block,
+            counts: HashMap::new(),
+        }
+    }
+
+    pub fn merge(&mut self, other: BlockHeatmap) {
+        self.counts.extend(other.counts);
+    }
+
+    /// The lowest and highest chunk coordinates, as `((min_x, min_z), (max_x,
+    /// max_z))`, or `None` if no chunk was counted.
+    pub fn bounds(&self) -> Option<((isize, isize), (isize, isize))> {
+        let xs = self.counts.keys().map(|&(x, _)| x);
+        let zs = self.counts.keys().map(|&(_, z)| z);
+        Some((
+            (xs.clone().min()?, zs.clone().min()?),
+            (xs.max()?, zs.max()?),
+        ))
+    }
+
+    /// The number of chunks on each side of a cell of the grid, so that the grid
+    /// is at most [`MAX_HEATMAP_SIDE`] cells wide and tall.
+    pub fn cell_size(&self) -> isize {
+        let Some(((min_x, min_z), (max_x, max_z))) = self.bounds() else {
+            return 1;
+        };
+        let side = (max_x - min_x).max(max_z - min_z) + 1;
+        if side <= MAX_HEATMAP_SIDE {
+            return 1;
+        }
+        // The cells are aligned on multiples of their size, so the chunks can
+        // spread over one more cell than they fill.
+        (side + MAX_HEATMAP_SIDE - 2) / (MAX_HEATMAP_SIDE - 1)
+    }
+
+    /// The counts added up into square cells of [`BlockHeatmap::cell_size`]
+    /// chunks on each side, keyed by the chunk coordinates divided by that size.
+    /// With cells of one chunk, that's the heatmap itself.
+    pub fn binned(&self) -> BlockHeatmap {
+        let size = self.cell_size();
+        let mut binned = BlockHeatmap::empty(self.dimension.clone(), self.block.clone());
+        for (&(x, z), &count) in &self.counts {
+            *binned
+                .counts
+                .entry((x.div_euclid(size), z.div_euclid(size)))
+                .or_default() += count;
+        }
+        binned
+    }
+
+    /// The highest count in a chunk, 0 if none were counted.
+    pub fn max_count(&self) -> u64 {
+        self.counts.values().copied().max().unwrap_or(0)
+    }
+}
+
+/// Counts a block in every chunk of the region at `(region_x, region_z)` that
+/// passes the filters of `options`, including the chunks without any.
+pub fn region_block_heatmap<R: Read + Seek>(
+    region: &mut Region<R>,
+    (region_x, region_z): (isize, isize),
+    block: &str,
+    dimension: &str,
+    options: &ScanOptions,
+) -> BlockHeatmap {
+    let mut heatmap = BlockHeatmap::empty(dimension.to_owned(), block.to_owned());
+    for data in readable_chunks(region) {
+        let Some(chunk) = countable_chunk(&data.data, options) else {
+            continue;
+        };
+        let Some(count) = chunk_block_count(&chunk, block, dimension, options) else {
+            soft_warn!(
+                "Reading chunk ({}, {}) of region ({region_x}, {region_z}) in {dimension} failed, \
+                 its data is probably corrupt. Skipping it.",
+                data.x,
+                data.z
+            );
+            continue;
+        };
+        let coords = (
+            region_x * 32 + data.x as isize,
+            region_z * 32 + data.z as isize,
+        );
+        heatmap.counts.insert(coords, count);
+    }
+    heatmap
+}
+
+/// Generates a CSV grid of the counts, with a column per chunk X and a row per
+/// chunk Z, as on a map with north up. The chunks that weren't counted, e.g.
+/// because they weren't generated, are left empty. A heatmap too large for that
+/// has a column and row per cell of several chunks instead (see
+/// [`BlockHeatmap::binned`]), labeled with the coordinates of its first chunk.
+pub fn generate_heatmap_csv(heatmap: &BlockHeatmap) -> String {
+    let mut res = String::new();
+    res.write_str("z\\x").unwrap();
+    let size = heatmap.cell_size();
+    let cells = heatmap.binned();
+    let Some(((min_x, min_z), (max_x, max_z))) = cells.bounds() else {
+        res.push('\n');
+        return res;
+    };
+    for x in min_x..=max_x {
+        write!(res, ",{}", x * size).unwrap();
+    }
+    res.push('\n');
+    for z in min_z..=max_z {
+        write!(res, "{}", z * size).unwrap();
+        for x in min_x..=max_x {
+            match cells.counts.get(&(x, z)) {
+                Some(count) => write!(res, ",{count}").unwrap(),
+                None => res.push(','),
+            }
+        }
+        res.push('\n');
+    }
+    res
+}
+
+#[test]
+fn test_region_block_heatmap() {
+    use crate::fixtures::*;
+    let with_ores = |ores: usize| {
+        ChunkNbt::full(vec![SectionNbt::with_blocks(
+            0,
+            &["minecraft:stone", "minecraft:diamond_ore"],
+            move |x, _, z| usize::from(x < ores && z == 0),
+        )])
+        .to_bytes()
+    };
+    let mut region = region_with(&[((0, 0), with_ores(0)), ((2, 1), with_ores(3))]);
+    let heatmap = region_block_heatmap(
+        &mut region,
+        (-1, 0),
+        "minecraft:diamond_ore",
+        "test:dim",
+        &ScanOptions::default(),
+    );
+    assert_eq!(
+        heatmap.counts,
+        HashMap::from([((-32, 0), 0), ((-30, 1), 3 * 16)])
+    );
+    assert_eq!(heatmap.bounds(), Some(((-32, 0), (-30, 1))));
+    assert_eq!(
+        generate_heatmap_csv(&heatmap),
+        "z\\x,-32,-31,-30\n0,0,,\n1,,,48\n"
+    );
+}
+
+#[test]
+fn test_heatmap_excluded_levels() {
+    use crate::fixtures::*;
+    // Ore on the bottom level, and on a level higher up.
+    let chunk = ChunkNbt::full(vec![SectionNbt::with_blocks(
+        0,
+        &["minecraft:stone", "minecraft:diamond_ore"],
+        |_, y, _| usize::from(y == 0 || y == 10),
+    )]);
+    let heatmap = |options: &ScanOptions| {
+        let mut region = region_with(&[((0, 0), chunk.to_bytes())]);
+        region_block_heatmap(
+            &mut region,
+            (0, 0),
+            "minecraft:diamond_ore",
+            "test:dim",
+            options,
+        )
+        .counts
+    };
+    assert_eq!(
+        heatmap(&ScanOptions::default()),
+        HashMap::from([((0, 0), 2 * 256)])
+    );
+    let options = ScanOptions {
+        excluded_levels: vec![0..=0],
+        ..Default::default()
+    };
+    assert_eq!(heatmap(&options), HashMap::from([((0, 0), 256)]));
+    let options = ScanOptions {
+        y_range: Some(1..=9),
+        ..Default::default()
+    };
+    assert_eq!(heatmap(&options), HashMap::from([((0, 0), 0)]));
+}
+
+#[test]
+fn test_sparse_heatmap() {
+    let mut heatmap = BlockHeatmap::empty("test:dim".to_owned(), "test:block".to_owned());
+    heatmap.counts = HashMap::from([((-5, 0), 1), ((-4, 0), 2), ((2_000_000, 3), 4)]);
+    // 2000006 chunks wide, so cells of 1956 chunks.
+    assert_eq!(heatmap.cell_size(), 1956);
+    let cells = heatmap.binned();
+    assert_eq!(cells.counts, HashMap::from([((-1, 0), 3), ((1022, 0), 4)]));
+    let csv = generate_heatmap_csv(&heatmap);
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines.len(), 2);
+    let header: Vec<_> = lines[0].split(',').collect();
+    assert_eq!(header.len(), 1 + 1024);
+    assert_eq!(header[1..3], ["-1956", "0"]);
+    assert!(lines[1].starts_with("0,3,,"));
+    assert!(lines[1].ends_with(",4"));
+}
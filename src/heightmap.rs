@@ -0,0 +1,290 @@
+//! Terrain surface heights, as recorded by the `MOTION_BLOCKING` heightmap of
+//! chunks.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io::{Read, Seek},
+};
+
+use fastanvil::Region;
+use fastnbt::LongArray;
+use itertools::Itertools;
+use serde::{de::IgnoredAny, Deserialize};
+
+use crate::{is_full_status, readable_chunks, ProtoOption};
+
+/// The first DataVersion (20w17a) where heightmap entries no longer span across
+/// two longs.
+const NON_SPANNING_DATA_VERSION: i32 = 2529;
+
+#[derive(Deserialize)]
+struct ChunkHeightmaps {
+    #[serde(rename = "DataVersion")]
+    data_version: Option<i32>,
+    #[serde(rename = "Status")]
+    status: Option<String>,
+    /// The lowest section of the chunk, since 1.18.
+    #[serde(rename = "yPos")]
+    y_pos: Option<i32>,
+    #[serde(rename = "Heightmaps")]
+    heightmaps: Option<Heightmaps>,
+    /// Only read since 1.18, when the sections moved out of the Level compound.
+    sections: Option<Vec<SectionY>>,
+    /// Before 1.18, everything but the DataVersion was in a Level compound.
+    #[serde(rename = "Level")]
+    level: Option<Box<ChunkHeightmaps>>,
+}
+
+#[derive(Deserialize)]
+struct SectionY {
+    #[serde(rename = "Y")]
+    y: i8,
+    block_states: Option<IgnoredAny>,
+}
+
+impl ChunkHeightmaps {
+    /// The height of the world the chunk is in. Since 1.18, it's spanned by the
+    /// sections with block states, as the ones just below and above the world
+    /// only hold light. Before that, worlds were always 256 blocks high.
+    fn world_height(&self) -> Option<usize> {
+        if self.y_pos.is_none() {
+            return Some(256);
+        }
+        let (min, max) = self
+            .sections
+            .iter()
+            .flatten()
+            .filter(|section| section.block_states.is_some())
+            .map(|section| section.y as isize)
+            .minmax()
+            .into_option()?;
+        Some(16 * (max - min + 1) as usize)
+    }
+}
+
+#[derive(Deserialize)]
+struct Heightmaps {
+    #[serde(rename = "MOTION_BLOCKING")]
+    motion_blocking: Option<LongArray>,
+}
+
+/// Unpacks the 256 entries of a heightmap. The number of bits per entry isn't
+/// stored, but is the fewest that fit any height from 0 to `height`, the height
+/// of the world. Before 20w17a, entries could span across two longs.
+pub fn unpack_heightmap(data: &[i64], height: usize, spanning: bool) -> Option<[u16; 256]> {
+    let bits = (height + 1).next_power_of_two().trailing_zeros() as usize;
+    if !(1..=16).contains(&bits) {
+        return None;
+    }
+    let longs = if spanning {
+        (256 * bits).div_ceil(64)
+    } else {
+        256usize.div_ceil(64 / bits)
+    };
+    if longs != data.len() {
+        return None;
+    }
+    let mask = (1u64 << bits) - 1;
+    let mut heights = [0u16; 256];
+    for (i, height) in heights.iter_mut().enumerate() {
+        let value = if spanning {
+            let start = i * bits;
+            let (long, offset) = (start / 64, start % 64);
+            let mut value = (data[long] as u64) >> offset;
+            if offset + bits > 64 {
+                value |= (data[long + 1] as u64) << (64 - offset);
+            }
+            value
+        } else {
+            let per_long = 64 / bits;
+            (data[i / per_long] as u64) >> ((i % per_long) * bits)
+        };
+        *height = (value & mask) as u16;
+    }
+    Some(heights)
+}
+
+/// Reads the Y of the highest motion-blocking block of every column of a chunk,
+/// along with whether the chunk is fully generated. Columns without any such
+/// blocks are left out. Pre-1.13 chunks have no `MOTION_BLOCKING` heightmap,
+/// and give `None`.
+pub fn chunk_surface_heights(data: &[u8]) -> Option<(Vec<isize>, bool)> {
+    let chunk: ChunkHeightmaps = fastnbt::from_bytes(data).ok()?;
+    let data_version = chunk.data_version.unwrap_or(0);
+    let chunk = match chunk.level {
+        Some(level) => *level,
+        None => chunk,
+    };
+    let height = chunk.world_height()?;
+    let heightmap = chunk.heightmaps?.motion_blocking?;
+    let heights = unpack_heightmap(&heightmap, height, data_version < NON_SPANNING_DATA_VERSION)?;
+    let min_y = chunk.y_pos.unwrap_or(0) as isize * 16;
+    let full = chunk.status.as_deref().is_none_or(is_full_status);
+    // Heights are stored as the number of blocks from the bottom of the world
+    // to the first free block above the surface.
+    let surface = heights
+        .iter()
+        .filter(|&&height| height > 0)
+        .map(|&height| min_y + height as isize - 1)
+        .collect();
+    Some((surface, full))
+}
+
+/// The distribution of surface heights in a dimension.
+pub struct SurfaceHeights {
+    pub dimension: String,
+    /// The number of columns with their surface at each Y.
+    pub columns: BTreeMap<isize, u64>,
+    pub chunks_counted: usize,
+}
+
+impl SurfaceHeights {
+    pub fn empty(dimension: String) -> Self {
+        Self {
+            dimension,
+            columns: BTreeMap::new(),
+            chunks_counted: 0,
+        }
+    }
+
+    pub fn merge(&mut self, other: SurfaceHeights) {
+        for (y, columns) in other.columns {
+            *self.columns.entry(y).or_default() += columns;
+        }
+        self.chunks_counted += other.chunks_counted;
+    }
+}
+
+/// Adds up the surface heights of the chunks in a region that pass the
+/// protochunk filter.
+pub fn region_surface_heights<R: Read + Seek>(
+    region: &mut Region<R>,
+    proto: ProtoOption,
+    dimension: &str,
+) -> SurfaceHeights {
+    let mut heights = SurfaceHeights::empty(dimension.to_owned());
+    for data in readable_chunks(region) {
+        let Some((surface, full)) = chunk_surface_heights(&data.data) else {
+            continue;
+        };
+        if proto.accepts(full) {
+            for y in surface {
+                *heights.columns.entry(y).or_default() += 1;
+            }
+            heights.chunks_counted += 1;
+        }
+    }
+    heights
+}
+
+/// Generates a CSV with a row per dimension and surface Y, holding the number
+/// of columns with their surface there and their share of the dimension's.
+pub fn generate_surface_heights_csv(heights: &[SurfaceHeights]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,surface_y,columns,share\n").unwrap();
+    for dim_heights in heights {
+        let total: u64 = dim_heights.columns.values().sum();
+        for (y, columns) in &dim_heights.columns {
+            writeln!(
+                res,
+                "{},{},{},{}",
+                dim_heights.dimension,
+                y,
+                columns,
+                *columns as f64 / total as f64
+            )
+            .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+#[test]
+fn test_unpack_heightmap() {
+    // 9 bits per entry, as in worlds 256 or 384 blocks high.
+    let heights: Vec<u64> = (0..256).map(|i| (i * 37 % 385) as u64).collect();
+    let mut packed = vec![0i64; 37];
+    for (i, height) in heights.iter().enumerate() {
+        packed[i / 7] |= (height << ((i % 7) * 9)) as i64;
+    }
+    let unpacked = unpack_heightmap(&packed, 384, false).unwrap();
+    assert!(unpacked.iter().zip(&heights).all(|(a, b)| *a as u64 == *b));
+
+    let mut packed = vec![0u64; 36];
+    for (i, height) in heights.iter().enumerate() {
+        let (long, offset) = (i * 9 / 64, i * 9 % 64);
+        packed[long] |= height << offset;
+        if offset + 9 > 64 {
+            packed[long + 1] |= height >> (64 - offset);
+        }
+    }
+    let packed: Vec<i64> = packed.into_iter().map(|long| long as i64).collect();
+    let unpacked = unpack_heightmap(&packed, 384, true).unwrap();
+    assert!(unpacked.iter().zip(&heights).all(|(a, b)| *a as u64 == *b));
+
+    assert!(unpack_heightmap(&[0; 5], 384, false).is_none());
+
+    // 12 bits per entry take up 52 longs, just like 11 bits would, so only the
+    // height of the world tells them apart.
+    let heights: Vec<u64> = (0..256).map(|i| (i * 997 % 4065) as u64).collect();
+    let mut packed = vec![0i64; 52];
+    for (i, height) in heights.iter().enumerate() {
+        packed[i / 5] |= (height << ((i % 5) * 12)) as i64;
+    }
+    let unpacked = unpack_heightmap(&packed, 4064, false).unwrap();
+    assert!(unpacked.iter().zip(&heights).all(|(a, b)| *a as u64 == *b));
+    assert!(heights.iter().any(|&height| height >= 1 << 11));
+}
+
+#[test]
+fn test_chunk_surface_heights() {
+    #[derive(serde::Serialize)]
+    struct ChunkNbt {
+        #[serde(rename = "DataVersion")]
+        data_version: i32,
+        #[serde(rename = "Status")]
+        status: &'static str,
+        #[serde(rename = "yPos")]
+        y_pos: i32,
+        sections: Vec<SectionNbt>,
+        #[serde(rename = "Heightmaps")]
+        heightmaps: HeightmapsNbt,
+    }
+    #[derive(serde::Serialize)]
+    struct SectionNbt {
+        #[serde(rename = "Y")]
+        y: i8,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        block_states: Option<BTreeMap<String, String>>,
+    }
+    #[derive(serde::Serialize)]
+    struct HeightmapsNbt {
+        #[serde(rename = "MOTION_BLOCKING")]
+        motion_blocking: LongArray,
+    }
+
+    // A world 4064 blocks high, with a section only holding light just below
+    // and above it, so its heightmap takes 12 bits per entry.
+    let sections = (-128..=127)
+        .map(|y| SectionNbt {
+            y,
+            block_states: (-127..=126).contains(&y).then(BTreeMap::new),
+        })
+        .collect();
+    let mut heightmap = vec![0; 52];
+    heightmap[0] = 4000 | (1 << 12);
+    let chunk = fastnbt::to_bytes(&ChunkNbt {
+        data_version: 3953,
+        status: "minecraft:full",
+        y_pos: -127,
+        sections,
+        heightmaps: HeightmapsNbt {
+            motion_blocking: LongArray::new(heightmap),
+        },
+    })
+    .unwrap();
+    let (surface, full) = chunk_surface_heights(&chunk).unwrap();
+    assert_eq!(surface, vec![-127 * 16 + 3999, -127 * 16]);
+    assert!(full);
+}
@@ -0,0 +1,205 @@
+//! How the number of a block per chunk varies, which the averaged frequencies
+//! hide.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io::{Read, Seek},
+};
+
+use fastanvil::{Chunk, JavaChunk, Region};
+
+use crate::{
+    catch_chunk_panic, has_complete_block_data, is_full_status, normalize_block_name, parse_chunk,
+    readable_chunks, ScanOptions, MAX_WORLD_HEIGHT,
+};
+
+/// The number of chunks with each count of a block.
+pub struct ChunkHistogram {
+    pub dimension: String,
+    pub block: String,
+    /// The number of chunks in which the block occurs each number of times.
+    pub chunks: BTreeMap<u64, usize>,
+    pub chunks_counted: usize,
+}
+
+impl ChunkHistogram {
+    pub fn empty(dimension: String, block: String) -> Self {
+        Self {
+            dimension,
+            block,
+            chunks: BTreeMap::new(),
+            chunks_counted: 0,
+        }
+    }
+
+    pub fn merge(&mut self, other: ChunkHistogram) {
+        for (count, chunks) in other.chunks {
+            *self.chunks.entry(count).or_default() += chunks;
+        }
+        self.chunks_counted += other.chunks_counted;
+    }
+}
+
+/// The number of times a block occurs in a chunk of `dimension`, on the levels
+/// `options` doesn't exclude. `None` if fastanvil panics on the data of the
+/// chunk, see [`catch_chunk_panic`].
+pub fn chunk_block_count(
+    chunk: &JavaChunk,
+    block: &str,
+    dimension: &str,
+    options: &ScanOptions,
+) -> Option<u64> {
+    catch_chunk_panic(|| {
+        let mut count = 0;
+        let levels = options
+            .clamp_levels(chunk.y_range())
+            .filter(|&y| !options.is_level_excluded(dimension, y));
+        for (y, z, x) in itertools::iproduct!(levels, 0..16, 0..16) {
+            let Some(found) = chunk.block(x, y, z) else {
+                continue;
+            };
+            let matches = if options.normalize_names {
+                normalize_block_name(found.name()) == block
+            } else {
+                found.name() == block
+            };
+            count += u64::from(matches);
+        }
+        count
+    })
+}
+
+/// Parses a chunk, if it passes the status and InhabitedTime filters of
+/// `options` and has all of its blocks, so that a block can be counted in it.
+pub(crate) fn countable_chunk(data: &[u8], options: &ScanOptions) -> Option<JavaChunk> {
+    let chunk = parse_chunk(data).ok()?;
+    let status = chunk.status();
+    (options.accepts_status(&status, is_full_status(&status))
+        && options.is_inhabited_enough(data)
+        && chunk.y_range().len() <= MAX_WORLD_HEIGHT
+        && has_complete_block_data(&chunk))
+    .then_some(chunk)
+}
+
+/// Counts a block in every chunk of a region that passes the status and
+/// InhabitedTime filters of `options`, including the chunks without any.
+pub fn region_chunk_histogram<R: Read + Seek>(
+    region: &mut Region<R>,
+    block: &str,
+    dimension: &str,
+    options: &ScanOptions,
+) -> ChunkHistogram {
+    let mut histogram = ChunkHistogram::empty(dimension.to_owned(), block.to_owned());
+    for data in readable_chunks(region) {
+        let Some(chunk) = countable_chunk(&data.data, options) else {
+            continue;
+        };
+        let Some(count) = chunk_block_count(&chunk, block, dimension, options) else {
+            soft_warn!(
+                "Reading chunk ({}, {}) of a region in {dimension} failed, its data is probably \
+                 corrupt. Skipping it.",
+                data.x,
+                data.z
+            );
+            continue;
+        };
+        *histogram.chunks.entry(count).or_default() += 1;
+        histogram.chunks_counted += 1;
+    }
+    histogram
+}
+
+/// Generates a CSV with a row per dimension and number of the block per chunk
+/// that occurs, with the number of such chunks and their share.
+pub fn generate_chunk_histogram_csv(histograms: &[ChunkHistogram]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block,count_per_chunk,chunks,share\n")
+        .unwrap();
+    for histogram in histograms {
+        for (count, chunks) in &histogram.chunks {
+            writeln!(
+                res,
+                "{},{},{},{},{}",
+                histogram.dimension,
+                histogram.block,
+                count,
+                chunks,
+                *chunks as f64 / histogram.chunks_counted as f64
+            )
+            .expect("Error when assembling CSV");
+        }
+    }
+    res
+}
+
+#[test]
+fn test_region_chunk_histogram() {
+    use crate::fixtures::*;
+    let with_ores = |ores: usize| {
+        ChunkNbt::full(vec![SectionNbt::with_blocks(
+            0,
+            &["minecraft:stone", "minecraft:diamond_ore"],
+            move |x, _, _| usize::from(x < ores),
+        )])
+        .to_bytes()
+    };
+    let mut region = region_with(&[
+        ((0, 0), with_ores(0)),
+        ((1, 0), with_ores(1)),
+        ((2, 0), with_ores(1)),
+    ]);
+    let histogram = region_chunk_histogram(
+        &mut region,
+        "minecraft:diamond_ore",
+        "test:dim",
+        &ScanOptions::default(),
+    );
+    assert_eq!(histogram.chunks_counted, 3);
+    // One ore column is 16 by 16 blocks.
+    assert_eq!(histogram.chunks, BTreeMap::from([(0, 1), (256, 2)]));
+}
+
+#[test]
+fn test_chunk_block_count_levels() {
+    use crate::fixtures::*;
+    // A diamond ore layer on every fourth level.
+    let chunk = ChunkNbt::full(vec![SectionNbt::with_blocks(
+        0,
+        &["minecraft:stone", "minecraft:diamond_ore"],
+        |_, y, _| usize::from(y % 4 == 0),
+    )]);
+    let count = |options: &ScanOptions| {
+        let mut region = region_with(&[((0, 0), chunk.to_bytes())]);
+        let histogram =
+            region_chunk_histogram(&mut region, "minecraft:diamond_ore", "test:dim", options);
+        histogram.chunks.into_keys().collect::<Vec<_>>()
+    };
+    assert_eq!(count(&ScanOptions::default()), [4 * 256]);
+    let options = ScanOptions {
+        y_range: Some(2..=9),
+        ..Default::default()
+    };
+    assert_eq!(count(&options), [2 * 256]);
+    let options = ScanOptions {
+        excluded_levels: vec![0..=0, 12..=20],
+        ..Default::default()
+    };
+    assert_eq!(count(&options), [2 * 256]);
+
+    // Block data too short for its palette makes fastanvil panic.
+    let mut corrupt = ChunkNbt::full(vec![SectionNbt::with_blocks(
+        0,
+        &["minecraft:stone", "minecraft:diamond_ore"],
+        |_, _, _| 0,
+    )]);
+    corrupt.sections[0].block_states.data = Some(fastnbt::LongArray::new(vec![0; 128]));
+    let corrupt = parse_chunk(&corrupt.to_bytes()).unwrap();
+    let count = chunk_block_count(
+        &corrupt,
+        "minecraft:diamond_ore",
+        "test:dim",
+        &ScanOptions::default(),
+    );
+    assert_eq!(count, None);
+}
@@ -1,82 +1,1385 @@
+/// Like `warn!`, but for the non-fatal warnings that can be turned off with
+/// [`silence_warnings`].
+#[macro_export]
+macro_rules! soft_warn {
+    ($($arg:tt)+) => {
+        if !$crate::warnings_silenced() {
+            ::log::warn!($($arg)+)
+        }
+    };
+}
+
+mod activity;
+#[cfg(feature = "archive")]
+mod archive;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod biomes;
+mod block_entities;
+mod block_meta;
+#[cfg(feature = "charts")]
+mod charts;
+mod diff;
+mod entities;
+mod error;
+mod export;
+mod exposure;
+#[cfg(test)]
+mod fixtures;
+mod heatmap;
+mod heightmap;
+mod histogram;
+#[cfg(feature = "linear")]
+mod linear;
+mod polygon;
+mod report;
+mod scan;
+mod source;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 mod utils;
+mod veins;
 
+pub use activity::*;
+#[cfg(feature = "archive")]
+pub use archive::*;
+#[cfg(feature = "arrow")]
+pub use arrow::generate_arrow;
+pub use biomes::*;
+pub use block_entities::*;
+pub use block_meta::{BlockMeta, BlockMetaTable, BLOCK_META_HEADER};
+#[cfg(feature = "charts")]
+pub use charts::{generate_block_plots, generate_charts, generate_heatmap_png};
+pub use diff::*;
+pub use entities::*;
+pub use error::RegionScannerError;
+pub use export::*;
+pub use exposure::{is_air, AIR_BLOCKS};
+pub use heatmap::*;
+pub use heightmap::*;
+pub use histogram::*;
+#[cfg(feature = "linear")]
+pub use linear::*;
+pub use polygon::Polygon;
+pub use report::generate_html_report;
+pub use scan::*;
+pub use source::*;
+#[cfg(feature = "sqlite")]
+pub use sqlite::generate_sqlite;
 use std::{
-    collections::{hash_map::Entry, HashMap, HashSet},
+    borrow::Cow,
+    cell::Cell,
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Write,
-    fs::File,
+    io::{Read, Seek},
+    num::NonZeroUsize,
+    ops::{Range, RangeInclusive},
+    panic::AssertUnwindSafe,
     path::{Path, PathBuf},
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, Once,
+    },
+    time::Duration,
+};
+#[cfg(feature = "mmap")]
+pub use utils::mmap_region;
+pub use utils::{
+    compression_name, compression_types, decompress_lz4_chunk, open_region, readable_region,
+    RegionStream, LZ4_COMPRESSION,
 };
+pub use veins::*;
 
+use exposure::*;
 use utils::*;
 #[macro_use]
 extern crate log;
-use fastanvil::{Chunk, JavaChunk, RCoord, Region, RegionFileLoader, RegionLoader};
-use itertools::iproduct;
+use fastanvil::{Block, Chunk, ChunkData, JavaChunk, RCoord, Region};
+use itertools::{iproduct, Itertools};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
-pub fn count_blocks(
-    region: &mut Region<File>,
+pub fn count_blocks<R: Read + Seek>(
+    region: &mut Region<R>,
+    region_pos: (isize, isize),
     verbose: bool,
     dimension: &str,
-    proto: ProtoOption,
+    options: &ScanOptions,
 ) -> BlockCounts {
-    let mut chunks_counted = 0;
-    let mut protochunks_seen = 0;
-    let mut blocks_counted: u64 = 0;
-    let mut counts: HashMap<String, HashMap<isize, u64>> = HashMap::new();
-    let mut closure = |xpos: usize, zpos: usize, chunk_processed: JavaChunk| {
-        if verbose && chunks_counted % 100 == 0 {
+    let count = |totals: &mut ChunkTotals,
+                 chunk: &JavaChunk,
+                 xpos: usize,
+                 zpos: usize,
+                 window: Option<&ChunkWindow>| {
+        if verbose && totals.chunks_counted.is_multiple_of(100) {
             info!(
                 "Handling chunk number {} at position ({},{})",
-                chunks_counted + 1,
+                totals.chunks_counted + 1,
                 xpos,
                 zpos
             );
         }
-        // The block data is stored in sections by y, so we iterate by y least often.
-        // Inside a section, x is the fastest-changing index. Hence, order yzx.
-        for (y, z, x) in iproduct!(chunk_processed.y_range(), 0..16, 0..16) {
-            if let Some(block) = chunk_processed.block(x, y, z) {
-                let block_entry = counts.entry(block.name().to_string());
-                let count_entry = block_entry.or_default().entry(y).or_insert(0);
-                *count_entry += 1;
+        let counted =
+            count_accepted_chunk(chunk, (xpos, zpos), window, region_pos, dimension, options);
+        totals.add(chunk, (xpos, zpos), counted, verbose, dimension);
+    };
+
+    // Neighbouring chunks have to be at hand for --exposed-only, so those
+    // scans always go one chunk at a time.
+    let (totals, tally) = if options.parallel_chunks && !options.exposed_only {
+        let (accepted, tally) = accepted_chunks(region, options);
+        let totals = accepted
+            .par_iter()
+            .fold(ChunkTotals::default, |mut totals, (chunk, xpos, zpos)| {
+                count(&mut totals, chunk, *xpos, *zpos, None);
+                totals
+            })
+            .reduce(ChunkTotals::default, ChunkTotals::merge);
+        (totals, tally)
+    } else {
+        let mut totals = ChunkTotals::default();
+        let tally = for_each_chunk(region, options, |chunk, data, window| {
+            count(&mut totals, chunk, data.x, data.z, window)
+        });
+        (totals, tally)
+    };
+    BlockCounts {
+        counts: totals.counts,
+        blocks_counted: totals.blocks_counted,
+        columns_counted: totals.columns_counted,
+        chunks_counted: totals.chunks_counted,
+        chunks_failed: totals.chunks_failed + tally.unreadable,
+        protochunks_seen: tally.protochunks_seen,
+        statuses: tally.statuses,
+        min_y: totals.min_y,
+        max_y: totals.max_y,
+        version: totals.version,
+        dimension: dimension.to_string(),
+    }
+}
+
+/// What counting the blocks of a single chunk came to.
+enum ChunkCount<'a> {
+    /// The chunk's data is corrupt, so it was skipped.
+    Failed,
+    /// None of the chunk is inside [`ScanOptions::polygon`].
+    OutsidePolygon,
+    Counted {
+        counts: HashMap<Cow<'a, str>, HashMap<isize, u64>>,
+        blocks: u64,
+        columns: u64,
+    },
+}
+
+/// Counts a chunk that passed the filters of [`for_each_chunk`], checking
+/// first that its data can be read. `pos` is its region-relative position.
+fn count_accepted_chunk<'a>(
+    chunk: &'a JavaChunk,
+    pos: (usize, usize),
+    window: Option<&ChunkWindow>,
+    region_pos: (isize, isize),
+    dimension: &str,
+    options: &ScanOptions,
+) -> ChunkCount<'a> {
+    let (xpos, zpos) = pos;
+    let y_range = chunk.y_range();
+    if y_range.len() > MAX_WORLD_HEIGHT {
+        soft_warn!(
+            "Chunk ({xpos}, {zpos}) of a region in {dimension} claims to span y={} to y={}, \
+             more than a world can be tall. Its data is probably corrupt, skipping it.",
+            y_range.start,
+            y_range.end - 1
+        );
+        return ChunkCount::Failed;
+    }
+    // Which columns are inside the polygon, if there's one.
+    let columns = options.polygon.as_ref().map(|polygon| {
+        let chunk_x = (region_pos.0 * 32 + xpos as isize) * 16;
+        let chunk_z = (region_pos.1 * 32 + zpos as isize) * 16;
+        let mut columns = [false; 256];
+        for (z, x) in iproduct!(0..16, 0..16) {
+            columns[z * 16 + x] = polygon.contains(chunk_x + x as isize, chunk_z + z as isize);
+        }
+        columns
+    });
+    let column_count = columns.map_or(256, |columns| columns.iter().filter(|&&c| c).count());
+    if column_count == 0 {
+        return ChunkCount::OutsidePolygon;
+    }
+    // The chunk is counted on its own first, so that if fastanvil panics on
+    // its data, e.g. block data shorter than its palette calls for, none of it
+    // ends up in the counts.
+    let counted = catch_chunk_panic(|| {
+        count_chunk(
+            chunk,
+            columns.as_ref(),
+            window.map(|window| (window, pos)),
+            dimension,
+            options,
+        )
+    });
+    let Some((counts, blocks)) = counted else {
+        soft_warn!(
+            "Reading chunk ({xpos}, {zpos}) of region ({}, {}) in {dimension} failed, its data \
+             is probably corrupt. Skipping it.",
+            region_pos.0,
+            region_pos.1
+        );
+        return ChunkCount::Failed;
+    };
+    ChunkCount::Counted {
+        counts,
+        blocks,
+        columns: column_count as u64,
+    }
+}
+
+/// The counts of some of the chunks of a region, which [`count_blocks`] adds
+/// up either one chunk at a time or, with [`ScanOptions::parallel_chunks`],
+/// merged from the ones of several threads.
+#[derive(Default)]
+struct ChunkTotals {
+    counts: HashMap<String, HashMap<isize, u64>>,
+    blocks_counted: u64,
+    columns_counted: u64,
+    chunks_counted: usize,
+    chunks_failed: usize,
+    min_y: Option<isize>,
+    max_y: Option<isize>,
+    version: Option<RegionVersion>,
+}
+
+impl ChunkTotals {
+    fn add(
+        &mut self,
+        chunk: &JavaChunk,
+        pos: (usize, usize),
+        counted: ChunkCount,
+        verbose: bool,
+        dimension: &str,
+    ) {
+        let (counts, blocks, columns) = match counted {
+            ChunkCount::Failed => {
+                self.chunks_failed += 1;
+                return;
+            }
+            ChunkCount::OutsidePolygon => return,
+            ChunkCount::Counted {
+                counts,
+                blocks,
+                columns,
+            } => (counts, blocks, columns),
+        };
+        for (name, levels) in counts {
+            // The name is only copied for blocks not seen in the region yet.
+            let block_counts = match self.counts.get_mut(name.as_ref()) {
+                Some(block_counts) => block_counts,
+                None => {
+                    if verbose {
+                        let y = *levels.keys().min().expect("counted blocks have a level");
+                        log_first_occurrence(&name, dimension, (pos.0, y, pos.1));
+                    }
+                    self.counts.entry(name.into_owned()).or_default()
+                }
+            };
+            for (y, count) in levels {
+                *block_counts.entry(y).or_insert(0) += count;
+            }
+        }
+        self.version.get_or_insert_with(|| chunk_version(chunk));
+        let y_range = chunk.y_range();
+        if !y_range.is_empty() {
+            self.min_y = Some(
+                self.min_y
+                    .map_or(y_range.start, |min_y| min_y.min(y_range.start)),
+            );
+            let top = y_range.end - 1;
+            self.max_y = Some(self.max_y.map_or(top, |max_y| max_y.max(top)));
+        }
+        self.blocks_counted += blocks;
+        self.chunks_counted += 1;
+        self.columns_counted += columns;
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        for (name, levels) in other.counts {
+            let block_counts = self.counts.entry(name).or_default();
+            for (y, count) in levels {
+                *block_counts.entry(y).or_insert(0) += count;
             }
-            blocks_counted += 1;
         }
-        chunks_counted += 1;
+        self.blocks_counted += other.blocks_counted;
+        self.columns_counted += other.columns_counted;
+        self.chunks_counted += other.chunks_counted;
+        self.chunks_failed += other.chunks_failed;
+        self.min_y = self.min_y.into_iter().chain(other.min_y).min();
+        self.max_y = self.max_y.into_iter().chain(other.max_y).max();
+        self.version = self.version.or(other.version);
+        self
+    }
+}
+
+/// What [`for_each_chunk`] found out about the chunks of a region, including
+/// the ones it skipped.
+#[derive(Default)]
+struct ChunkTally {
+    protochunks_seen: usize,
+    statuses: BTreeMap<String, usize>,
+    /// The chunks that couldn't be read from the region file at all.
+    unreadable: usize,
+}
+
+impl ChunkTally {
+    /// The data of a chunk if it could be read, or else warns about it and
+    /// tallies it as unreadable.
+    fn readable(
+        unreadable: &mut usize,
+        chunk: Result<ChunkData, UnreadableChunk>,
+    ) -> Option<ChunkData> {
+        chunk
+            .inspect_err(|chunk| {
+                chunk.warn();
+                *unreadable += 1;
+            })
+            .ok()
+    }
+
+    /// Whether a chunk, given as its NBT and as parsed (if it parsed), passes
+    /// the status and InhabitedTime filters of `options`, tallying its status.
+    fn accepts(&mut self, options: &ScanOptions, data: &[u8], chunk: Option<&JavaChunk>) -> bool {
+        // This skips chunks that fail to deserialise.
+        let Some(c) = chunk else {
+            warn_if_newer_than_supported(data);
+            *self
+                .statuses
+                .entry(UNPARSEABLE_STATUS.to_owned())
+                .or_default() += 1;
+            return false;
+        };
+        // See https://minecraft.wiki/w/Chunk_format
+        let status = c.status();
+        let full = is_full_status(&status);
+        if !full {
+            self.protochunks_seen += 1;
+        }
+        let accepted = options.accepts_status(&status, full) && options.is_inhabited_enough(data);
+        *self.statuses.entry(status).or_default() += 1;
+        accepted
+    }
+}
+
+/// Calls `f` with every chunk of a region that parses and passes the status
+/// and InhabitedTime filters of `options`, along with its raw data (which holds
+/// its region-relative position). With [`ScanOptions::exposed_only`], `f` also
+/// gets the neighbouring chunks.
+fn for_each_chunk<R: Read + Seek>(
+    region: &mut Region<R>,
+    options: &ScanOptions,
+    mut f: impl FnMut(&JavaChunk, &ChunkData, Option<&ChunkWindow>),
+) -> ChunkTally {
+    let mut tally = ChunkTally::default();
+    if options.exposed_only {
+        // Rows of chunks are counted once the next one is read, since the
+        // blocks at their edges need it for their neighbours.
+        let mut window = ChunkWindow::default();
+        let mut unreadable = 0;
+        let mut all_chunks = chunks(region)
+            .flatten()
+            .filter_map(|chunk| ChunkTally::readable(&mut unreadable, chunk))
+            .peekable();
+        for z in 0..=32 {
+            while let Some(data) = all_chunks.next_if(|data| data.z == z) {
+                window.insert(data);
+            }
+            let Some(row) = z.checked_sub(1) else {
+                continue;
+            };
+            window.retain_rows_from(row.saturating_sub(1));
+            for (data, c) in window.row(row) {
+                if tally.accepts(options, &data.data, c.as_ref()) {
+                    f(c.as_ref().unwrap(), data, Some(&window));
+                }
+            }
+        }
+        drop(all_chunks);
+        tally.unreadable = unreadable;
+    } else {
+        for chunk in chunks(region).flatten() {
+            let Some(data) = ChunkTally::readable(&mut tally.unreadable, chunk) else {
+                continue;
+            };
+            let c = parse_chunk(&data.data).ok();
+            if tally.accepts(options, &data.data, c.as_ref()) {
+                f(c.as_ref().unwrap(), &data, None);
+            }
+        }
+    }
+    tally
+}
+
+/// Like [`for_each_chunk`] without [`ScanOptions::exposed_only`], but collects
+/// the accepted chunks along with their positions, parsing them in parallel.
+fn accepted_chunks<R: Read + Seek>(
+    region: &mut Region<R>,
+    options: &ScanOptions,
+) -> (Vec<(JavaChunk, usize, usize)>, ChunkTally) {
+    let mut tally = ChunkTally::default();
+    let all_chunks: Vec<_> = chunks(region)
+        .flatten()
+        .filter_map(|chunk| ChunkTally::readable(&mut tally.unreadable, chunk))
+        .collect();
+    let parsed: Vec<_> = all_chunks
+        .par_iter()
+        .map(|data| parse_chunk(&data.data).ok())
+        .collect();
+    let mut accepted = vec![];
+    for (data, c) in all_chunks.iter().zip(parsed) {
+        if tally.accepts(options, &data.data, c.as_ref()) {
+            accepted.push((c.unwrap(), data.x, data.z));
+        }
+    }
+    (accepted, tally)
+}
+
+/// Calls `f` with every chunk of a region that `proto` accepts, along with its
+/// region-relative position (x, z), for aggregations of one's own on top of the
+/// chunk iteration of [`count_blocks`]. Chunks that fail to parse are skipped.
+pub fn scan_region_with<R: Read + Seek, F: FnMut(&JavaChunk, usize, usize)>(
+    region: &mut Region<R>,
+    proto: ProtoOption,
+    mut f: F,
+) {
+    let options = ScanOptions {
+        proto,
+        ..Default::default()
     };
+    for_each_chunk(region, &options, |chunk, data, _| f(chunk, data.x, data.z));
+}
 
-    for data in chunks(region).flatten() {
-        use ProtoOption::*;
-        // This silently skips chunks that fail to deserialise.
-        if let Ok(c) = JavaChunk::from_bytes(&data.data) {
-            // See https://minecraft.wiki/w/Chunk_format
-            if c.status() != "minecraft:full" {
-                protochunks_seen += 1;
-                if proto == Skip {
+/// Counts the blocks of a single chunk, by name and level, along with the
+/// number of blocks looked at. `columns` are the columns inside the polygon,
+/// if any, and `window` holds the neighbours of the chunk at the given position
+/// for telling which blocks are exposed.
+fn count_chunk<'a>(
+    chunk: &'a JavaChunk,
+    columns: Option<&[bool; 256]>,
+    window: Option<(&ChunkWindow, (usize, usize))>,
+    dimension: &str,
+    options: &ScanOptions,
+) -> (HashMap<Cow<'a, str>, HashMap<isize, u64>>, u64) {
+    // fastanvil hands out the blocks from the palettes of the sections, so the
+    // name of a block (and whether it's counted) is only worked out the first
+    // time its palette entry comes up. After that, blocks are tallied by the
+    // index of their name, and the names are only hashed once per chunk.
+    let mut ids: HashMap<*const Block, Option<usize>> = HashMap::new();
+    let mut name_ids: HashMap<Cow<str>, usize> = HashMap::new();
+    let mut by_level: Vec<HashMap<isize, u64>> = vec![];
+    let mut level_counts: Vec<u64> = vec![];
+    let mut blocks_counted = 0;
+    // The block data is stored in sections by y, so we iterate by y least often.
+    // Inside a section, x is the fastest-changing index. Hence, order yzx.
+    let levels = options
+        .clamp_levels(chunk.y_range())
+        .filter(|&y| !options.is_level_excluded(dimension, y));
+    for y in levels {
+        for (z, x) in iproduct!(0..16, 0..16) {
+            if columns.is_some_and(|columns| !columns[z * 16 + x]) {
+                continue;
+            }
+            blocks_counted += 1;
+            if let Some((window, pos)) = window {
+                if !is_exposed(window, chunk, pos, (x, y, z)) {
                     continue;
                 }
             }
-            // otherwise it's a full chunk
-            else if proto == OnlyProto {
+            let Some(block) = chunk.block(x, y, z) else {
                 continue;
+            };
+            let id = *ids.entry(block as *const Block).or_insert_with(|| {
+                let name = counted_name(block, options)?;
+                let next_id = name_ids.len();
+                Some(*name_ids.entry(name).or_insert(next_id))
+            });
+            if let Some(id) = id {
+                if id >= level_counts.len() {
+                    level_counts.resize(id + 1, 0);
+                    by_level.resize_with(id + 1, HashMap::new);
+                }
+                level_counts[id] += 1;
+            }
+        }
+        for (id, count) in level_counts.iter_mut().enumerate() {
+            if *count > 0 {
+                by_level[id].insert(y, std::mem::take(count));
             }
-            closure(data.x, data.z, c);
         }
     }
-    BlockCounts {
-        counts,
-        blocks_counted,
-        chunks_counted,
-        protochunks_seen,
-        dimension: dimension.to_string(),
+    // Every name has been counted at least once, as it's only given an index
+    // when a block with it is counted.
+    let names = name_ids
+        .into_iter()
+        .sorted_unstable_by_key(|&(_, id)| id)
+        .map(|(name, _)| name);
+    let counts = names.zip(by_level).collect();
+    (counts, blocks_counted)
+}
+
+/// The name a block is counted under with the naming options of `options`, or
+/// `None` if the whitelist leaves it out.
+fn counted_name<'a>(block: &'a Block, options: &ScanOptions) -> Option<Cow<'a, str>> {
+    let mut name = if options.track_properties {
+        Cow::Owned(block_state_name(block))
+    } else {
+        Cow::Borrowed(block.name())
+    };
+    if options.normalize_names {
+        if let Cow::Owned(normalized) = normalize_block_name(&name) {
+            name = Cow::Owned(normalized);
+        }
+    }
+    options
+        .is_counted(strip_block_properties(&name))
+        .then_some(name)
+}
+
+/// The tallest a world can be according to the dimension type format, from
+/// y=-2032 to y=2031. Chunks taller than that are corrupt.
+pub const MAX_WORLD_HEIGHT: usize = 4064;
+
+/// Whether looking up blocks stays within the block data of a chunk. A block
+/// state array shorter than its palette calls for makes fastanvil index past
+/// its end and panic, so the last block of every section, which is the
+/// furthest into the array, is looked up with the panic caught.
+pub fn has_complete_block_data(chunk: &JavaChunk) -> bool {
+    let y_range = chunk.y_range();
+    catch_chunk_panic(|| {
+        for y in y_range.step_by(16) {
+            chunk.block(15, y + 15, 15);
+        }
+    })
+    .is_some()
+}
+
+thread_local! {
+    /// How many calls of [`catch_chunk_panic`] the current thread is in.
+    static CATCHING_CHUNK_PANICS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Reads a chunk with `read`, or returns `None` if fastanvil panics on its
+/// data, as it does on some corrupt chunks. This is the one place such panics
+/// are caught. The panic hook doesn't print them, as the callers warn about the
+/// chunk themselves (unless warnings are silenced); they're logged at the debug
+/// level instead.
+pub fn catch_chunk_panic<T>(read: impl FnOnce() -> T) -> Option<T> {
+    static QUIET_HOOK: Once = Once::new();
+    QUIET_HOOK.call_once(|| {
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if CATCHING_CHUNK_PANICS.get() > 0 {
+                debug!("Reading a chunk panicked: {info}");
+            } else {
+                hook(info);
+            }
+        }));
+    });
+    CATCHING_CHUNK_PANICS.set(CATCHING_CHUNK_PANICS.get() + 1);
+    let res = std::panic::catch_unwind(AssertUnwindSafe(read));
+    CATCHING_CHUNK_PANICS.set(CATCHING_CHUNK_PANICS.get() - 1);
+    res.ok()
+}
+
+/// The key under which [`BlockCounts::statuses`] tallies the chunks that failed
+/// to parse.
+pub const UNPARSEABLE_STATUS: &str = "<unparseable>";
+
+/// The most block names [`log_first_occurrence`] logs, so that modded worlds
+/// with thousands of blocks don't flood the log.
+const MAX_LOGGED_BLOCK_NAMES: usize = 500;
+
+/// Logs the first time a block name is encountered in a dimension, to tell
+/// during a scan what the world actually contains. `pos` is the region-relative
+/// chunk position and the Y of the block.
+fn log_first_occurrence(name: &str, dimension: &str, pos: (usize, isize, usize)) {
+    static SEEN: Mutex<Option<HashSet<(String, String)>>> = Mutex::new(None);
+    let mut seen = SEEN.lock().unwrap();
+    let seen = seen.get_or_insert_with(HashSet::new);
+    if seen.len() >= MAX_LOGGED_BLOCK_NAMES {
+        return;
+    }
+    if seen.insert((dimension.to_owned(), name.to_owned())) {
+        let (x, y, z) = pos;
+        info!("Found {name} in {dimension} for the first time, at y={y} in chunk ({x},{z}).");
+        if seen.len() == MAX_LOGGED_BLOCK_NAMES {
+            info!("Logged {MAX_LOGGED_BLOCK_NAMES} kinds of blocks, not logging any further ones.");
+        }
+    }
+}
+
+static WARNINGS_SILENCED: AtomicBool = AtomicBool::new(false);
+
+/// Turns the non-fatal warnings (failed regions and chunks, height limits and
+/// the like) off or on, for use in scripts where they're noise.
+pub fn silence_warnings(silenced: bool) {
+    WARNINGS_SILENCED.store(silenced, Ordering::Relaxed);
+}
+
+pub fn warnings_silenced() -> bool {
+    WARNINGS_SILENCED.load(Ordering::Relaxed)
+}
+
+/// Lowercases a block name and adds the `minecraft:` namespace if it has none,
+/// so that inconsistently written IDs of the same block are counted together.
+pub fn normalize_block_name(name: &str) -> Cow<'_, str> {
+    let has_namespace = name.contains(':');
+    if has_namespace && !name.chars().any(|c| c.is_uppercase()) {
+        return Cow::Borrowed(name);
+    }
+    let name = name.to_lowercase();
+    if has_namespace {
+        Cow::Owned(name)
+    } else {
+        Cow::Owned(format!("minecraft:{name}"))
+    }
+}
+
+/// The full state of a block in the game's notation, like
+/// `minecraft:oak_log[axis=x]`, or just its name if it has no properties.
+/// Properties are sorted and separated by semicolons rather than commas, so
+/// that the name can go into a CSV as is, like
+/// `minecraft:furnace[facing=north;lit=true]`. fastanvil leaves out the
+/// `waterlogged` and `powered` properties.
+pub fn block_state_name(block: &Block) -> String {
+    match block.encoded_description().split_once('|') {
+        Some((name, properties)) if !properties.is_empty() => {
+            format!("{name}[{}]", properties.replace(',', ";"))
+        }
+        _ => block.name().to_owned(),
+    }
+}
+
+/// The name of a block without the properties [`block_state_name`] adds.
+pub fn strip_block_properties(name: &str) -> &str {
+    name.split_once('[').map_or(name, |(name, _)| name)
+}
+
+/// DataVersion of Minecraft 1.21.
+pub const DATA_VERSION_1_21: i32 = 3953;
+
+/// The newest chunk format (by DataVersion) known to parse correctly.
+pub const NEWEST_SUPPORTED_DATA_VERSION: i32 = DATA_VERSION_1_21;
+
+/// The first DataVersion with the chunk format of 1.18 (snapshot 21w43a),
+/// where the sections hold paletted block states and biomes.
+const FIRST_1_18_DATA_VERSION: i32 = 2844;
+
+/// The first DataVersion with the flattened block IDs of 1.13 (snapshot
+/// 17w47a).
+const FIRST_1_13_DATA_VERSION: i32 = 1451;
+
+/// Parses a chunk with the format of its DataVersion. fastanvil's
+/// `JavaChunk::from_bytes` falls back on the older formats whenever the
+/// current one fails, which can make a chunk of a newer, unsupported format
+/// parse as a garbled pre-1.18 one; here, chunks from 1.18 on are only parsed
+/// with the current format, and chunks from 1.13 on never as pre-1.13 ones.
+/// Chunks without a DataVersion (before 1.9) can be of any of the formats.
+pub fn parse_chunk(data: &[u8]) -> fastnbt::error::Result<JavaChunk> {
+    let current = match fastnbt::from_bytes::<fastanvil::CurrentJavaChunk>(data) {
+        Ok(chunk) => return Ok(JavaChunk::Post18(chunk)),
+        Err(e) => e,
+    };
+    let version = chunk_data_version(data);
+    if version.is_some_and(|version| version >= FIRST_1_18_DATA_VERSION) {
+        return Err(current);
+    }
+    let pre18 = match fastnbt::from_bytes::<fastanvil::pre18::JavaChunk>(data) {
+        Ok(chunk) => return Ok(JavaChunk::Pre18(chunk)),
+        Err(e) => e,
+    };
+    if version.is_some_and(|version| version >= FIRST_1_13_DATA_VERSION) {
+        return Err(pre18);
+    }
+    Ok(JavaChunk::Pre13(fastnbt::from_bytes(data)?))
+}
+
+/// Whether a chunk status means that the chunk is fully generated. Newer
+/// versions namespace the statuses (`minecraft:full`), while older ones, as
+/// well as pre-1.13 chunks as reported by fastanvil, use just `full`.
+pub fn is_full_status(status: &str) -> bool {
+    status == "minecraft:full" || status == "full"
+}
+
+/// Reads just the DataVersion of a chunk, which works even for chunks that
+/// fail to parse fully.
+pub fn chunk_data_version(data: &[u8]) -> Option<i32> {
+    #[derive(Deserialize)]
+    struct VersionOnly {
+        #[serde(rename = "DataVersion")]
+        data_version: i32,
+    }
+    fastnbt::from_bytes::<VersionOnly>(data)
+        .ok()
+        .map(|v| v.data_version)
+}
+
+/// Called for chunks that failed to parse. Those are skipped silently, unless
+/// they come from a version newer than the supported ones - in that case the
+/// user is told once that that's the likely cause.
+fn warn_if_newer_than_supported(data: &[u8]) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    match chunk_data_version(data) {
+        Some(version) if version > NEWEST_SUPPORTED_DATA_VERSION => {
+            if !WARNED.swap(true, Ordering::Relaxed) {
+                soft_warn!(
+                    "Failed to read a chunk with DataVersion {version}, which is newer than the \
+                     newest supported one ({NEWEST_SUPPORTED_DATA_VERSION}). The chunk format \
+                     of this Minecraft version is probably not supported yet."
+                );
+            }
+        }
+        version => trace!("Skipping an unreadable chunk with DataVersion {version:?}."),
+    }
+}
+
+#[test]
+fn test_parse_chunk_by_data_version() {
+    use fixtures::*;
+    #[derive(Serialize)]
+    struct Pre18Chunk {
+        #[serde(rename = "DataVersion")]
+        data_version: i32,
+        #[serde(rename = "Level")]
+        level: Pre18Level,
+    }
+    #[derive(Serialize)]
+    struct Pre18Level {
+        #[serde(rename = "xPos")]
+        x_pos: i32,
+        #[serde(rename = "zPos")]
+        z_pos: i32,
+        #[serde(rename = "Status")]
+        status: String,
+    }
+    let pre18 = |data_version| {
+        fastnbt::to_bytes(&Pre18Chunk {
+            data_version,
+            level: Pre18Level {
+                x_pos: 0,
+                z_pos: 0,
+                status: "full".to_owned(),
+            },
+        })
+        .unwrap()
+    };
+    assert!(matches!(parse_chunk(&pre18(2586)), Ok(JavaChunk::Pre18(_))));
+    // A chunk of 1.18 or later that the current format can't read isn't
+    // mistaken for an older one.
+    assert!(parse_chunk(&pre18(FIRST_1_18_DATA_VERSION)).is_err());
+    assert!(parse_chunk(&pre18(DATA_VERSION_1_21 + 1)).is_err());
+    let current = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]);
+    assert!(matches!(
+        parse_chunk(&current.to_bytes()),
+        Ok(JavaChunk::Post18(_))
+    ));
+}
+
+#[test]
+fn test_count_1_21_chunk() {
+    use fixtures::*;
+    let chunk = ChunkNbt::full(vec![
+        SectionNbt::uniform(-4, "minecraft:deepslate"),
+        SectionNbt::with_blocks(
+            0,
+            &["minecraft:stone", "minecraft:diamond_ore"],
+            |x, y, z| usize::from((x, y, z) == (3, 5, 7)),
+        ),
+    ]);
+    let mut old_status = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]);
+    old_status.status = "full".to_owned();
+    let mut proto = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]);
+    proto.status = "minecraft:features".to_owned();
+    let mut region = region_with(&[
+        ((0, 0), chunk.to_bytes()),
+        ((1, 0), old_status.to_bytes()),
+        ((2, 0), proto.to_bytes()),
+        ((3, 0), b"not nbt".to_vec()),
+    ]);
+    let counts = count_blocks(
+        &mut region,
+        (0, 0),
+        false,
+        "test:dim",
+        &ScanOptions::default(),
+    );
+    assert_eq!(counts.chunks_counted, 2);
+    assert_eq!(counts.protochunks_seen, 1);
+    assert_eq!(counts.min_y, Some(-64));
+    assert_eq!(
+        counts.statuses,
+        BTreeMap::from([
+            ("full".to_owned(), 1),
+            ("minecraft:features".to_owned(), 1),
+            ("minecraft:full".to_owned(), 1),
+            (UNPARSEABLE_STATUS.to_owned(), 1),
+        ])
+    );
+    assert_eq!(
+        counts.counts["minecraft:diamond_ore"],
+        HashMap::from([(5, 1)])
+    );
+    assert_eq!(counts.counts["minecraft:deepslate"][&-64], 256);
+    assert_eq!(counts.counts["minecraft:stone"][&5], 2 * 256 - 1);
+}
+
+#[test]
+fn test_normalize_block_names() {
+    use fixtures::*;
+    assert!(matches!(
+        normalize_block_name("minecraft:stone"),
+        Cow::Borrowed("minecraft:stone")
+    ));
+    assert_eq!(normalize_block_name("stone"), "minecraft:stone");
+    assert_eq!(normalize_block_name("Minecraft:Stone"), "minecraft:stone");
+    assert_eq!(normalize_block_name("Create:Zinc_Ore"), "create:zinc_ore");
+
+    // The same block written three ways, on alternating levels.
+    let chunk = ChunkNbt::full(vec![SectionNbt::with_blocks(
+        0,
+        &[
+            "minecraft:stone",
+            "Stone",
+            "MINECRAFT:STONE",
+            "Minecraft:Diamond_Ore",
+        ],
+        |_, y, _| y % 4,
+    )]);
+    let count = |options: &ScanOptions| {
+        let mut region = region_with(&[((0, 0), chunk.to_bytes())]);
+        count_blocks(&mut region, (0, 0), false, "test:dim", options).counts
+    };
+
+    let plain = count(&ScanOptions::default());
+    assert_eq!(plain.len(), 4);
+    assert_eq!(
+        plain["Stone"],
+        HashMap::from([(1, 256), (5, 256), (9, 256), (13, 256)])
+    );
+
+    let options = ScanOptions {
+        normalize_names: true,
+        ..Default::default()
+    };
+    let normalized = count(&options);
+    assert_eq!(normalized.len(), 2);
+    assert_eq!(normalized["minecraft:stone"].len(), 12);
+    assert!(normalized["minecraft:stone"].values().all(|&n| n == 256));
+    assert_eq!(normalized["minecraft:diamond_ore"][&3], 256);
+}
+
+#[test]
+fn test_track_properties() {
+    use fixtures::*;
+    let chunk = ChunkNbt::full(vec![SectionNbt::with_blocks(
+        0,
+        &[
+            "minecraft:oak_log[axis=y]",
+            "minecraft:oak_log[axis=x]",
+            "minecraft:furnace[facing=north,lit=true]",
+        ],
+        |x, y, _| usize::from(y == 0) + usize::from((x, y) == (0, 0)),
+    )]);
+    let count = |options: &ScanOptions| {
+        let mut region = region_with(&[((0, 0), chunk.to_bytes())]);
+        count_blocks(&mut region, (0, 0), false, "test:dim", options).counts
+    };
+
+    let plain = count(&ScanOptions::default());
+    assert_eq!(plain["minecraft:oak_log"][&0], 256 - 16);
+    assert_eq!(plain["minecraft:oak_log"][&1], 256);
+
+    let options = ScanOptions {
+        track_properties: true,
+        ..Default::default()
+    };
+    let states = count(&options);
+    assert_eq!(states["minecraft:furnace[facing=north;lit=true]"][&0], 16);
+    let options = ScanOptions {
+        block_whitelist: Some(HashSet::from(["minecraft:oak_log".to_owned()])),
+        ..options
+    };
+    let states = count(&options);
+    assert_eq!(states.len(), 2);
+    assert_eq!(
+        states["minecraft:oak_log[axis=x]"],
+        HashMap::from([(0, 256 - 16)])
+    );
+    assert_eq!(states["minecraft:oak_log[axis=y]"][&15], 256);
+
+    let mut freqs = BlockFrequencies::empty("test:dim".to_owned());
+    freqs
+        .frequencies
+        .insert("minecraft:oak_log[axis=x]".to_owned(), [(0, 0.25)].into());
+    freqs.frequencies.insert(
+        "minecraft:oak_log[axis=y]".to_owned(),
+        [(0, 0.5), (1, 1.)].into(),
+    );
+    assert_eq!(
+        freqs.sorted_blocks_without_properties(),
+        [("minecraft:oak_log".to_owned(), [(0, 0.75), (1, 1.)].into())]
+    );
+}
+
+#[test]
+fn test_include_exclude_patterns() {
+    use fixtures::*;
+    let chunk = ChunkNbt::full(vec![SectionNbt::with_blocks(
+        0,
+        &[
+            "minecraft:stone",
+            "minecraft:iron_ore",
+            "minecraft:deepslate_iron_ore",
+            "mymod:tin_ore",
+        ],
+        |x, _, _| x % 4,
+    )]);
+    let count = |include: &[&str], exclude: &[&str]| {
+        let patterns = |patterns: &[&str]| {
+            patterns
+                .iter()
+                .map(|pattern| glob::Pattern::new(pattern).unwrap())
+                .collect()
+        };
+        let options = ScanOptions {
+            include: patterns(include),
+            exclude: patterns(exclude),
+            ..Default::default()
+        };
+        let mut region = region_with(&[((0, 0), chunk.to_bytes())]);
+        let counts = count_blocks(&mut region, (0, 0), false, "test:dim", &options);
+        // Filtered out blocks are still part of the blocks looked at.
+        assert_eq!(counts.blocks_counted, 16 * 256);
+        counts.counts.into_keys().sorted().collect::<Vec<_>>()
+    };
+    assert_eq!(
+        count(&["*_ore"], &[]),
+        [
+            "minecraft:deepslate_iron_ore",
+            "minecraft:iron_ore",
+            "mymod:tin_ore"
+        ]
+    );
+    assert_eq!(
+        count(&["*_ore"], &["*deepslate*", "mymod:*"]),
+        ["minecraft:iron_ore"]
+    );
+    assert_eq!(count(&[], &["*_ore"]), ["minecraft:stone"]);
+}
+
+#[test]
+fn test_scan_region_with() {
+    use fixtures::*;
+    let full = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]);
+    let mut proto = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:dirt")]);
+    proto.status = "minecraft:features".to_owned();
+    let mut region = region_with(&[
+        ((4, 0), full.to_bytes()),
+        ((1, 2), proto.to_bytes()),
+        ((3, 3), b"not nbt".to_vec()),
+    ]);
+    let mut seen = vec![];
+    scan_region_with(&mut region, ProtoOption::Include, |chunk, x, z| {
+        seen.push((x, z, chunk.block(0, 0, 0).unwrap().name().to_owned()));
+    });
+    assert_eq!(
+        seen,
+        [
+            (4, 0, "minecraft:stone".to_owned()),
+            (1, 2, "minecraft:dirt".to_owned())
+        ]
+    );
+    let mut count = 0;
+    scan_region_with(&mut region, ProtoOption::OnlyProto, |_, _, _| count += 1);
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn test_exposed_only() {
+    use fixtures::*;
+    // Stone with a diamond ore buried in the middle and one at the edge of the
+    // first chunk, next to an air chunk.
+    let stone = |ore: (usize, usize, usize)| {
+        ChunkNbt::full(vec![SectionNbt::with_blocks(
+            0,
+            &["minecraft:stone", "minecraft:diamond_ore"],
+            move |x, y, z| usize::from((x, y, z) == ore || (x, y, z) == (15, 8, 8)),
+        )])
+        .to_bytes()
+    };
+    let air = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:air")]).to_bytes();
+    let mut region = region_with(&[((0, 0), stone((8, 8, 8))), ((1, 0), air)]);
+    let options = ScanOptions {
+        exposed_only: true,
+        ..Default::default()
+    };
+    let counts = count_blocks(&mut region, (0, 0), false, "test:dim", &options);
+    assert_eq!(
+        counts.counts["minecraft:diamond_ore"],
+        HashMap::from([(8, 1)])
+    );
+    // The top of the stone is exposed, as is its side facing the air chunk,
+    // and all of the air itself.
+    assert_eq!(counts.counts["minecraft:stone"][&15], 256);
+    assert_eq!(counts.counts["minecraft:stone"][&8], 16 - 1);
+    assert_eq!(counts.counts["minecraft:air"][&0], 256);
+    assert_eq!(counts.blocks_counted, 2 * 16 * 256);
+}
+
+#[test]
+fn test_absurd_y_range() {
+    use fixtures::*;
+    let corrupt = ChunkNbt::full(vec![
+        SectionNbt::uniform(-128, "minecraft:stone"),
+        SectionNbt::uniform(127, "minecraft:stone"),
+    ]);
+    let mut region = region_with(&[((0, 0), corrupt.to_bytes())]);
+    let counts = count_blocks(
+        &mut region,
+        (0, 0),
+        false,
+        "test:dim",
+        &ScanOptions::default(),
+    );
+    assert_eq!(counts.chunks_counted, 0);
+    assert_eq!(counts.chunks_failed, 1);
+    assert_eq!(counts.blocks_counted, 0);
+}
+
+#[test]
+fn test_truncated_region_file() {
+    use fixtures::*;
+    let chunk = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]).to_bytes();
+    let region = region_with(&[((0, 0), chunk.clone()), ((5, 3), chunk)]);
+    let mut data = region.into_inner().unwrap().into_inner();
+    // The chunk written last is at the end of the file - cut it off.
+    data.truncate(data.len() - 4096);
+    for exposed_only in [false, true] {
+        for parallel_chunks in [false, true] {
+            let options = ScanOptions {
+                exposed_only,
+                parallel_chunks,
+                ..Default::default()
+            };
+            let mut region = Region::from_stream(std::io::Cursor::new(data.clone())).unwrap();
+            let counts = count_blocks(&mut region, (0, 0), false, "test:dim", &options);
+            assert_eq!(counts.chunks_counted, 1);
+            assert_eq!(counts.chunks_failed, 1);
+        }
+    }
+}
+
+#[test]
+fn test_parallel_chunks() {
+    use fixtures::*;
+    let mut chunks = vec![];
+    for i in 0..40 {
+        let chunk = ChunkNbt::full(vec![
+            SectionNbt::uniform(-1, "minecraft:deepslate"),
+            SectionNbt::with_blocks(
+                0,
+                &["minecraft:stone", "minecraft:diamond_ore"],
+                |x, y, _| usize::from(x < i % 16 && y == 3),
+            ),
+        ]);
+        chunks.push(((i % 32, i / 32), chunk.to_bytes()));
     }
+    let corrupt = ChunkNbt::full(vec![
+        SectionNbt::uniform(-128, "minecraft:stone"),
+        SectionNbt::uniform(127, "minecraft:stone"),
+    ]);
+    chunks.push(((31, 31), corrupt.to_bytes()));
+    let serial = count_blocks(
+        &mut region_with(&chunks),
+        (0, 0),
+        false,
+        "test:dim",
+        &ScanOptions::default(),
+    );
+    let options = ScanOptions {
+        parallel_chunks: true,
+        ..Default::default()
+    };
+    let parallel = count_blocks(
+        &mut region_with(&chunks),
+        (0, 0),
+        false,
+        "test:dim",
+        &options,
+    );
+    assert_eq!(parallel.counts, serial.counts);
+    assert_eq!(parallel.chunks_counted, 40);
+    assert_eq!(parallel.chunks_failed, 1);
+    assert_eq!(parallel.blocks_counted, serial.blocks_counted);
+    assert_eq!(parallel.columns_counted, serial.columns_counted);
+    assert_eq!(parallel.statuses, serial.statuses);
+    assert_eq!(parallel.min_y, Some(-16));
+    assert_eq!(parallel.version, Some(RegionVersion::AtLeast118));
+}
+
+#[test]
+fn test_deep_world() {
+    use fixtures::*;
+    let deep = ChunkNbt::full(vec![
+        SectionNbt::uniform(-20, "minecraft:deepslate"),
+        SectionNbt::uniform(19, "minecraft:stone"),
+    ]);
+    let mut region = region_with(&[((0, 0), deep.to_bytes())]);
+    let counts = count_blocks(
+        &mut region,
+        (0, 0),
+        false,
+        "spectrum:deeper_down",
+        &ScanOptions::default(),
+    );
+    assert_eq!(counts.chunks_counted, 1);
+    assert_eq!(counts.chunks_failed, 0);
+    assert_eq!(counts.min_y, Some(-320));
+    assert_eq!(counts.blocks_counted, 640 * 256);
+    assert_eq!(counts.counts["minecraft:deepslate"][&-320], 256);
+    assert_eq!(counts.counts["minecraft:stone"][&319], 256);
+}
+
+#[test]
+fn test_truncated_block_data() {
+    use fixtures::*;
+    let intact = ChunkNbt::full(vec![SectionNbt::with_blocks(
+        0,
+        &["minecraft:stone", "minecraft:diamond_ore"],
+        |x, _, _| usize::from(x == 0),
+    )]);
+    // 4 bits per block take 256 longs, of which only the first half is left.
+    let mut corrupt = ChunkNbt::full(vec![SectionNbt::with_blocks(
+        0,
+        &["minecraft:stone", "minecraft:diamond_ore"],
+        |x, _, _| usize::from(x == 0),
+    )]);
+    corrupt.sections[0].block_states.data = Some(fastnbt::LongArray::new(vec![0; 128]));
+    let mut region = region_with(&[((0, 0), corrupt.to_bytes()), ((1, 0), intact.to_bytes())]);
+    let counts = count_blocks(
+        &mut region,
+        (0, 0),
+        false,
+        "test:dim",
+        &ScanOptions::default(),
+    );
+    assert_eq!(counts.chunks_counted, 1);
+    assert_eq!(counts.chunks_failed, 1);
+    assert_eq!(counts.counts["minecraft:diamond_ore"][&0], 16);
+
+    // The failures are kept through merging and the tall CSV.
+    let freqs = count_frequencies(
+        &mut region,
+        (0, 0),
+        false,
+        "test:dim",
+        &ScanOptions::default(),
+    );
+    let mut merged = BlockFrequencies::empty("test:dim".to_owned());
+    merge_frequencies_into(&mut merged, freqs).unwrap();
+    assert_eq!(merged.chunks_failed, 1);
+    let csv = generate_tall_csv(&[(merged, RegionVersion::AtLeast118)], false, true);
+    let parsed = parse_tall_csv(&csv).unwrap();
+    assert_eq!(parsed[0].0.chunks_failed, 1);
+}
+
+#[test]
+fn test_merge_counts() {
+    use fixtures::*;
+    let mut dense = region_with(&[
+        (
+            (0, 0),
+            ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]).to_bytes(),
+        ),
+        (
+            (1, 0),
+            ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:iron_ore")]).to_bytes(),
+        ),
+    ]);
+    let mut sparse = region_with(&[(
+        (0, 0),
+        ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]).to_bytes(),
+    )]);
+    let options = ScanOptions::default();
+    let mut counts = count_blocks(&mut dense, (0, 0), false, "test:dim", &options);
+    let other = count_blocks(&mut sparse, (1, 0), false, "test:dim", &options);
+    // Counts survive being written to a checkpoint and read back.
+    let other: BlockCounts = serde_json::from_str(&serde_json::to_string(&other).unwrap()).unwrap();
+    merge_counts_into(&mut counts, other).unwrap();
+    assert_eq!((counts.chunks_counted, counts.columns_counted), (3, 768));
+    let freqs = frequencies_from_counts(counts);
+    assert_eq!(freqs.area, 768);
+    assert_eq!(freqs.frequencies["minecraft:stone"][&0], 2. / 3.);
+    assert_eq!(freqs.frequencies["minecraft:iron_ore"][&15], 1. / 3.);
+    assert!(merge_counts_into(
+        &mut BlockCounts::empty("test:dim".to_owned()),
+        BlockCounts::empty("test:other".to_owned())
+    )
+    .is_err());
+}
+
+#[test]
+fn test_merge_dense_and_sparse_regions() {
+    use fixtures::*;
+    let chunk = |block| ChunkNbt::full(vec![SectionNbt::uniform(0, block)]).to_bytes();
+    let mut dense = region_with(&[
+        ((0, 0), chunk("minecraft:stone")),
+        ((1, 0), chunk("minecraft:stone")),
+        ((2, 0), chunk("minecraft:iron_ore")),
+    ]);
+    let mut sparse = region_with(&[((0, 0), chunk("minecraft:stone"))]);
+    let options = ScanOptions::default();
+    let mut merged = count_frequencies(&mut dense, (0, 0), false, "test:dim", &options);
+    let sparse = count_frequencies(&mut sparse, (1, 0), false, "test:dim", &options);
+    assert_eq!((merged.area, sparse.area), (768, 256));
+    merge_frequencies_into(&mut merged, sparse).unwrap();
+    // The same as if all 4 chunks had been counted together, including for the
+    // block missing from the sparse region.
+    assert_eq!(merged.area, 1024);
+    assert_eq!(merged.frequencies["minecraft:stone"][&0], 0.75);
+    assert_eq!(merged.frequencies["minecraft:iron_ore"][&0], 0.25);
+}
+
+#[test]
+fn test_catch_chunk_panic() {
+    assert_eq!(catch_chunk_panic(|| 1), Some(1));
+    let nested = catch_chunk_panic(|| {
+        let inner: Option<()> = catch_chunk_panic(|| panic!("corrupt chunk"));
+        assert_eq!(inner, None);
+        // Still caught quietly after the inner call returned.
+        panic!("corrupt chunk")
+    });
+    assert_eq!(nested, None::<()>);
+    assert_eq!(CATCHING_CHUNK_PANICS.get(), 0);
+}
+
+#[test]
+fn test_min_inhabited_ticks() {
+    use fixtures::*;
+    let visited = ChunkNbt {
+        inhabited_time: 1200,
+        ..ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")])
+    };
+    let unvisited = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:dirt")]);
+    let mut region = region_with(&[((0, 0), visited.to_bytes()), ((0, 1), unvisited.to_bytes())]);
+    let options = ScanOptions {
+        min_inhabited_ticks: Some(600),
+        ..Default::default()
+    };
+    let counts = count_blocks(&mut region, (0, 0), false, "test:dim", &options);
+    assert_eq!(counts.chunks_counted, 1);
+    assert!(counts.counts.contains_key("minecraft:stone"));
+    assert!(!counts.counts.contains_key("minecraft:dirt"));
+}
+
+#[test]
+fn test_status_filter() {
+    use fixtures::*;
+    let mut noise = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]);
+    noise.status = "minecraft:noise".to_owned();
+    let full = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:grass_block")]);
+    let mut region = region_with(&[((0, 0), noise.to_bytes()), ((0, 1), full.to_bytes())]);
+    let options = ScanOptions {
+        statuses: Some(HashSet::from(["minecraft:noise".to_owned()])),
+        ..Default::default()
+    };
+    let counts = count_blocks(&mut region, (0, 0), false, "test:dim", &options);
+    assert_eq!(counts.chunks_counted, 1);
+    assert!(counts.counts.contains_key("minecraft:stone"));
+    assert!(!counts.counts.contains_key("minecraft:grass_block"));
+}
+
+#[test]
+fn test_excluded_levels() {
+    use fixtures::*;
+    let chunk = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:netherrack")]).to_bytes();
+    let mut region = region_with(&[((0, 0), chunk)]);
+    let options = ScanOptions {
+        excluded_levels: vec![10..=12],
+        skip_nether_bedrock: true,
+        ..Default::default()
+    };
+    let counts = count_blocks(&mut region, (0, 0), false, "minecraft:the_nether", &options);
+    let mut levels: Vec<isize> = counts.counts["minecraft:netherrack"]
+        .keys()
+        .copied()
+        .collect();
+    levels.sort_unstable();
+    assert_eq!(levels, [5, 6, 7, 8, 9, 13, 14, 15]);
+    assert_eq!(counts.blocks_counted, 8 * 256);
+    // The nether preset doesn't apply to other dimensions.
+    let counts = count_blocks(&mut region, (0, 0), false, "minecraft:overworld", &options);
+    assert_eq!(counts.blocks_counted, 13 * 256);
+}
+
+#[test]
+fn test_y_range() {
+    use fixtures::*;
+    let chunk = ChunkNbt::full(vec![
+        SectionNbt::uniform(-1, "minecraft:deepslate"),
+        SectionNbt::uniform(0, "minecraft:stone"),
+    ])
+    .to_bytes();
+    let mut region = region_with(&[((0, 0), chunk)]);
+    let options = ScanOptions {
+        y_range: Some(-3..=40),
+        excluded_levels: vec![0..=1],
+        ..Default::default()
+    };
+    let freqs = count_frequencies(&mut region, (0, 0), false, "test:dim", &options);
+    assert_eq!(freqs.area, 256);
+    assert_eq!(freqs.blocks_counted, (3 + 14) * 256);
+    // The bottom of the world is still that of the chunks, for the JER offset.
+    assert_eq!(freqs.min_y, Some(-16));
+    let mut levels: Vec<isize> = freqs.frequencies["minecraft:stone"]
+        .keys()
+        .copied()
+        .collect();
+    levels.sort_unstable();
+    assert_eq!(levels, (2..16).collect::<Vec<_>>());
+    assert_eq!(freqs.frequencies["minecraft:deepslate"].len(), 3);
+    assert_eq!(freqs.frequencies["minecraft:deepslate"][&-1], 1.);
+    // Chunks entirely outside the range are still part of the area.
+    let options = ScanOptions {
+        y_range: Some(100..=200),
+        ..Default::default()
+    };
+    let freqs = count_frequencies(&mut region, (0, 0), false, "test:dim", &options);
+    assert_eq!(
+        (freqs.chunks_counted, freqs.area, freqs.blocks_counted),
+        (1, 256, 0)
+    );
+}
+
+#[test]
+fn test_polygon_columns() {
+    use fixtures::*;
+    let stone = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]).to_bytes();
+    let mut region = region_with(&[((0, 0), stone.clone()), ((1, 0), stone)]);
+    // Half of the first chunk of region (-1, 0), which is at x=-512.
+    let options = ScanOptions {
+        polygon: Polygon::new(vec![(-512, 0), (-504, 0), (-504, 16), (-512, 16)]),
+        ..Default::default()
+    };
+    let freqs = count_frequencies(&mut region, (-1, 0), false, "test:dim", &options);
+    assert_eq!(freqs.chunks_counted, 1);
+    assert_eq!(freqs.area, 128);
+    assert_eq!(freqs.blocks_counted, 128 * 16);
+    assert_eq!(freqs.frequencies["minecraft:stone"][&0], 1.);
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
 pub enum ProtoOption {
     /// Protochunks will be skipped
+    #[default]
     Skip,
     /// Protochunks will be included in the scan
     Include,
@@ -84,21 +1387,348 @@ pub enum ProtoOption {
     OnlyProto,
 }
 
+impl ProtoOption {
+    /// Whether a chunk should be scanned, given whether it's fully generated.
+    pub fn accepts(self, full: bool) -> bool {
+        match self {
+            ProtoOption::Skip => full,
+            ProtoOption::Include => true,
+            ProtoOption::OnlyProto => !full,
+        }
+    }
+}
+
+/// Settings that decide which regions get scanned, and which chunks and blocks
+/// `count_blocks` counts in them.
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    pub proto: ProtoOption,
+    /// If present, only chunks with exactly one of these statuses are counted,
+    /// regardless of `proto`. Statuses without a namespace are taken to be in
+    /// `minecraft:`.
+    pub statuses: Option<HashSet<String>>,
+    pub sampling: RegionSampling,
+    /// If present, only regions whose file name (like `r.0.-1.mca`) matches
+    /// this pattern are scanned.
+    pub region_glob: Option<glob::Pattern>,
+    /// If present, only blocks with these names are counted. All other blocks
+    /// are still part of `blocks_counted`, so frequencies stay relative to the
+    /// whole scanned area.
+    pub block_whitelist: Option<HashSet<String>>,
+    /// If not empty, only blocks whose name matches one of these patterns are
+    /// counted. Like with `block_whitelist`, the others are still part of
+    /// `blocks_counted`.
+    pub include: Vec<glob::Pattern>,
+    /// Blocks whose name matches one of these patterns aren't counted, though
+    /// they're still part of `blocks_counted`.
+    pub exclude: Vec<glob::Pattern>,
+    /// Normalize block names with [`normalize_block_name`] before counting.
+    pub normalize_names: bool,
+    /// Count every state of a block separately, by its [`block_state_name`].
+    /// The whitelist still matches the plain block names.
+    pub track_properties: bool,
+    /// Read region files by memory-mapping them instead of through a `File`.
+    #[cfg(feature = "mmap")]
+    pub mmap: bool,
+    /// The format of the region files to read. `mmap` only applies to `.mca`
+    /// files.
+    #[cfg(feature = "linear")]
+    pub region_format: RegionFormat,
+    /// If present, only the columns inside this polygon are counted, and the
+    /// area is the number of such columns. Regions are still chosen by the
+    /// zone, see [`Polygon::bounding_zone`].
+    pub polygon: Option<Polygon>,
+    /// Only count blocks with air next to them, see [`is_air`]. Blocks that
+    /// aren't counted are still part of `blocks_counted`.
+    pub exposed_only: bool,
+    /// Take the world version from the chunks counted rather than determining
+    /// it with [`determine_version`] before the scan, which reads some chunks
+    /// twice.
+    pub single_pass: bool,
+    /// Log every chunk counted and the first occurrence of each kind of block.
+    pub verbose: bool,
+    /// Count the chunks of each region in parallel rather than one after
+    /// another, which pays off when there are fewer regions than threads to
+    /// scan them with. Ignored with `exposed_only`.
+    pub parallel_chunks: bool,
+    /// If present, only chunks where players have spent at least this many
+    /// ticks (by their InhabitedTime) are counted.
+    pub min_inhabited_ticks: Option<i64>,
+    /// A soft cap on the memory the frequencies of a dimension may take up, in
+    /// megabytes, as estimated by [`BlockFrequencies::estimated_memory`].
+    /// Exceeding it only logs a warning.
+    pub max_memory_mb: Option<u64>,
+    /// If present, regions are scanned in batches of this many, and the counts
+    /// of each batch are merged before the next one starts. This bounds the
+    /// memory taken by the counts to roughly this many times that of the
+    /// counts of a single region (plus the total), at the cost of threads
+    /// idling at the end of each batch.
+    pub max_regions_in_flight: Option<NonZeroUsize>,
+    /// Levels not to count at all, in any dimension. They're not part of
+    /// `blocks_counted` either.
+    pub excluded_levels: Vec<RangeInclusive<isize>>,
+    /// If present, only the levels in this range are counted, in any
+    /// dimension. Like with `excluded_levels`, the others aren't part of
+    /// `blocks_counted`.
+    pub y_range: Option<RangeInclusive<isize>>,
+    /// Don't count the bedrock floor and roof of the nether (and what's above
+    /// the roof), at y<=4 and y>=123.
+    pub skip_nether_bedrock: bool,
+    /// A pause before reading each region, so that scanning the world of a
+    /// running server doesn't starve it of disk bandwidth.
+    pub throttle: Option<Duration>,
+}
+
+/// The levels of the nether between its bedrock floor and roof.
+pub const NETHER_BETWEEN_BEDROCK: RangeInclusive<isize> = 5..=122;
+
+impl ScanOptions {
+    /// Whether a level is left out of the count, see
+    /// [`ScanOptions::excluded_levels`], [`ScanOptions::y_range`] and
+    /// [`ScanOptions::skip_nether_bedrock`].
+    pub fn is_level_excluded(&self, dimension: &str, y: isize) -> bool {
+        (self.skip_nether_bedrock
+            && dimension == "minecraft:the_nether"
+            && !NETHER_BETWEEN_BEDROCK.contains(&y))
+            || self.excluded_levels.iter().any(|range| range.contains(&y))
+            || self
+                .y_range
+                .as_ref()
+                .is_some_and(|range| !range.contains(&y))
+    }
+
+    /// The part of the levels of a chunk that [`ScanOptions::y_range`] lets
+    /// through, which may be empty.
+    pub fn clamp_levels(&self, levels: Range<isize>) -> Range<isize> {
+        match &self.y_range {
+            Some(range) => levels.start.max(*range.start())..levels.end.min(range.end() + 1),
+            None => levels,
+        }
+    }
+
+    /// Whether a chunk with this status passes the status filter, or else the
+    /// protochunk one.
+    pub fn accepts_status(&self, status: &str, full: bool) -> bool {
+        match &self.statuses {
+            Some(statuses) => statuses.contains(normalize_block_name(status).as_ref()),
+            None => self.proto.accepts(full),
+        }
+    }
+
+    /// Whether blocks with this name (without properties) are counted, by
+    /// [`ScanOptions::block_whitelist`], [`ScanOptions::include`] and
+    /// [`ScanOptions::exclude`].
+    pub fn is_counted(&self, name: &str) -> bool {
+        self.block_whitelist
+            .as_ref()
+            .is_none_or(|whitelist| whitelist.contains(name))
+            && (self.include.is_empty() || self.include.iter().any(|p| p.matches(name)))
+            && !self.exclude.iter().any(|pattern| pattern.matches(name))
+    }
+
+    /// Whether a chunk, given as its NBT, passes the InhabitedTime threshold.
+    /// Chunks without an InhabitedTime don't pass any threshold.
+    pub fn is_inhabited_enough(&self, data: &[u8]) -> bool {
+        self.min_inhabited_ticks.is_none_or(|min_ticks| {
+            chunk_inhabited_time(data).is_some_and(|(ticks, _)| ticks >= min_ticks)
+        })
+    }
+
+    /// Describes the options that change what's counted, along with the
+    /// `zone`, to tell whether counts kept from an earlier scan (like the ones
+    /// of a checkpoint) can be combined with the ones of this scan. Options
+    /// that only change how the scan runs, like `verbose` or `throttle`, are
+    /// left out.
+    pub fn counting_fingerprint(&self, zone: Option<Zone>) -> String {
+        fn sorted(set: &Option<HashSet<String>>) -> Option<Vec<&String>> {
+            set.as_ref().map(|set| set.iter().sorted().collect())
+        }
+        fn patterns(patterns: &[glob::Pattern]) -> Vec<&str> {
+            patterns.iter().map(glob::Pattern::as_str).collect()
+        }
+        format!(
+            "{:?}",
+            (
+                zone.map(|z| (z.from_x, z.to_x, z.from_z, z.to_z)),
+                // The seed only matters when sampling.
+                (
+                    self.proto,
+                    sorted(&self.statuses),
+                    self.sampling.is_active().then_some(self.sampling),
+                ),
+                (
+                    self.region_glob.as_ref().map(glob::Pattern::as_str),
+                    sorted(&self.block_whitelist),
+                    patterns(&self.include),
+                    patterns(&self.exclude),
+                ),
+                (
+                    self.normalize_names,
+                    self.track_properties,
+                    self.exposed_only
+                ),
+                (&self.polygon, self.min_inhabited_ticks),
+                (
+                    &self.excluded_levels,
+                    &self.y_range,
+                    self.skip_nether_bedrock
+                ),
+            )
+        )
+    }
+}
+
+#[test]
+fn test_counting_fingerprint() {
+    let options = ScanOptions {
+        block_whitelist: Some(["a:x", "b:y", "c:z"].map(str::to_owned).into()),
+        ..ScanOptions::default()
+    };
+    let zone = Some(Zone::new(0, 2, 0, 2).unwrap());
+    let fingerprint = options.counting_fingerprint(zone);
+    // Sets are written in order, whatever order they iterate in.
+    let reordered = ScanOptions {
+        block_whitelist: Some(["c:z", "a:x", "b:y"].map(str::to_owned).into()),
+        verbose: true,
+        sampling: RegionSampling {
+            seed: 42,
+            ..RegionSampling::default()
+        },
+        ..ScanOptions::default()
+    };
+    assert_eq!(reordered.counting_fingerprint(zone), fingerprint);
+    assert_ne!(options.counting_fingerprint(None), fingerprint);
+    let narrower = ScanOptions {
+        y_range: Some(0..=10),
+        ..options.clone()
+    };
+    assert_ne!(narrower.counting_fingerprint(zone), fingerprint);
+}
+
+/// Restricts a scan to a random subset of the regions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RegionSampling {
+    /// The share of the regions to scan, rounded up.
+    pub fraction: Option<f64>,
+    /// The largest number of regions to scan.
+    pub max_regions: Option<usize>,
+    /// The seed of the RNG choosing the regions, so that the same subset is
+    /// chosen every time.
+    pub seed: u64,
+}
+
+impl RegionSampling {
+    pub fn is_active(&self) -> bool {
+        self.fraction.is_some() || self.max_regions.is_some()
+    }
+}
+
+/// Chooses the regions to scan according to `sampling`. The choice only
+/// depends on the seed and the set of coordinates passed in. The result is
+/// sorted.
+pub fn sample_regions(
+    mut coords: Vec<(RCoord, RCoord)>,
+    sampling: &RegionSampling,
+) -> Vec<(RCoord, RCoord)> {
+    if !sampling.is_active() {
+        return coords;
+    }
+    let mut keep = coords.len();
+    if let Some(fraction) = sampling.fraction {
+        keep = (keep as f64 * fraction).ceil() as usize;
+    }
+    if let Some(max_regions) = sampling.max_regions {
+        keep = keep.min(max_regions);
+    }
+    // Sorted first so that the order the coordinates came in doesn't matter.
+    coords.sort_unstable();
+    coords.shuffle(&mut StdRng::seed_from_u64(sampling.seed));
+    coords.truncate(keep);
+    coords.sort_unstable();
+    coords
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct BlockCounts {
     pub counts: HashMap<String, HashMap<isize, u64>>,
     pub blocks_counted: u64,
+    /// The number of columns counted, which is 256 per chunk unless part of
+    /// the chunk is outside of [`ScanOptions::polygon`].
+    pub columns_counted: u64,
     pub chunks_counted: usize,
+    /// The number of chunks skipped because reading them failed, as they
+    /// couldn't be read from the region file or their data is corrupt despite
+    /// parsing.
+    pub chunks_failed: usize,
     pub protochunks_seen: usize,
+    /// The number of chunks with each status, including the skipped ones. Chunks
+    /// that failed to parse are under [`UNPARSEABLE_STATUS`].
+    pub statuses: BTreeMap<String, usize>,
+    /// The bottom of the world, as inferred from the lowest section of the
+    /// chunks counted.
+    pub min_y: Option<isize>,
+    /// The top of the world, as inferred from the highest section of the
+    /// chunks counted, whatever levels were counted in them.
+    #[serde(default)]
+    pub max_y: Option<isize>,
+    /// The version of the first chunk counted.
+    pub version: Option<RegionVersion>,
     pub dimension: String,
 }
+impl BlockCounts {
+    pub fn empty(dimension: String) -> BlockCounts {
+        BlockCounts {
+            counts: HashMap::new(),
+            blocks_counted: 0,
+            columns_counted: 0,
+            chunks_counted: 0,
+            chunks_failed: 0,
+            protochunks_seen: 0,
+            statuses: BTreeMap::new(),
+            min_y: None,
+            max_y: None,
+            version: None,
+            dimension,
+        }
+    }
+
+    /// Number of distinct (block, level) pairs with a count.
+    pub fn entry_count(&self) -> usize {
+        self.counts.values().map(HashMap::len).sum()
+    }
+
+    /// Like [`BlockFrequencies::estimated_memory`].
+    pub fn estimated_memory(&self) -> u64 {
+        estimated_map_memory(self.counts.len(), self.entry_count())
+    }
+}
+#[derive(Serialize, Deserialize)]
 pub struct BlockFrequencies {
     // Remember to update merge_frequencies_into when adding fields!
+    #[serde(serialize_with = "serialize_sorted")]
     pub frequencies: HashMap<String, HashMap<isize, f64>>,
     pub blocks_counted: u64,
     pub chunks_counted: usize,
     /// For ProtoOption::Skip these were skipped, for Include they are part of
     /// the counted, for OnlyProto should be equal to chunks_counted.
     pub protochunks_seen: usize,
+    /// See [`BlockCounts::chunks_failed`].
+    #[serde(default)]
+    pub chunks_failed: usize,
+    /// See [`BlockCounts::statuses`].
+    #[serde(default)]
+    pub statuses: BTreeMap<String, usize>,
+    /// See [`BlockCounts::min_y`].
+    #[serde(default)]
+    pub min_y: Option<isize>,
+    /// See [`BlockCounts::max_y`]. Not serialized, as it's only needed for
+    /// [`detected_world_height`] right after a scan.
+    #[serde(skip)]
+    pub max_y: Option<isize>,
+    /// See [`BlockCounts::version`]. Not serialized, as exports store the
+    /// version of the whole dimension separately.
+    #[serde(skip)]
+    pub version: Option<RegionVersion>,
     pub area: u64,
     pub dimension: String,
 }
@@ -109,10 +1739,60 @@ impl BlockFrequencies {
             blocks_counted: 0,
             chunks_counted: 0,
             protochunks_seen: 0,
+            chunks_failed: 0,
+            statuses: BTreeMap::new(),
+            min_y: None,
+            max_y: None,
+            version: None,
             area: 0,
             dimension,
         }
     }
+
+    /// The frequencies of every block, sorted by name, so that exports don't
+    /// depend on the iteration order of the map.
+    pub fn sorted_blocks(&self) -> Vec<(&String, &HashMap<isize, f64>)> {
+        let mut blocks: Vec<_> = self.frequencies.iter().collect();
+        blocks.sort_unstable_by_key(|(name, _)| *name);
+        blocks
+    }
+
+    /// Like [`BlockFrequencies::sorted_blocks`], but with the states of a block
+    /// counted separately by [`ScanOptions::track_properties`] summed up.
+    pub fn sorted_blocks_without_properties(&self) -> Vec<(String, HashMap<isize, f64>)> {
+        let mut blocks: BTreeMap<&str, HashMap<isize, f64>> = BTreeMap::new();
+        for (name, freqs) in &self.frequencies {
+            let block = blocks.entry(strip_block_properties(name)).or_default();
+            for (&y, &freq) in freqs {
+                *block.entry(y).or_default() += freq;
+            }
+        }
+        blocks
+            .into_iter()
+            .map(|(name, freqs)| (name.to_owned(), freqs))
+            .collect()
+    }
+
+    /// Number of distinct (block, level) pairs with a frequency.
+    pub fn entry_count(&self) -> usize {
+        self.frequencies.values().map(HashMap::len).sum()
+    }
+
+    /// A rough estimate of the memory taken up by the frequencies, in bytes.
+    /// Only the number of entries is taken into account, with an allowance for
+    /// the hash map overhead, as that's what grows with modded worlds.
+    pub fn estimated_memory(&self) -> u64 {
+        estimated_map_memory(self.frequencies.len(), self.entry_count())
+    }
+}
+
+/// See [`BlockFrequencies::estimated_memory`].
+fn estimated_map_memory(blocks: usize, entries: usize) -> u64 {
+    // The key and value of a level, plus a control byte and spare capacity.
+    const BYTES_PER_LEVEL: u64 = 32;
+    // The name, its String and its map of levels.
+    const BYTES_PER_BLOCK: u64 = 128;
+    entries as u64 * BYTES_PER_LEVEL + blocks as u64 * BYTES_PER_BLOCK
 }
 #[derive(Copy, Clone)]
 pub struct Zone {
@@ -123,90 +1803,226 @@ pub struct Zone {
 }
 
 impl Zone {
-    pub fn new(from_x: isize, to_x: isize, from_z: isize, to_z: isize) -> Self {
+    /// A zone from `from_x` to `to_x` and `from_z` to `to_z` in region
+    /// coordinates, exclusive of the ends. Fails if it's empty.
+    pub fn new(
+        from_x: isize,
+        to_x: isize,
+        from_z: isize,
+        to_z: isize,
+    ) -> Result<Self, RegionScannerError> {
         if to_x <= from_x {
-            panic!(
-                "Tried to create a Zone with from_x={from_x}, to_x={to_x}. This is invalid - to_x \
-                 must be larger than from_x for the zone to be nonempty. Perhaps the order of \
-                 arguments is wrong?"
-            );
+            return Err(RegionScannerError::EmptyZone {
+                axis: 'x',
+                from: from_x,
+                to: to_x,
+            });
         }
-        Self {
+        if to_z <= from_z {
+            return Err(RegionScannerError::EmptyZone {
+                axis: 'z',
+                from: from_z,
+                to: to_z,
+            });
+        }
+        Ok(Self {
             from_x,
             to_x,
             from_z,
             to_z,
-        }
+        })
     }
 
+    /// The number of regions in the zone, 0 if it's empty (which only a zone
+    /// built from its fields can be).
     pub fn size(&self) -> usize {
-        (self.to_x - self.from_x) as usize * (self.to_z - self.from_z) as usize
+        let width = (self.to_x - self.from_x).max(0) as usize;
+        let height = (self.to_z - self.from_z).max(0) as usize;
+        width * height
     }
 }
 
-impl From<Vec<isize>> for Zone {
-    fn from(vec: Vec<isize>) -> Self {
-        if vec.len() < 4 {
-            panic!("Vector too small to convert to a Zone:{:?}", vec);
-        }
-        Zone::new(vec[0], vec[1], vec[2], vec[3])
+impl TryFrom<Vec<isize>> for Zone {
+    type Error = RegionScannerError;
+
+    fn try_from(vec: Vec<isize>) -> Result<Self, Self::Error> {
+        let [from_x, to_x, from_z, to_z] = vec[..] else {
+            return Err(RegionScannerError::WrongZoneLength(vec));
+        };
+        Zone::new(from_x, to_x, from_z, to_z)
     }
 }
-#[derive(Clone, Copy, Debug)]
+#[test]
+fn test_invalid_input_errors() {
+    assert!(Zone::try_from(vec![-1, 1, -1, 1]).is_ok());
+    assert_eq!(
+        Zone::try_from(vec![1, -1, -1, 1]).err(),
+        Some(RegionScannerError::EmptyZone {
+            axis: 'x',
+            from: 1,
+            to: -1
+        })
+    );
+    assert_eq!(
+        Zone::try_from(vec![-1, 1, 1, -1]).err(),
+        Some(RegionScannerError::EmptyZone {
+            axis: 'z',
+            from: 1,
+            to: -1
+        })
+    );
+    assert!(Zone::new(0, 2, 5, 5).is_err());
+    let zone = Zone {
+        from_x: 0,
+        to_x: 2,
+        from_z: 5,
+        to_z: 3,
+    };
+    assert_eq!(zone.size(), 0);
+    assert!(matches!(
+        Zone::try_from(vec![0, 1, 0]),
+        Err(RegionScannerError::WrongZoneLength(_))
+    ));
+    let mut a = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    let b = BlockFrequencies::empty("minecraft:the_nether".to_owned());
+    assert!(merge_frequencies_into(&mut a, b).is_err());
+    assert!(counts_add_weighted(&mut HashMap::new(), &HashMap::new(), 1.5).is_err());
+    assert!(remove_too_rare(&mut [], 0.).is_err());
+    assert!(remove_too_rare_by_height(&mut [], 1e-7, 0.).is_err());
+}
+
+#[test]
+fn test_determine_version_without_chunks() {
+    use fixtures::*;
+    let dir = TempDir::new("version_without_chunks");
+    assert_eq!(
+        determine_version(&RegionFolder::new(dir.to_path_buf()), None),
+        Err(RegionScannerError::NoReadableChunks)
+    );
+    let write_region = |chunk: Vec<u8>| {
+        let data = region_with(&[((0, 0), chunk)])
+            .into_inner()
+            .unwrap()
+            .into_inner();
+        std::fs::write(dir.join("r.0.0.mca"), data).unwrap();
+    };
+    write_region(b"not nbt".to_vec());
+    assert_eq!(
+        determine_version(&RegionFolder::new(dir.to_path_buf()), None),
+        Err(RegionScannerError::NoReadableChunks)
+    );
+    write_region(ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]).to_bytes());
+    assert_eq!(
+        determine_version(&RegionFolder::new(dir.to_path_buf()), None),
+        Ok(RegionVersion::AtLeast118)
+    );
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RegionVersion {
     Pre118,
     AtLeast118,
 }
+/// The version of the chunk format a chunk uses.
+pub fn chunk_version(chunk: &JavaChunk) -> RegionVersion {
+    match chunk {
+        JavaChunk::Post18(_) => RegionVersion::AtLeast118,
+        JavaChunk::Pre18(_) => RegionVersion::Pre118,
+        JavaChunk::Pre13(_) => RegionVersion::Pre118,
+    }
+}
+
 /// Determines the version of a world by checking the first nonempty region it
-/// finds in the zone provided (or all the regions in the loader).
-pub fn determine_version(loader: &RegionFileLoader, zone: Option<Zone>) -> RegionVersion {
-    use fastanvil::JavaChunk as JavaChunkEnum;
-    for mut region in iter_regions(loader, zone) {
-        if let Some(c) = chunks(&mut region)
-            .find_map(|data| data.and_then(|x| JavaChunkEnum::from_bytes(&x.data).ok()))
-        {
-            return match c {
-                JavaChunkEnum::Post18(_) => RegionVersion::AtLeast118,
-                JavaChunkEnum::Pre18(_) => RegionVersion::Pre118,
-                JavaChunkEnum::Pre13(_) => RegionVersion::Pre118,
-            };
+/// finds in the zone provided (or all the regions of the source).
+pub fn determine_version(
+    source: &impl RegionSource,
+    zone: Option<Zone>,
+) -> Result<RegionVersion, RegionScannerError> {
+    for mut region in iter_regions(source, zone) {
+        if let Some(c) = readable_chunks(&mut region).find_map(|x| parse_chunk(&x.data).ok()) {
+            return Ok(chunk_version(&c));
         }
     }
-    panic!(
-        "Was unable to find a single chunk in a single region in the zone provided that was \
-         readable!"
-    );
+    Err(RegionScannerError::NoReadableChunks)
 }
 
-pub fn region_coords(loader: &RegionFileLoader, zone: Option<Zone>) -> Vec<(RCoord, RCoord)> {
-    if let Some(zone) = zone {
+/// Returns the coordinates of the regions to scan, sorted by x and then z so
+/// that the scan order doesn't depend on the order the OS lists files in. Fails
+/// if no zone is given and the regions can't be listed.
+pub fn region_coords<L: RegionSource + ?Sized>(
+    source: &L,
+    zone: Option<Zone>,
+) -> std::io::Result<Vec<(RCoord, RCoord)>> {
+    let mut coords = if let Some(zone) = zone {
         iproduct!(zone.from_x..zone.to_x, zone.from_z..zone.to_z)
             .map(|(x, z)| (RCoord(x), RCoord(z)))
             .collect()
     } else {
-        loader.list().unwrap()
-    }
+        source.list()?
+    };
+    coords.sort_unstable();
+    Ok(coords)
+}
+
+/// Keeps only the regions whose file name matches `pattern`.
+pub fn filter_regions_by_glob(
+    coords: Vec<(RCoord, RCoord)>,
+    pattern: &glob::Pattern,
+) -> Vec<(RCoord, RCoord)> {
+    coords
+        .into_iter()
+        .filter(|(x, z)| pattern.matches(&format!("r.{}.{}.mca", x.0, z.0)))
+        .collect()
+}
+
+#[test]
+fn test_filter_regions_by_glob() {
+    let coords = iproduct!(-1..=1, -1..=1)
+        .map(|(x, z)| (RCoord(x), RCoord(z)))
+        .collect();
+    let pattern = glob::Pattern::new("r.0.*.mca").unwrap();
+    assert_eq!(
+        filter_regions_by_glob(coords, &pattern),
+        [
+            (RCoord(0), RCoord(-1)),
+            (RCoord(0), RCoord(0)),
+            (RCoord(0), RCoord(1))
+        ]
+    );
 }
 
-/// Iterates over the regions in a zone, or all regions in the loader. Ignores
-/// regions that fail to load, which may or may not be a good idea
-pub fn iter_regions(
-    loader: &RegionFileLoader,
+/// Iterates over the regions in a zone, or all regions of the source. Ignores
+/// regions that fail to load, which may or may not be a good idea. If the
+/// regions can't be listed, there are none.
+pub fn iter_regions<L: RegionSource>(
+    source: &L,
     zone: Option<Zone>,
-) -> impl Iterator<Item = Region<File>> + '_ {
-    region_coords(loader, zone)
+) -> impl Iterator<Item = Region<L::Stream>> + '_ {
+    region_coords(source, zone)
+        .unwrap_or_default()
         .into_iter()
-        .filter_map(|(reg_x, reg_z)| loader.region(reg_x, reg_z).ok().flatten())
+        .filter_map(|(reg_x, reg_z)| source.region(reg_x, reg_z).ok().flatten())
 }
 
-pub fn count_frequencies(
-    region: &mut Region<File>,
+pub fn count_frequencies<R: Read + Seek>(
+    region: &mut Region<R>,
+    region_pos: (isize, isize),
     verbose: bool,
     dimension: &str,
-    proto: ProtoOption,
+    options: &ScanOptions,
 ) -> BlockFrequencies {
-    let counting_results = count_blocks(region, verbose, dimension, proto);
-    let area: u64 = (16 * 16) * counting_results.chunks_counted as u64;
+    frequencies_from_counts(count_blocks(
+        region, region_pos, verbose, dimension, options,
+    ))
+}
+
+/// Turns counts into frequencies, dividing by the number of columns counted.
+/// Every level is divided by all of them, even when some chunks don't reach
+/// that level (e.g. a dimension whose height changed between versions), so a
+/// frequency is always the number of blocks per column counted. That keeps the
+/// frequencies of different levels comparable, and merging them by area exact.
+pub fn frequencies_from_counts(counting_results: BlockCounts) -> BlockFrequencies {
+    let area: u64 = counting_results.columns_counted;
     let mut frequencies: HashMap<String, HashMap<isize, f64>> = HashMap::new();
     let d_area = area as f64;
     for (name, nums) in counting_results.counts {
@@ -222,35 +2038,126 @@ pub fn count_frequencies(
         blocks_counted: counting_results.blocks_counted,
         chunks_counted: counting_results.chunks_counted,
         protochunks_seen: counting_results.protochunks_seen,
+        chunks_failed: counting_results.chunks_failed,
+        statuses: counting_results.statuses,
+        min_y: counting_results.min_y,
+        max_y: counting_results.max_y,
+        version: counting_results.version,
         area,
         dimension: counting_results.dimension,
     }
 }
 
-pub fn merge_frequencies_into(main: &mut BlockFrequencies, other: BlockFrequencies) {
-    for (name, freq) in other.frequencies {
-        match main.frequencies.entry(name) {
-            Entry::Occupied(mut a) => {
-                let total_area: f64 = (main.area + other.area) as f64;
-                let alpha: f64 = main.area as f64 / total_area;
-                counts_add_weighted(a.get_mut(), &freq, alpha);
-            }
-            Entry::Vacant(a) => {
-                a.insert(freq);
-            }
+/// Adds the counts of `other` to `main`. Both have to be from the same
+/// dimension. Unlike merging frequencies, this is exact: merging the counts of
+/// every region and then calling [`frequencies_from_counts`] gives the same
+/// result in whatever order the regions are merged.
+pub fn merge_counts_into(
+    main: &mut BlockCounts,
+    other: BlockCounts,
+) -> Result<(), RegionScannerError> {
+    if main.dimension != other.dimension {
+        return Err(RegionScannerError::DimensionMismatch {
+            main: main.dimension.clone(),
+            other: other.dimension,
+        });
+    }
+    for (name, counts) in other.counts {
+        let main_counts = main.counts.entry(name).or_default();
+        for (y, count) in counts {
+            *main_counts.entry(y).or_default() += count;
         }
     }
+    main.blocks_counted += other.blocks_counted;
+    main.columns_counted += other.columns_counted;
+    main.chunks_counted += other.chunks_counted;
+    main.chunks_failed += other.chunks_failed;
+    main.protochunks_seen += other.protochunks_seen;
+    for (status, count) in other.statuses {
+        *main.statuses.entry(status).or_default() += count;
+    }
+    main.min_y = main.min_y.into_iter().chain(other.min_y).min();
+    main.max_y = main.max_y.into_iter().chain(other.max_y).max();
+    main.version = main.version.or(other.version);
+    Ok(())
+}
+
+/// Merges the frequencies of `other` into `main`, weighting them by area. Both
+/// have to be from the same dimension - see [`merge_across_dimensions`] for
+/// combining different ones.
+pub fn merge_frequencies_into(
+    main: &mut BlockFrequencies,
+    other: BlockFrequencies,
+) -> Result<(), RegionScannerError> {
+    if main.dimension != other.dimension {
+        return Err(RegionScannerError::DimensionMismatch {
+            main: main.dimension.clone(),
+            other: other.dimension,
+        });
+    }
+    merge_frequencies_unchecked(main, other);
+    Ok(())
+}
+
+/// Like [`merge_frequencies_into`], but allows the dimensions to differ. The
+/// result is labelled as dimension `label`, since it no longer describes
+/// either of the original ones.
+pub fn merge_across_dimensions(main: &mut BlockFrequencies, other: BlockFrequencies, label: &str) {
+    main.dimension = label.to_owned();
+    merge_frequencies_unchecked(main, other);
+}
+
+fn merge_frequencies_unchecked(main: &mut BlockFrequencies, other: BlockFrequencies) {
+    // Frequencies are per column, so weighting them by the columns counted
+    // gives the frequencies over all of them, just as if the counts had been
+    // added up. That holds however sparse either side is, as long as a block
+    // missing from one side counts as a frequency of 0 there rather than being
+    // taken as is.
+    let total_area: f64 = (main.area + other.area) as f64;
+    // With no area on either side there's nothing to weight them by, which
+    // would make every frequency NaN, so `main` is kept as it is.
+    if total_area == 0. {
+        return;
+    }
+    let alpha: f64 = main.area as f64 / total_area;
+    let empty = HashMap::new();
+    let mut other_frequencies = other.frequencies;
+    for (name, freq) in &mut main.frequencies {
+        add_weighted(freq, other_frequencies.get(name).unwrap_or(&empty), alpha);
+        other_frequencies.remove(name);
+    }
+    for (name, mut freq) in other_frequencies {
+        add_weighted(&mut freq, &empty, 1. - alpha);
+        main.frequencies.insert(name, freq);
+    }
     main.area += other.area;
     main.blocks_counted += other.blocks_counted;
     main.chunks_counted += other.chunks_counted;
     main.protochunks_seen += other.protochunks_seen;
+    main.chunks_failed += other.chunks_failed;
+    for (status, count) in other.statuses {
+        *main.statuses.entry(status).or_default() += count;
+    }
+    main.min_y = match (main.min_y, other.min_y) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    main.max_y = main.max_y.into_iter().chain(other.max_y).max();
+    main.version = main.version.or(other.version);
+}
+pub fn counts_add_weighted(
+    a: &mut HashMap<isize, f64>,
+    b: &HashMap<isize, f64>,
+    a_weight: f64,
+) -> Result<(), RegionScannerError> {
+    if !(0.0..=1.0).contains(&a_weight) {
+        return Err(RegionScannerError::WeightOutOfRange(a_weight));
+    }
+    add_weighted(a, b, a_weight);
+    Ok(())
 }
-pub fn counts_add_weighted(a: &mut HashMap<isize, f64>, b: &HashMap<isize, f64>, a_weight: f64) {
-    assert!(
-        (0.0..=1.0).contains(&a_weight),
-        "Weight is not in the [0,1] range!"
-    );
 
+fn add_weighted(a: &mut HashMap<isize, f64>, b: &HashMap<isize, f64>, a_weight: f64) {
     let b_weight = 1.0 - a_weight;
     let keys: HashSet<isize> = a.keys().chain(b.keys()).cloned().collect();
     for key in keys {
@@ -259,22 +2166,280 @@ pub fn counts_add_weighted(a: &mut HashMap<isize, f64>, b: &HashMap<isize, f64>,
         a.insert(key, a_val + b_val);
     }
 }
+/// Serializes the frequencies with blocks and levels in order, so that exports
+/// of the same data are identical.
+fn serialize_sorted<S: serde::Serializer>(
+    frequencies: &HashMap<String, HashMap<isize, f64>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let sorted: BTreeMap<&String, BTreeMap<&isize, &f64>> = frequencies
+        .iter()
+        .map(|(name, freqs)| (name, freqs.iter().collect()))
+        .collect();
+    sorted.serialize(serializer)
+}
+
+/// One dimension in the JSON export.
+#[derive(Serialize, Deserialize)]
+struct JsonDimension {
+    version: RegionVersion,
+    results: BlockFrequencies,
+}
+
+/// Generates a JSON file holding the results exactly, along with the metadata
+/// (area, counts and version) needed to merge them with other results later.
+/// Can be read back with [`parse_json`].
+pub fn generate_json(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+) -> Result<String, serde_json::Error> {
+    #[derive(Serialize)]
+    struct JsonDimensionRef<'a> {
+        version: RegionVersion,
+        results: &'a BlockFrequencies,
+    }
+    let dims: Vec<JsonDimensionRef> = frequency_data
+        .iter()
+        .map(|(results, version)| JsonDimensionRef {
+            version: *version,
+            results,
+        })
+        .collect();
+    serde_json::to_string_pretty(&dims)
+}
+
+/// Writes the frequencies as JSON Lines: one `{"dim":..,"block":..,"y":..,"freq":..}`
+/// object per line, dimension by dimension, block by block (sorted by name)
+/// and level by level from the bottom. Unlike the other formats, the output is
+/// written as it's produced rather than assembled in memory first.
+pub fn write_ndjson<W: std::io::Write>(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+    mut writer: W,
+) -> std::io::Result<()> {
+    #[derive(Serialize)]
+    struct Row<'a> {
+        dim: &'a str,
+        block: &'a str,
+        y: isize,
+        freq: f64,
+    }
+    for (freq_data, _version) in frequency_data {
+        for (block, freqs) in freq_data.sorted_blocks() {
+            for (&y, &freq) in freqs.iter().sorted_by_key(|(&y, _)| y) {
+                let row = Row {
+                    dim: &freq_data.dimension,
+                    block,
+                    y,
+                    freq,
+                };
+                serde_json::to_writer(&mut writer, &row)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_write_ndjson() {
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs
+        .frequencies
+        .insert("minecraft:stone".to_owned(), [(1, 1.), (0, 0.5)].into());
+    freqs
+        .frequencies
+        .insert("minecraft:coal_ore".to_owned(), [(0, 0.25)].into());
+    let mut out = vec![];
+    write_ndjson(&[(freqs, RegionVersion::AtLeast118)], &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\"dim\":\"minecraft:overworld\",\"block\":\"minecraft:coal_ore\",\"y\":0,\"freq\":0.25}\n\
+         {\"dim\":\"minecraft:overworld\",\"block\":\"minecraft:stone\",\"y\":0,\"freq\":0.5}\n\
+         {\"dim\":\"minecraft:overworld\",\"block\":\"minecraft:stone\",\"y\":1,\"freq\":1.0}\n"
+    );
+}
+
+/// Reads results written by [`generate_json`].
+pub fn parse_json(json: &str) -> Result<Vec<(BlockFrequencies, RegionVersion)>, serde_json::Error> {
+    let dims: Vec<JsonDimension> = serde_json::from_str(json)?;
+    Ok(dims.into_iter().map(|d| (d.results, d.version)).collect())
+}
+
+/// The start of the files written by [`generate_binary`], followed by a byte
+/// with the version of the format.
+const BINARY_MAGIC: &[u8; 4] = b"RSCB";
+const BINARY_FORMAT_VERSION: u8 = 2;
+
+/// Serializes the results exactly into a compact binary format, which is much
+/// faster to read back with [`parse_binary`] than JSON.
+pub fn generate_binary(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+) -> Result<Vec<u8>, bincode::Error> {
+    let mut res = BINARY_MAGIC.to_vec();
+    res.push(BINARY_FORMAT_VERSION);
+    bincode::serialize_into(&mut res, frequency_data)?;
+    Ok(res)
+}
+
+/// Reads back results written by [`generate_binary`].
+pub fn parse_binary(data: &[u8]) -> Result<Vec<(BlockFrequencies, RegionVersion)>, bincode::Error> {
+    let error = |message: String| Box::new(bincode::ErrorKind::Custom(message));
+    let Some(data) = data.strip_prefix(BINARY_MAGIC) else {
+        return Err(error("not a binary export of RegionScanner".to_owned()));
+    };
+    match data.split_first() {
+        Some((&BINARY_FORMAT_VERSION, data)) => bincode::deserialize(data),
+        Some((version, _)) => Err(error(format!(
+            "unsupported version {version} of the binary format, expected \
+             {BINARY_FORMAT_VERSION}"
+        ))),
+        None => Err(error("the file is empty past its header".to_owned())),
+    }
+}
+
+/// Combines results from several scans, merging the ones for the same
+/// dimension with [`merge_frequencies_into`]. The order of the dimensions'
+/// first appearances is kept.
+pub fn merge_results(
+    results: impl IntoIterator<Item = (BlockFrequencies, RegionVersion)>,
+) -> Vec<(BlockFrequencies, RegionVersion)> {
+    let mut merged: Vec<(BlockFrequencies, RegionVersion)> = vec![];
+    for (freqs, version) in results {
+        match merged
+            .iter_mut()
+            .find(|(f, _)| f.dimension == freqs.dimension)
+        {
+            Some((main, main_version)) => {
+                if *main_version != version {
+                    soft_warn!(
+                        "Merging results for dimension {} from different world versions ({:?} \
+                         and {:?}). Keeping the former.",
+                        main.dimension,
+                        main_version,
+                        version
+                    );
+                }
+                merge_frequencies_unchecked(main, freqs);
+            }
+            None => merged.push((freqs, version)),
+        }
+    }
+    merged
+}
+
+#[test]
+fn test_json_round_trip_and_merge() {
+    let mut a = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    a.frequencies
+        .insert("minecraft:stone".to_owned(), [(0, 0.5), (1, 1.)].into());
+    a.area = 256;
+    let mut b = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    b.frequencies
+        .insert("minecraft:stone".to_owned(), [(0, 1.)].into());
+    b.area = 768;
+    let json = generate_json(&[(a, RegionVersion::AtLeast118)]).unwrap();
+    let mut parsed = parse_json(&json).unwrap();
+    parsed.push((b, RegionVersion::AtLeast118));
+    let merged = merge_results(parsed);
+    assert_eq!(merged.len(), 1);
+    let (merged, _) = &merged[0];
+    assert_eq!(merged.area, 1024);
+    assert_eq!(
+        merged.frequencies["minecraft:stone"],
+        [(0, 0.875), (1, 0.25)].into()
+    );
+}
+
+#[test]
+fn test_binary_round_trip() {
+    let mut freqs = BlockFrequencies::empty("minecraft:the_nether".to_owned());
+    freqs.frequencies.insert(
+        "minecraft:ancient_debris".to_owned(),
+        [(15, 1. / 3.)].into(),
+    );
+    freqs.area = 4096;
+    freqs.chunks_counted = 16;
+    freqs.min_y = Some(0);
+    freqs.statuses.insert("minecraft:full".to_owned(), 16);
+    let binary = generate_binary(&[(freqs, RegionVersion::Pre118)]).unwrap();
+    let parsed = parse_binary(&binary).unwrap();
+    assert_eq!(parsed.len(), 1);
+    let (parsed, version) = &parsed[0];
+    assert_eq!(*version, RegionVersion::Pre118);
+    assert_eq!(parsed.frequencies["minecraft:ancient_debris"][&15], 1. / 3.);
+    assert_eq!((parsed.area, parsed.chunks_counted), (4096, 16));
+    assert_eq!((parsed.min_y, parsed.statuses.len()), (Some(0), 1));
+
+    assert!(parse_binary(b"[]").is_err());
+    let mut newer = binary;
+    newer[4] += 1;
+    assert!(parse_binary(&newer).is_err());
+}
+
 #[allow(non_snake_case)]
 pub fn generate_JER_json(
     frequency_data: &[(BlockFrequencies, RegionVersion)],
 ) -> Result<String, serde_json::Error> {
-    let mut distrib_list: Vec<BlockJERDistributionData> = vec![];
+    generate_JER_json_merged_into(vec![], None, frequency_data)
+}
+
+/// Like [`generate_JER_json`], but merges the results into the `existing`
+/// entries of a previous JER export. The entries of dimensions that aren't in
+/// `frequency_data` are kept as they are. For the dimensions in both, the
+/// distributions of every block are parsed back with [`parse_distrib`] and
+/// merged with the new ones like [`merge_frequencies_into`] does, a block
+/// missing from either side counting as a frequency of 0 there. JER files
+/// don't record the area the frequencies were counted over, so it's given as
+/// `existing_area`; without it, the existing entries weigh as much as the new
+/// results of each dimension.
+#[allow(non_snake_case)]
+pub fn generate_JER_json_merged_into(
+    existing: Vec<BlockJERDistributionData>,
+    existing_area: Option<u64>,
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+) -> Result<String, serde_json::Error> {
+    let is_rescanned = |dim: &str| frequency_data.iter().any(|(f, _)| f.dimension == dim);
+    let (merged, mut distrib_list): (Vec<_>, Vec<_>) = existing
+        .into_iter()
+        .partition(|entry| is_rescanned(&entry.dim));
     for (freq_data, version) in frequency_data {
-        for (name, freqs) in &freq_data.frequencies {
+        let offset = jer_offset(*version, freq_data.min_y);
+        // JER only knows blocks, not their states.
+        let mut blocks = BlockFrequencies {
+            frequencies: freq_data
+                .sorted_blocks_without_properties()
+                .into_iter()
+                .collect(),
+            ..BlockFrequencies::empty(freq_data.dimension.clone())
+        };
+        blocks.area = freq_data.area;
+        let old_entries: Vec<_> = merged
+            .iter()
+            .filter(|entry| entry.dim == freq_data.dimension)
+            .cloned()
+            .collect();
+        if !old_entries.is_empty() {
+            info!(
+                "Merging {} existing entries into dimension {}.",
+                old_entries.len(),
+                freq_data.dimension
+            );
+            for mut old in frequencies_from_JER(&old_entries, |_| offset) {
+                old.area = existing_area.unwrap_or(freq_data.area);
+                merge_frequencies_unchecked(&mut blocks, old);
+            }
+        }
+        let mut sorted: Vec<_> = blocks.frequencies.into_iter().collect();
+        sorted.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        for (name, freqs) in sorted {
             if freqs.is_empty() {
                 continue;
             }
-            let distrib = freqs_to_distrib(freqs, *version, &freq_data.dimension, name);
+            let distrib = freqs_to_distrib(&freqs, *version, offset, &freq_data.dimension, &name);
             if distrib.is_empty() {
                 continue;
             }
             distrib_list.push(BlockJERDistributionData {
-                block: name.clone(),
+                block: name,
                 distrib,
                 silktouch: false,
                 dim: freq_data.dimension.clone().to_string(),
@@ -284,76 +2449,488 @@ pub fn generate_JER_json(
     serde_json::to_string_pretty(&distrib_list)
 }
 
-pub fn generate_tall_csv(frequency_data: &[(BlockFrequencies, RegionVersion)]) -> String {
+/// Generates the tall CSV. If `with_intervals` is set, two extra columns
+/// `freq_low,freq_high` hold the bounds of the 95% confidence interval of each
+/// frequency, see [`wilson_interval`]. If `with_metadata` is set, the header is
+/// preceded by a comment line per dimension, which [`parse_tall_csv`] needs to
+/// read the results back.
+pub fn generate_tall_csv(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+    with_intervals: bool,
+    with_metadata: bool,
+) -> String {
     let mut res = String::new();
-    res.write_str("dim,block,level,freq\n").unwrap();
+    if with_metadata {
+        for (freq_data, version) in frequency_data {
+            write!(
+                res,
+                "# dim={} version={:?} area={} blocks_counted={} chunks_counted={} protochunks_seen={}",
+                freq_data.dimension,
+                version,
+                freq_data.area,
+                freq_data.blocks_counted,
+                freq_data.chunks_counted,
+                freq_data.protochunks_seen
+            )
+            .unwrap();
+            if let Some(min_y) = freq_data.min_y {
+                write!(res, " min_y={}", min_y).unwrap();
+            }
+            if freq_data.chunks_failed > 0 {
+                write!(res, " chunks_failed={}", freq_data.chunks_failed).unwrap();
+            }
+            res.push('\n');
+        }
+    }
+    if with_intervals {
+        res.write_str("dim,block,level,freq,freq_low,freq_high\n")
+            .unwrap();
+    } else {
+        res.write_str("dim,block,level,freq\n").unwrap();
+    }
     for (freq_data, _version) in frequency_data {
-        for (name, freqs) in &freq_data.frequencies {
+        for (name, freqs) in freq_data.sorted_blocks() {
             if freqs.is_empty() {
                 continue;
             }
             let min_y = *freqs.keys().min().unwrap();
             let max_y = *freqs.keys().max().unwrap();
             for y in min_y..=max_y {
-                res.write_str(&format!(
-                    "{},{},{},{}\n",
-                    freq_data.dimension,
-                    name,
-                    y,
-                    freqs.get(&y).unwrap_or(&0f64)
-                ))
-                .expect("Error when assembling CSV");
+                let freq = *freqs.get(&y).unwrap_or(&0f64);
+                res.write_str(&format!("{},{},{},{}", freq_data.dimension, name, y, freq))
+                    .expect("Error when assembling CSV");
+                if with_intervals {
+                    let (low, high) = wilson_interval(freq, freq_data.area, Z_95);
+                    res.write_str(&format!(",{},{}", low, high))
+                        .expect("Error when assembling CSV");
+                }
+                res.push('\n');
             }
         }
     }
     res
 }
 
+/// A malformed line encountered by [`parse_tall_csv`].
+#[derive(Debug)]
+pub struct CsvParseError {
+    /// The line number, starting from 1.
+    pub line: usize,
+    pub reason: String,
+}
+
+impl std::fmt::Display for CsvParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for CsvParseError {}
+
+/// Reads results written by [`generate_tall_csv`] with metadata back, using the
+/// metadata comment lines at its start. The confidence interval columns are
+/// ignored if present.
+pub fn parse_tall_csv(csv: &str) -> Result<Vec<(BlockFrequencies, RegionVersion)>, CsvParseError> {
+    let mut results: Vec<(BlockFrequencies, RegionVersion)> = vec![];
+    for (i, line) in csv.lines().enumerate() {
+        let error = |reason: String| CsvParseError {
+            line: i + 1,
+            reason,
+        };
+        let line = line.trim();
+        if let Some(metadata) = line.strip_prefix('#') {
+            results.push(parse_csv_metadata(metadata).map_err(error)?);
+            continue;
+        }
+        if line.is_empty() || line.starts_with("dim,") {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        let [dim, block, level, freq, ..] = fields[..] else {
+            return Err(error(format!(
+                "expected at least 4 fields, found {}",
+                fields.len()
+            )));
+        };
+        let level: isize = level
+            .parse()
+            .map_err(|_| error(format!("invalid level {:?}", level)))?;
+        let freq: f64 = freq
+            .parse()
+            .map_err(|_| error(format!("invalid frequency {:?}", freq)))?;
+        let Some((freq_data, _)) = results.iter_mut().find(|(f, _)| f.dimension == dim) else {
+            return Err(error(format!(
+                "no metadata line for dimension {} - was the file exported without --csv-metadata?",
+                dim
+            )));
+        };
+        // The export fills the gaps between levels with zeroes, which weren't
+        // in the original frequencies.
+        if freq != 0. {
+            freq_data
+                .frequencies
+                .entry(block.to_owned())
+                .or_default()
+                .insert(level, freq);
+        }
+    }
+    Ok(results)
+}
+
+fn parse_csv_metadata(metadata: &str) -> Result<(BlockFrequencies, RegionVersion), String> {
+    let mut freq_data = BlockFrequencies::empty(String::new());
+    let mut version = None;
+    for pair in metadata.split_whitespace() {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("malformed metadata {:?}", pair))?;
+        let invalid = |_| format!("invalid value for {}: {:?}", key, value);
+        match key {
+            "dim" => freq_data.dimension = value.to_owned(),
+            "version" => {
+                version = Some(match value {
+                    "Pre118" => RegionVersion::Pre118,
+                    "AtLeast118" => RegionVersion::AtLeast118,
+                    _ => return Err(format!("unknown version {:?}", value)),
+                })
+            }
+            "area" => freq_data.area = value.parse().map_err(invalid)?,
+            "blocks_counted" => freq_data.blocks_counted = value.parse().map_err(invalid)?,
+            "chunks_counted" => freq_data.chunks_counted = value.parse().map_err(invalid)?,
+            "protochunks_seen" => freq_data.protochunks_seen = value.parse().map_err(invalid)?,
+            "min_y" => freq_data.min_y = Some(value.parse().map_err(invalid)?),
+            "chunks_failed" => freq_data.chunks_failed = value.parse().map_err(invalid)?,
+            _ => {}
+        }
+    }
+    if freq_data.dimension.is_empty() {
+        return Err("metadata without a dimension".to_owned());
+    }
+    let version = version.ok_or("metadata without a version")?;
+    Ok((freq_data, version))
+}
+
+#[test]
+fn test_tall_csv_round_trip() {
+    let mut freqs = BlockFrequencies::empty("minecraft:the_nether".to_owned());
+    freqs.frequencies.insert(
+        "minecraft:ancient_debris".to_owned(),
+        [(8, 0.1), (12, 1. / 3.)].into(),
+    );
+    freqs.area = 3 * 1024;
+    freqs.blocks_counted = 1234;
+    freqs.chunks_counted = 3;
+    let data = [(freqs, RegionVersion::Pre118)];
+    // Without the metadata, the file is a plain CSV that can't be read back.
+    let plain = generate_tall_csv(&data, false, false);
+    assert!(plain.starts_with("dim,block,level,freq\n"));
+    assert!(parse_tall_csv(&plain).is_err());
+    let csv = generate_tall_csv(&data, true, true);
+    assert!(csv.starts_with("# dim=minecraft:the_nether version=Pre118 area=3072 "));
+    let parsed = parse_tall_csv(&csv).unwrap();
+    assert_eq!(parsed.len(), 1);
+    let (parsed, version) = &parsed[0];
+    assert_eq!(*version, RegionVersion::Pre118);
+    assert_eq!(parsed.dimension, "minecraft:the_nether");
+    assert_eq!(
+        (parsed.area, parsed.blocks_counted, parsed.chunks_counted),
+        (3072, 1234, 3)
+    );
+    assert_eq!(
+        parsed.frequencies["minecraft:ancient_debris"],
+        [(8, 0.1), (12, 1. / 3.)].into()
+    );
+    assert!(
+        parse_tall_csv("dim,block,level,freq\nminecraft:overworld,minecraft:stone,0,1\n").is_err()
+    );
+}
+
+/// The z-score of a two-sided 95% confidence interval.
+pub const Z_95: f64 = 1.959964;
+
+/// Computes the Wilson score interval for a frequency observed over `area`
+/// positions (every position of a level is one trial, and the frequency is the
+/// share of successes). Unlike the normal approximation this stays inside
+/// [0,1] and behaves well for the tiny frequencies typical of ores.
+pub fn wilson_interval(freq: f64, area: u64, z: f64) -> (f64, f64) {
+    if area == 0 {
+        return (0., 1.);
+    }
+    let n = area as f64;
+    let z2 = z * z;
+    let denominator = 1. + z2 / n;
+    let center = (freq + z2 / (2. * n)) / denominator;
+    let margin = z * (freq * (1. - freq) / n + z2 / (4. * n * n)).sqrt() / denominator;
+    ((center - margin).max(0.), (center + margin).min(1.))
+}
+
+#[test]
+fn test_wilson_interval() {
+    let (low, high) = wilson_interval(0.5, 100, Z_95);
+    assert!((low - 0.4038).abs() < 1e-4 && (high - 0.5962).abs() < 1e-4);
+    // A level where the block was never seen still gets a nonzero upper bound.
+    let (low, high) = wilson_interval(0., 256, Z_95);
+    assert_eq!(low, 0.);
+    assert!(high > 0. && high < 0.02);
+}
+
+/// JER for 1.18+ stores the levels with an offset of 64 - that way levels start
+/// from 0 inclusive regardless of version. Dimensions going deeper than that
+/// (which is possible since 1.18) are offset by their depth instead, given as
+/// `min_y`, so that their bottom is still level 0.
+pub fn jer_offset(version: RegionVersion, min_y: Option<isize>) -> isize {
+    match version {
+        RegionVersion::Pre118 => 0,
+        RegionVersion::AtLeast118 => isize::max(64, -min_y.unwrap_or(0)),
+    }
+}
+
+/// The highest level JER accepts, as it loads the frequencies into an array
+/// of size 320. Before 1.18, the top of the world is the limit.
+fn jer_max_height(version: RegionVersion, offset: isize) -> isize {
+    match version {
+        RegionVersion::Pre118 => 255,
+        RegionVersion::AtLeast118 => 319 - offset,
+    }
+}
+
+/// The inverse of the distribution rendering done for the JER export: parses a
+/// `"level,freq;level,freq;"` string back into frequencies by y, undoing the
+/// level `offset` (see [`jer_offset`]). Returns `None` if the string is
+/// malformed.
+pub fn parse_distrib(distrib: &str, offset: isize) -> Option<HashMap<isize, f64>> {
+    distrib
+        .split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (level, freq) = entry.split_once(',')?;
+            let level: isize = level.trim().parse().ok()?;
+            let freq: f64 = freq.trim().parse().ok()?;
+            Some((level - offset, freq))
+        })
+        .collect()
+}
+
+#[test]
+fn test_distrib_round_trip() {
+    let freqs: HashMap<isize, f64> = [(-64, 0.5), (-60, 0.25), (10, 1e-7)].into();
+    let distrib = freqs_to_distrib(
+        &freqs,
+        RegionVersion::AtLeast118,
+        64,
+        "test:dim",
+        "test:block",
+    );
+    let parsed = parse_distrib(&distrib, 64).unwrap();
+    // Levels between the ones present are rendered as explicit zeroes.
+    assert_eq!(parsed.len(), 75);
+    for (y, freq) in parsed {
+        assert_eq!(freq, *freqs.get(&y).unwrap_or(&0.));
+    }
+    assert!(parse_distrib("1,0.5;2,", 0).is_none());
+}
+
+#[test]
+fn test_distrib_deep_dimension() {
+    // A custom dimension with its floor at y=-128.
+    let offset = jer_offset(RegionVersion::AtLeast118, Some(-128));
+    assert_eq!(offset, 128);
+    assert_eq!(jer_offset(RegionVersion::AtLeast118, Some(-64)), 64);
+    assert_eq!(jer_offset(RegionVersion::AtLeast118, Some(0)), 64);
+    let freqs: HashMap<isize, f64> = [(-128, 0.5), (-100, 0.25), (191, 0.1), (250, 1.)].into();
+    let distrib = freqs_to_distrib(
+        &freqs,
+        RegionVersion::AtLeast118,
+        offset,
+        "test:deep",
+        "test:block",
+    );
+    assert!(distrib.starts_with("0,0.5;"));
+    // The levels still have to fit in JER's array of 320.
+    assert!(distrib.ends_with("319,0.1;"));
+    let parsed = parse_distrib(&distrib, offset).unwrap();
+    assert_eq!(parsed[&-128], 0.5);
+    assert_eq!(parsed[&-100], 0.25);
+    assert!(!parsed.contains_key(&250));
+}
+
+#[test]
+fn test_jer_merged_into() {
+    let entry = |block: &str, distrib: &str, dim: &str| BlockJERDistributionData {
+        block: block.to_owned(),
+        distrib: distrib.to_owned(),
+        silktouch: false,
+        dim: dim.to_owned(),
+    };
+    let existing = vec![
+        entry("minecraft:stone", "64,1;65,0.5;", "minecraft:overworld"),
+        entry("minecraft:dirt", "64,0.5;", "minecraft:overworld"),
+        entry("minecraft:netherrack", "0,1;", "minecraft:the_nether"),
+    ];
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.area = 768;
+    freqs
+        .frequencies
+        .insert("minecraft:stone".to_owned(), [(0, 0.5)].into());
+    freqs
+        .frequencies
+        .insert("minecraft:gravel".to_owned(), [(1, 1.)].into());
+    let results = [(freqs, RegionVersion::AtLeast118)];
+
+    let merged = |existing_area| {
+        let json = generate_JER_json_merged_into(existing.clone(), existing_area, &results);
+        let entries: Vec<BlockJERDistributionData> = serde_json::from_str(&json.unwrap()).unwrap();
+        entries
+            .into_iter()
+            .map(|e| {
+                let freqs = parse_distrib(&e.distrib, 64).unwrap();
+                ((e.dim, e.block), freqs)
+            })
+            .collect::<HashMap<_, _>>()
+    };
+    let at = |entries: &HashMap<(String, String), HashMap<isize, f64>>,
+              dim: &str,
+              block: &str,
+              y: isize| {
+        entries[&(dim.to_owned(), block.to_owned())]
+            .get(&y)
+            .copied()
+            .unwrap_or(0.)
+    };
+    let overworld = "minecraft:overworld";
+
+    // Weighted equally.
+    let entries = merged(None);
+    assert_eq!(entries.len(), 4);
+    assert_eq!(at(&entries, overworld, "minecraft:stone", 0), 0.75);
+    assert_eq!(at(&entries, overworld, "minecraft:stone", 1), 0.25);
+    assert_eq!(at(&entries, overworld, "minecraft:dirt", 0), 0.25);
+    assert_eq!(at(&entries, overworld, "minecraft:gravel", 1), 0.5);
+    // The dimension that wasn't scanned again is kept as it is.
+    assert_eq!(
+        at(
+            &entries,
+            "minecraft:the_nether",
+            "minecraft:netherrack",
+            -64
+        ),
+        1.
+    );
+
+    // Weighted by area, the existing entries having a third of the new area.
+    let entries = merged(Some(256));
+    assert_eq!(at(&entries, overworld, "minecraft:stone", 0), 0.625);
+    assert_eq!(at(&entries, overworld, "minecraft:dirt", 0), 0.125);
+    assert_eq!(at(&entries, overworld, "minecraft:gravel", 1), 0.75);
+
+    // A scan with no area, merged into entries without one either, is kept as
+    // it is instead of turning into NaN.
+    let mut empty = BlockFrequencies::empty(overworld.to_owned());
+    empty
+        .frequencies
+        .insert("minecraft:stone".to_owned(), [(0, 0.5)].into());
+    let json = generate_JER_json_merged_into(
+        existing.clone(),
+        None,
+        &[(empty, RegionVersion::AtLeast118)],
+    )
+    .unwrap();
+    assert!(!json.contains("NaN"));
+    let entries: Vec<BlockJERDistributionData> = serde_json::from_str(&json).unwrap();
+    let overworld_entries: Vec<_> = entries.iter().filter(|e| e.dim == overworld).collect();
+    assert_eq!(overworld_entries.len(), 1);
+    assert_eq!(overworld_entries[0].block, "minecraft:stone");
+    let freqs = parse_distrib(&overworld_entries[0].distrib, 64).unwrap();
+    assert_eq!(freqs[&0], 0.5);
+    assert!(freqs.values().all(|&freq| freq == 0.5 || freq == 0.));
+}
+
+/// Converts the entries of a JER world-gen.json back into frequencies, one
+/// [`BlockFrequencies`] per dimension. JER files don't record the world
+/// version or depth, so `offset_of` has to supply the level offset (see
+/// [`jer_offset`]) of each dimension to undo it. The returned frequencies have
+/// no area or counts attached.
+#[allow(non_snake_case)]
+pub fn frequencies_from_JER(
+    entries: &[BlockJERDistributionData],
+    offset_of: impl Fn(&str) -> isize,
+) -> Vec<BlockFrequencies> {
+    let mut by_dim: Vec<BlockFrequencies> = vec![];
+    for entry in entries {
+        let Some(freqs) = parse_distrib(&entry.distrib, offset_of(&entry.dim)) else {
+            soft_warn!(
+                "Skipping the malformed distribution of {} in dimension {}.",
+                entry.block,
+                entry.dim
+            );
+            continue;
+        };
+        let dim_freqs = match by_dim.iter_mut().position(|f| f.dimension == entry.dim) {
+            Some(i) => &mut by_dim[i],
+            None => {
+                by_dim.push(BlockFrequencies::empty(entry.dim.clone()));
+                by_dim.last_mut().unwrap()
+            }
+        };
+        dim_freqs.frequencies.insert(entry.block.clone(), freqs);
+    }
+    by_dim
+}
+
 fn freqs_to_distrib(
     freqs: &HashMap<isize, f64>,
     version: RegionVersion,
+    offset: isize,
     dimension: &str,
     name: &str,
 ) -> String {
     assert!(!freqs.is_empty(), "Got an empty distribution!");
     let mut distrib = String::new();
 
-    // JER for 1.18+ stores the levels with an offset of 64 - that way levels start
-    // from 0 inclusive regardless of version.
-    let offset = match version {
-        RegionVersion::Pre118 => 0,
-        RegionVersion::AtLeast118 => 64,
-    };
     // We always mention all values from the very bottom of the world, otherwise JER
     // plots for rare ores can look bad.
     let depth_limit = -offset;
-    let max_jer_height = 255;
+    let max_jer_height = jer_max_height(version, offset);
     let min_y = *freqs.keys().min().unwrap();
     let max_y = *freqs.keys().max().unwrap();
 
     // It *is* possible for a modded dimension to be below that limit.
     // However, for JER export we ignore it, since JER won't be able to
     // render it as a plot anyway. See issue #11 for details.
-    // Similarly, we discard data about y=255, as the JER loads the frequencies as
-    // an array of size 320 and will raise an error on bigger ones. See issue #16.
+    // Similarly, we discard data above level 319 (y=255 in 1.18+ worlds), as the JER
+    // loads the frequencies as an array of size 320 and will raise an error on
+    // bigger ones. See issue #16.
     static DIMENSIONS_LIMITS_EXCEEDED: Mutex<Vec<String>> = Mutex::new(vec![]);
     if min_y < depth_limit || max_y > max_jer_height {
+        // The summed frequency of the omitted levels is the average number of
+        // blocks per column that won't make it into the export.
+        let dropped: f64 = freqs
+            .iter()
+            .filter(|(&y, _)| y < depth_limit || y > max_jer_height)
+            .map(|(_, freq)| freq)
+            .sum();
+        let total: f64 = freqs.values().sum();
+        let dropped_share = if total > 0. { dropped / total } else { 0. };
         let mut cache = DIMENSIONS_LIMITS_EXCEEDED.lock().unwrap();
         if !cache.iter().any(|x| x == dimension) {
-            warn!(
+            soft_warn!(
                 "Block kind {name} for dimension {dimension} exceeded the dimension height limits \
                  of {depth_limit} to {max_jer_height}: the lowest block of this kind was at \
                  y={min_y} and the highest at y={max_y}. Frequencies outside the height limits \
-                 will be omitted when exporting, as JER doesn't support them. Use another export \
-                 format to avoid this limitation. Further occurences of this warning for this \
-                 dimension will be at level TRACE."
+                 will be omitted when exporting, as JER doesn't support them - for this block \
+                 that's {dropped:.3e} blocks per column, or {:.2}% of all its occurences. Use \
+                 another export format to avoid this limitation. Further occurences of this \
+                 warning for this dimension will be at level TRACE.",
+                dropped_share * 100.
             );
             cache.push(dimension.to_owned());
         } else {
             trace!(
                 "Block kind {name} for dimension {dimension} exceeded the dimension height limits \
                  of {depth_limit} to {max_jer_height}: the lowest block of this kind was at \
-                 y={min_y} and the highest at y={max_y}."
+                 y={min_y} and the highest at y={max_y}. Omitting {dropped:.3e} blocks per \
+                 column ({:.2}% of its occurences).",
+                dropped_share * 100.
             );
         }
     }
@@ -364,12 +2941,90 @@ fn freqs_to_distrib(
     }
     distrib
 }
-#[derive(Serialize, Deserialize)]
+/// Parses a list of block IDs to count. Accepts either a JER `world-gen.json`
+/// (in which case the `block` field of every entry is used) or a plain text
+/// file with one block ID per line. Empty lines and lines starting with `#`
+/// are ignored in the latter.
+pub fn parse_block_whitelist(contents: &str) -> HashSet<String> {
+    if let Ok(entries) = serde_json::from_str::<Vec<BlockJERDistributionData>>(contents) {
+        return entries.into_iter().map(|entry| entry.block).collect();
+    }
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect()
+}
+
+#[test]
+fn test_parse_block_whitelist() {
+    let expected = HashSet::from(["minecraft:iron_ore".to_owned(), "mymod:tin_ore".to_owned()]);
+    // Duplicates are listed once, and blank lines and comments are skipped.
+    let text = "# ores\nminecraft:iron_ore\n\n  mymod:tin_ore  \nminecraft:iron_ore\n";
+    assert_eq!(parse_block_whitelist(text), expected);
+    // A JER file lists a block once per dimension.
+    let jer = r#"[
+        {"block": "minecraft:iron_ore", "distrib": "0,0.1;", "silktouch": false, "dim": "a"},
+        {"block": "mymod:tin_ore", "distrib": "0,0.1;", "silktouch": false, "dim": "a"},
+        {"block": "minecraft:iron_ore", "distrib": "0,0.2;", "silktouch": false, "dim": "b"}
+    ]"#;
+    assert_eq!(parse_block_whitelist(jer), expected);
+    // JSON that isn't a JER file is read as lines, none of them block IDs the
+    // scan would find, rather than as an empty list.
+    let bad_jer = r#"[{"block": "minecraft:iron_ore"}]"#;
+    assert_eq!(
+        parse_block_whitelist(bad_jer),
+        HashSet::from([bad_jer.to_owned()])
+    );
+    assert!(parse_block_whitelist("\n# nothing\n   \n").is_empty());
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct BlockJERDistributionData {
-    block: String,
-    distrib: String,
-    silktouch: bool,
-    dim: String,
+    pub block: String,
+    pub distrib: String,
+    pub silktouch: bool,
+    pub dim: String,
+}
+
+/// Turns a dimension or block ID into something usable as a file name.
+/// Characters that aren't safe in file names on every platform, the
+/// namespace separator included, become underscores.
+pub fn sanitize_file_name(id: &str) -> String {
+    id.chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Turns a dimension ID into the part of the file names naming it when each
+/// dimension gets a file of its own, keeping the namespace apart with a dot,
+/// e.g. `minecraft.the_nether`. Otherwise like [`sanitize_file_name`].
+pub fn dimension_file_stem(dimension: &str) -> String {
+    sanitize_file_name(&dimension.replace(':', "."))
+}
+
+#[test]
+fn test_sanitize_file_name() {
+    assert_eq!(
+        sanitize_file_name("minecraft:overworld"),
+        "minecraft_overworld"
+    );
+    assert_eq!(
+        sanitize_file_name("mymod:caves/deep\\er?"),
+        "mymod_caves_deep_er_"
+    );
+    assert_eq!(
+        dimension_file_stem("minecraft:the_nether"),
+        "minecraft.the_nether"
+    );
+    assert_eq!(
+        dimension_file_stem("mymod:caves/deep er"),
+        "mymod.caves_deep_er"
+    );
 }
 
 pub fn get_path_from_dimension(dimension: &str) -> Option<PathBuf> {
@@ -393,6 +3048,71 @@ pub fn get_path_from_dimension(dimension: &str) -> Option<PathBuf> {
     }
 }
 
+/// How a server lays out the folders of the dimensions of a world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ServerType {
+    /// Everything is in the world folder, as in singleplayer and on vanilla
+    /// servers
+    #[default]
+    Vanilla,
+    /// The nether and the end are in folders next to the world folder, like
+    /// `world_nether/DIM-1/region`, as on Bukkit, Spigot and Paper servers
+    Bukkit,
+}
+
+/// The folder that the path from [`get_path_from_dimension`] (or
+/// [`get_entities_path_from_dimension`]) is relative to, for a world saved in
+/// `save`.
+pub fn get_save_folder_for_dimension(save: &Path, dimension: &str, server: ServerType) -> PathBuf {
+    let suffix = match dimension {
+        "minecraft:the_nether" => "_nether",
+        "minecraft:the_end" => "_the_end",
+        _ => return save.to_path_buf(),
+    };
+    if server == ServerType::Vanilla {
+        return save.to_path_buf();
+    }
+    // The world folder has to be named, which '.' for example isn't.
+    let save = std::path::absolute(save).unwrap_or_else(|_| save.to_path_buf());
+    let Some(name) = save.file_name() else {
+        return save;
+    };
+    let mut sibling = name.to_owned();
+    sibling.push(suffix);
+    save.with_file_name(sibling)
+}
+
+#[test]
+fn test_bukkit_save_folders() {
+    let save = Path::new("/srv/mc/world");
+    for (dimension, server, folder) in [
+        ("minecraft:overworld", ServerType::Bukkit, "/srv/mc/world"),
+        (
+            "minecraft:the_nether",
+            ServerType::Bukkit,
+            "/srv/mc/world_nether",
+        ),
+        (
+            "minecraft:the_end",
+            ServerType::Bukkit,
+            "/srv/mc/world_the_end",
+        ),
+        ("mymod:mining", ServerType::Bukkit, "/srv/mc/world"),
+        ("minecraft:the_nether", ServerType::Vanilla, "/srv/mc/world"),
+    ] {
+        assert_eq!(
+            get_save_folder_for_dimension(save, dimension, server),
+            Path::new(folder)
+        );
+    }
+}
+
+/// Resolves a dimension to the folder of its entity region files, which are
+/// next to the block ones since 1.17.
+pub fn get_entities_path_from_dimension(dimension: &str) -> Option<PathBuf> {
+    get_path_from_dimension(dimension).map(|path| path.with_file_name("entities"))
+}
+
 #[test]
 fn test_dim_to_path_conversions() {
     let correct_results = [
@@ -430,13 +3150,178 @@ fn test_dim_to_path_conversions() {
     }
 }
 
-pub fn remove_too_rare(results_by_dim: &mut [(BlockFrequencies, RegionVersion)], cutoff: f64) {
+/// Finds the dimensions of a world from the region folders in it, the reverse
+/// of [`get_path_from_dimension`]: `region`, `DIM-1/region`, `DIM1/region` and
+/// every `dimensions/<namespace>/<name>/region`. The vanilla dimensions come
+/// first, then the others sorted by ID.
+pub fn discover_dimensions(save_path: &Path) -> Vec<(String, PathBuf)> {
+    let mut found = vec![];
+    for dimension in [
+        "minecraft:overworld",
+        "minecraft:the_nether",
+        "minecraft:the_end",
+    ] {
+        let path = save_path.join(get_path_from_dimension(dimension).expect("a valid ID"));
+        if path.is_dir() {
+            found.push((dimension.to_owned(), path));
+        }
+    }
+    let subfolders = |folder: &Path| -> Vec<(String, PathBuf)> {
+        let Ok(entries) = std::fs::read_dir(folder) else {
+            return vec![];
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| {
+                (
+                    entry.file_name().to_string_lossy().into_owned(),
+                    entry.path(),
+                )
+            })
+            .collect()
+    };
+    let mut modded = vec![];
+    for (namespace, namespace_path) in subfolders(&save_path.join("dimensions")) {
+        for (name, dimension_path) in subfolders(&namespace_path) {
+            let path = dimension_path.join("region");
+            // The vanilla dimensions never have folders here, but are skipped
+            // in case some tool made one.
+            let dimension = format!("{namespace}:{name}");
+            if path.is_dir() && !found.iter().any(|(known, _)| *known == dimension) {
+                modded.push((dimension, path));
+            }
+        }
+    }
+    modded.sort_unstable();
+    found.extend(modded);
+    found
+}
+
+#[test]
+fn test_discover_dimensions() {
+    let save = fixtures::TempDir::new("discover_dimensions");
+    for folder in [
+        "region",
+        "DIM1/region",
+        "dimensions/mymod/mining/region",
+        "dimensions/appliedenergistics2/spatial_storage/region",
+        // Not a dimension, as it has no region folder.
+        "dimensions/mymod/empty",
+    ] {
+        std::fs::create_dir_all(save.join(folder)).unwrap();
+    }
+    let found = discover_dimensions(&save);
+    let ids: Vec<&str> = found.iter().map(|(id, _)| id.as_str()).collect();
+    assert_eq!(
+        ids,
+        [
+            "minecraft:overworld",
+            "minecraft:the_end",
+            "appliedenergistics2:spatial_storage",
+            "mymod:mining"
+        ]
+    );
+    for (id, path) in &found {
+        assert_eq!(*path, save.join(get_path_from_dimension(id).unwrap()));
+    }
+}
+
+#[test]
+fn test_remove_too_rare_height() {
+    let mut freqs = BlockFrequencies::empty("test:tall".to_owned());
+    freqs.frequencies = HashMap::from([
+        (
+            "minecraft:air".to_owned(),
+            HashMap::from([(-320, 0.5), (319, 1.)]),
+        ),
+        // 0.02 / 255 is above 5e-5, but 0.02 / 640 isn't.
+        ("minecraft:iron_ore".to_owned(), HashMap::from([(0, 0.02)])),
+    ]);
+    assert_eq!(detected_world_height(&freqs), Some(640.));
+    // The range of the sections counts, not the levels that were counted,
+    // which --y-range may have narrowed.
+    freqs.min_y = Some(-64);
+    freqs.max_y = Some(319);
+    assert_eq!(detected_world_height(&freqs), Some(384.));
+    let mut results = [(freqs, RegionVersion::AtLeast118)];
+    remove_too_rare(&mut results, 5e-5).unwrap();
+    assert_eq!(results[0].0.frequencies.len(), 2);
+    remove_too_rare_by_height(&mut results, 5e-5, 640.).unwrap();
+    assert_eq!(
+        results[0].0.frequencies.keys().collect_vec(),
+        ["minecraft:air"]
+    );
+    assert_eq!(
+        detected_world_height(&BlockFrequencies::empty("test:empty".to_owned())),
+        None
+    );
+}
+
+#[test]
+fn test_dim_to_entities_path_conversions() {
+    for (inp, out) in [
+        ("minecraft:overworld", "entities"),
+        ("minecraft:the_end", "DIM1/entities"),
+        ("minecraft:the_nether", "DIM-1/entities"),
+        ("mymod:mining", "dimensions/mymod/mining/entities"),
+    ] {
+        assert_eq!(
+            get_entities_path_from_dimension(inp).as_deref(),
+            Some(Path::new(out))
+        );
+    }
+}
+
+/// The world height [`remove_too_rare`] divides by by default. It's 255 even
+/// in 1.18+ worlds, so that the cutoff keeps the same blocks of a world across
+/// versions.
+pub const DEFAULT_RARITY_HEIGHT: f64 = 255.;
+
+/// The height of a dimension, from the bottom of its lowest section to the
+/// top of its highest one, regardless of the levels that were counted. For
+/// results without the sections' range (read back from an export), the
+/// levels anything (air included) was counted at are used instead. `None` if
+/// nothing was counted.
+pub fn detected_world_height(freqs: &BlockFrequencies) -> Option<f64> {
+    let (min, max) = match (freqs.min_y, freqs.max_y) {
+        (Some(min), Some(max)) => (min, max),
+        _ => {
+            let levels = freqs.frequencies.values().flat_map(HashMap::keys);
+            levels
+                .minmax()
+                .into_option()
+                .map(|(&min, &max)| (min, max))?
+        }
+    };
+    Some((max - min + 1) as f64)
+}
+
+/// Drops the blocks whose normalized frequency - the sum of their frequencies
+/// by level divided by 255 - is below `cutoff`. See
+/// [`remove_too_rare_by_height`] for other world heights.
+pub fn remove_too_rare(
+    results_by_dim: &mut [(BlockFrequencies, RegionVersion)],
+    cutoff: f64,
+) -> Result<(), RegionScannerError> {
+    remove_too_rare_by_height(results_by_dim, cutoff, DEFAULT_RARITY_HEIGHT)
+}
+
+/// Like [`remove_too_rare`], but divides by `world_height` instead. The
+/// normalized frequency is then the average frequency over a world that is
+/// `world_height` levels high, so with a larger `world_height` fewer blocks
+/// make the cutoff; see [`detected_world_height`].
+pub fn remove_too_rare_by_height(
+    results_by_dim: &mut [(BlockFrequencies, RegionVersion)],
+    cutoff: f64,
+    world_height: f64,
+) -> Result<(), RegionScannerError> {
     if cutoff <= 0. {
-        panic!("Cutoff must be positive, got {}", cutoff);
+        return Err(RegionScannerError::NonPositiveCutoff(cutoff));
+    }
+    if world_height <= 0. {
+        return Err(RegionScannerError::NonPositiveHeight(world_height));
     }
-    // Always 255, even in 1.18+ worlds - otherwise this
-    // metric would change for the same world between versions.
-    let world_height = 255f64;
     for (freqs, _) in results_by_dim.iter_mut() {
         freqs.frequencies.retain(|k, v: &mut HashMap<isize, f64>| {
             let normalized_frequency = v.values().sum::<f64>() / world_height;
@@ -453,4 +3338,5 @@ pub fn remove_too_rare(results_by_dim: &mut [(BlockFrequencies, RegionVersion)],
             }
         });
     }
+    Ok(())
 }
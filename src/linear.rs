@@ -0,0 +1,246 @@
+//! Reading regions stored in the `.linear` format of LinearPaper and its forks,
+//! which some Paper and Folia servers use instead of `.mca` files to save disk.
+//!
+//! The layout read is that of versions 1 and 2 of the format, written by
+//! LinearPaper and LinearPurpur for Minecraft 1.19.4 to 1.20.x: a 32-byte
+//! header, a single zstd frame holding the 1024 chunk sizes and timestamps
+//! followed by the uncompressed NBT of the chunks, and a footer repeating the
+//! signature. The bucketed format of later forks isn't supported.
+
+use std::{
+    fs::File,
+    io::{self, Cursor, ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+use fastanvil::{CompressionScheme, RCoord, Region};
+use ruzstd::decoding::StreamingDecoder;
+
+use crate::RegionSource;
+
+/// The first and last 8 bytes of every `.linear` file.
+pub const LINEAR_SIGNATURE: u64 = 0xc3ff_1318_3cca_9d9a;
+const LINEAR_VERSIONS: [u8; 2] = [1, 2];
+const HEADER_SIZE: usize = 32;
+const CHUNK_TABLE_SIZE: usize = 1024 * 8;
+
+/// The format of the region files of a world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum RegionFormat {
+    /// Vanilla `.mca` region files
+    #[default]
+    Mca,
+    /// The `.linear` region files of LinearPaper and its forks
+    Linear,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Reads the chunks of a `.linear` file, indexed by `x + z * 32` like in a
+/// region. Chunks that weren't saved are `None`.
+pub fn read_linear(data: &[u8]) -> io::Result<Vec<Option<Vec<u8>>>> {
+    if data.len() < HEADER_SIZE + 8 {
+        return Err(invalid(format!(
+            "a .linear file must be at least {} bytes long, this one is {}",
+            HEADER_SIZE + 8,
+            data.len()
+        )));
+    }
+    let signature = |at: usize| u64::from_be_bytes(data[at..at + 8].try_into().unwrap());
+    if signature(0) != LINEAR_SIGNATURE || signature(data.len() - 8) != LINEAR_SIGNATURE {
+        return Err(invalid(
+            "not a .linear file, its signature is wrong".to_owned(),
+        ));
+    }
+    let version = data[8];
+    if !LINEAR_VERSIONS.contains(&version) {
+        return Err(invalid(format!(
+            "version {version} of the .linear format isn't supported"
+        )));
+    }
+    let compressed_len = i32::from_be_bytes(data[20..24].try_into().unwrap());
+    let compressed = usize::try_from(compressed_len)
+        .ok()
+        .and_then(|len| data.get(HEADER_SIZE..HEADER_SIZE + len))
+        .ok_or_else(|| invalid(format!("the compressed length {compressed_len} is wrong")))?;
+
+    let mut decoder = StreamingDecoder::new(compressed).map_err(|e| invalid(e.to_string()))?;
+    let mut raw = vec![];
+    decoder.read_to_end(&mut raw)?;
+    if raw.len() < CHUNK_TABLE_SIZE {
+        return Err(invalid("the chunk table is cut off".to_owned()));
+    }
+    let (table, mut chunk_data) = raw.split_at(CHUNK_TABLE_SIZE);
+    table
+        .chunks_exact(8)
+        .map(|entry| {
+            let size = u32::from_be_bytes(entry[..4].try_into().unwrap()) as usize;
+            if size == 0 {
+                return Ok(None);
+            }
+            if chunk_data.len() < size {
+                return Err(invalid("the chunk data is cut off".to_owned()));
+            }
+            let (chunk, rest) = chunk_data.split_at(size);
+            chunk_data = rest;
+            Ok(Some(chunk.to_vec()))
+        })
+        .collect()
+}
+
+/// Turns the chunks of a `.linear` file into a region in memory, so that they
+/// can be read like those of any other region. The chunks are stored
+/// uncompressed, unless they're too large for a region, in which case they're
+/// compressed with zlib like Minecraft does.
+fn region_from_linear(data: &[u8]) -> fastanvil::Result<Region<Cursor<Vec<u8>>>> {
+    let chunks = read_linear(data)?;
+    let mut region = Region::new(Cursor::new(vec![]))?;
+    for (i, chunk) in chunks.iter().enumerate() {
+        let Some(chunk) = chunk else {
+            continue;
+        };
+        let (x, z) = (i % 32, i / 32);
+        match region.write_compressed_chunk(x, z, CompressionScheme::Uncompressed, chunk) {
+            Err(fastanvil::Error::ChunkTooLarge) => region.write_chunk(x, z, chunk)?,
+            result => result?,
+        }
+    }
+    Ok(region)
+}
+
+/// A region folder of `.linear` files. Each region is decompressed into memory
+/// when it's read.
+pub struct LinearRegionFolder {
+    pub dir: PathBuf,
+}
+
+impl LinearRegionFolder {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+/// The coordinates of a region file named like `r.-1.2.linear`.
+fn parse_linear_name(name: &str) -> Option<(RCoord, RCoord)> {
+    let (x, z) = name
+        .strip_prefix("r.")?
+        .strip_suffix(".linear")?
+        .split_once('.')?;
+    Some((RCoord(x.parse().ok()?), RCoord(z.parse().ok()?)))
+}
+
+/// Loads the region at `(x, z)` from a folder of `.linear` files. `Ok(None)`
+/// means that the region file doesn't exist.
+pub fn open_linear_region(
+    region_dir: &Path,
+    x: RCoord,
+    z: RCoord,
+) -> fastanvil::Result<Option<Region<Cursor<Vec<u8>>>>> {
+    let path = region_dir.join(format!("r.{}.{}.linear", x.0, z.0));
+    let mut data = vec![];
+    match File::open(path) {
+        Ok(mut file) => file.read_to_end(&mut data)?,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    region_from_linear(&data).map(Some)
+}
+
+impl RegionSource for LinearRegionFolder {
+    type Stream = Cursor<Vec<u8>>;
+
+    fn region(&self, x: RCoord, z: RCoord) -> fastanvil::Result<Option<Region<Self::Stream>>> {
+        open_linear_region(&self.dir, x, z)
+    }
+
+    fn list(&self) -> io::Result<Vec<(RCoord, RCoord)>> {
+        // Like for .mca files, empty region files are left out.
+        let mut coords = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.metadata()?.len() == 0 {
+                continue;
+            }
+            if let Some(coord) = entry.file_name().to_str().and_then(parse_linear_name) {
+                coords.push(coord);
+            }
+        }
+        Ok(coords)
+    }
+}
+
+#[cfg(test)]
+fn linear_file(chunks: &[((usize, usize), Vec<u8>)]) -> Vec<u8> {
+    use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+    let mut sizes = vec![0u32; 1024];
+    let mut data = vec![];
+    let mut sorted: Vec<_> = chunks.iter().collect();
+    sorted.sort_unstable_by_key(|((x, z), _)| x + z * 32);
+    for ((x, z), chunk) in sorted {
+        sizes[x + z * 32] = chunk.len() as u32;
+        data.extend_from_slice(chunk);
+    }
+    let mut raw = vec![];
+    for size in sizes {
+        raw.extend_from_slice(&size.to_be_bytes());
+        raw.extend_from_slice(&0i32.to_be_bytes());
+    }
+    raw.extend_from_slice(&data);
+    let compressed = compress_to_vec(&raw[..], CompressionLevel::Fastest);
+
+    let mut file = vec![];
+    file.extend_from_slice(&LINEAR_SIGNATURE.to_be_bytes());
+    file.push(1);
+    file.extend_from_slice(&0i64.to_be_bytes());
+    file.push(6);
+    file.extend_from_slice(&(chunks.len() as i16).to_be_bytes());
+    file.extend_from_slice(&(compressed.len() as i32).to_be_bytes());
+    file.extend_from_slice(&0i64.to_be_bytes());
+    file.extend_from_slice(&compressed);
+    file.extend_from_slice(&LINEAR_SIGNATURE.to_be_bytes());
+    file
+}
+
+#[test]
+fn test_scan_linear_folder() {
+    use std::sync::Mutex;
+
+    use crate::{
+        fixtures::*, scan_source, DimensionScanResult, RegionManifest, ScanHooks, ScanOptions,
+    };
+
+    let dir = TempDir::new("linear");
+    let chunk = |block: &str| ChunkNbt::full(vec![SectionNbt::uniform(0, block)]).to_bytes();
+    let file = linear_file(&[
+        ((0, 0), chunk("minecraft:stone")),
+        ((5, 3), chunk("minecraft:dirt")),
+    ]);
+    std::fs::write(dir.join("r.-1.2.linear"), &file).unwrap();
+    std::fs::write(dir.join("r.0.0.linear"), b"").unwrap();
+
+    let source = LinearRegionFolder::new(dir.to_path_buf());
+    assert_eq!(source.list().unwrap(), [(RCoord(-1), RCoord(2))]);
+    let manifest = Mutex::new(RegionManifest::new(dir.to_path_buf()));
+    let result = scan_source(
+        &source,
+        &dir,
+        None,
+        "test:dim",
+        &ScanOptions::default(),
+        &manifest,
+        &ScanHooks::default(),
+    );
+    let DimensionScanResult::Ok((freqs, _version)) = result else {
+        panic!("expected the region to be scanned");
+    };
+    assert_eq!(freqs.chunks_counted, 2);
+    assert_eq!(freqs.frequencies["minecraft:stone"][&0], 0.5);
+    assert_eq!(freqs.frequencies["minecraft:dirt"][&0], 0.5);
+
+    let mut broken = file;
+    broken[8] = 3;
+    assert!(read_linear(&broken).is_err());
+}
@@ -1,48 +1,207 @@
-use std::{io::prelude::Write, path::PathBuf, time::Instant};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io::{prelude::Write, IsTerminal},
+    num::NonZeroUsize,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
 
-use clap::{Parser, ValueEnum, ValueHint};
+use clap::{
+    parser::ValueSource, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum, ValueHint,
+};
 use color_eyre::{
-    eyre::{bail, ensure, Context},
+    eyre::{bail, ensure, eyre, Context},
     Result,
 };
+use fastanvil::RCoord;
 #[macro_use]
 extern crate log;
-use fastanvil::{RCoord, RegionFileLoader, RegionLoader};
+use itertools::Itertools;
 use rayon::prelude::*;
 use region_scanner::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Without a subcommand, the arguments are those of `scan`.
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ScanArgs {
     /// The absolute path to the save folder of the world to scan.
     /// This is the folder the 'region' folder is in.
     /// Example: 'D:\Games\MultiMC\instances\FTB Presents Direwolf20
     /// 1.16\v.1.4.1\.minecraft\saves\MyTestWorld'
-    #[arg(short='p', long, value_name = "SAVEFOLDER", value_hint=ValueHint::DirPath)]
-    path: PathBuf,
+    ///
+    /// If several worlds are passed, the results for each dimension are
+    /// combined, weighting every world by the area scanned in it.
+    #[arg(
+        short='p',
+        long,
+        value_name = "SAVEFOLDER",
+        value_hint=ValueHint::DirPath,
+        num_args = 1..
+    )]
+    #[cfg_attr(
+        not(feature = "archive"),
+        arg(required_unless_present_any = ["list_formats", "config"])
+    )]
+    #[cfg_attr(
+        feature = "archive",
+        arg(required_unless_present_any = ["list_formats", "config", "archive"])
+    )]
+    path: Vec<PathBuf>,
+
+    /// Scan the world in this zip archive, such as a backup, instead of a save
+    /// folder, reading the region files from it without extracting them. The
+    /// world can be in a folder of the archive, which is found by its
+    /// level.dat; see --world for archives with several worlds. Only counting
+    /// blocks is supported.
+    #[cfg(feature = "archive")]
+    #[arg(
+        long,
+        value_name = "FILE.zip",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["path", "list_dims", "all_dims"]
+    )]
+    archive: Option<PathBuf>,
+
+    /// The folder of the world to scan in the --archive, like 'saves/MyWorld',
+    /// for archives holding several worlds. Empty for a world at the root.
+    #[cfg(feature = "archive")]
+    #[arg(long, value_name = "FOLDER", requires = "archive")]
+    world: Option<String>,
+
+    /// A TOML file with the arguments to scan with, so that a scan can be
+    /// repeated without retyping them. Supports the keys path, dims, zone,
+    /// format, threads, only_blocks_above and proto, named like the
+    /// arguments. Arguments given on the command line override the file.
+    /// Example:
+    /// 'path = ["saves/MyWorld"]', 'dims = ["minecraft:overworld"]',
+    /// 'zone = [-1, 1, -1, 1]'.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    config: Option<PathBuf>,
 
     /// The format to export to
     #[arg(short='f', long, required=false, value_enum, default_value_t=ExportFormat::Jer)]
     format: ExportFormat,
 
+    /// Add the bounds of the 95% confidence interval of every frequency as the
+    /// `freq_low,freq_high` columns. Useful to tell how reliable the
+    /// frequencies from a small scan are. Only supported for tall-csv.
+    #[arg(long)]
+    confidence_intervals: bool,
+
+    /// Precede the header of the tall CSV with a comment line per dimension,
+    /// holding the metadata (area, world version and so on) needed to read the
+    /// file back with the merge and compare subcommands. Only supported for
+    /// tall-csv.
+    #[arg(long)]
+    csv_metadata: bool,
+
+    /// A JER world-gen.json to use as the known-good baseline for the
+    /// anomalies format, for example one exported from a vanilla world of the
+    /// same version.
+    #[arg(long, value_name = "FILE", value_hint=ValueHint::FilePath)]
+    baseline: Option<PathBuf>,
+
+    /// For the JER format, an existing world-gen.json (e.g. from a previous
+    /// scan) to add the results to. Its entries for dimensions that aren't
+    /// scanned now are kept, while the ones for scanned dimensions are merged
+    /// with the new results, block by block.
+    #[arg(long, value_name = "FILE", value_hint=ValueHint::FilePath)]
+    merge_into: Option<PathBuf>,
+
+    /// The area (in columns) the --merge-into file was scanned over, to weight
+    /// its entries against the new results by. JER files don't record it, so
+    /// without this both weigh the same.
+    #[arg(long, value_name = "AREA", requires = "merge_into")]
+    merge_into_area: Option<u64>,
+
+    /// For the JER format, write a file per dimension instead of a single
+    /// world-gen.json, named like 'world-gen.minecraft.the_nether.json', so
+    /// that only some of the dimensions can be imported into JER.
+    #[arg(long, conflicts_with_all = ["merge_into", "output_file", "out_template"])]
+    split_by_dim: bool,
+
+    /// For the anomalies format, the smallest deviation from the baseline for
+    /// a block to be reported. The deviation is the sum of the absolute
+    /// per-level differences divided by the sum of both distributions, so it
+    /// ranges from 0 (identical) to 1 (completely different).
+    #[arg(long, default_value_t = 0.25)]
+    anomaly_threshold: f64,
+
+    #[command(flatten)]
+    mining: MiningArgs,
+
+    #[command(flatten)]
+    block_meta: BlockMetaArgs,
+
+    #[command(flatten)]
+    block_values: BlockValueArgs,
+
     /// The folder to put the output file in. Will be created if missing. The
     /// default is a folder called "output" in the current working
-    /// directory.
+    /// directory. With '-', the output is written to stdout instead, and only
+    /// warnings and errors are logged (to stderr), so that it can be piped.
     #[arg(long="output", value_name = "OUTFOLDER", value_hint=ValueHint::DirPath, default_value="output")]
     output_folder: PathBuf,
 
+    /// A template for the name of the output file, instead of the fixed name
+    /// of the format. The placeholders {dim} (the dimensions scanned), {world}
+    /// (the name of the world folder), {format}, {date} and {timestamp} (both
+    /// in UTC) are filled in. Example: '{world}-{date}.csv'.
+    #[arg(long, value_name = "TEMPLATE")]
+    out_template: Option<String>,
+
+    /// The path of the output file, replacing both the output folder and the
+    /// name of the file. Its extension has to be the one of the chosen format,
+    /// e.g. '.csv' for tall-csv. With '-', the output is written to stdout,
+    /// like with '--output -'. Example: 'scans/my-world.json'.
+    #[arg(
+        short = 'o',
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["output_folder", "out_template"]
+    )]
+    output_file: Option<PathBuf>,
+
     /// The dimension IDs to scan in the new format.
     /// Examples: 'minecraft:overworld', 'minecraft:the_nether',
     /// 'minecraft:the_end', 'jamd:mining'.
     #[arg(
         short='d',
         long,
-        required = true,
+        required_unless_present_any = ["list_formats", "list_dims", "all_dims", "config"],
         value_name = "DIMENSION_ID",
         num_args = 1..
     )]
     dims: Vec<String>,
 
+    /// Scan every dimension found in the save folders instead of the ones
+    /// given with --dims (see --list-dims for what gets found). When several
+    /// worlds are scanned, each dimension is only scanned in the worlds that
+    /// have it.
+    #[arg(long, conflicts_with_all = ["dims", "list_dims"])]
+    all_dims: bool,
+
     /// The zone to scan in every dimension, in regions, in the format of
     /// 'FROM_X,TO_X,FROM_Z,TO_Z' (separated either by commas or spaces).
     /// For example, '-1,1,-1,1' is a 2x2 square containing regions (-1,-1),
@@ -59,27 +218,495 @@ struct Args {
     )]
     zone: Option<Vec<isize>>,
 
+    /// Only count the columns inside a polygon, given by its vertices in block
+    /// coordinates as space-separated 'X,Z' pairs. The vertices are at block
+    /// corners, so '0,0 16,0 16,16 0,16' is exactly one chunk. Only the regions
+    /// overlapping its bounding box are read. Example: '-100,0 100,0 0,250'.
+    #[arg(
+        long,
+        value_name = "VERTICES",
+        value_parser = parse_polygon,
+        allow_hyphen_values = true,
+        conflicts_with = "zone"
+    )]
+    polygon: Option<Polygon>,
+
+    /// Don't count the levels from FROM to TO (both inclusive) in any
+    /// dimension, for example '-64,-60'. Can be given several times.
+    #[arg(
+        long,
+        value_name = "FROM,TO",
+        value_parser = parse_level_range,
+        allow_hyphen_values = true
+    )]
+    exclude_y: Vec<RangeInclusive<isize>>,
+
+    /// Only count the levels from FROM to TO (both inclusive) in every
+    /// dimension, for example '-64,16' when looking for a deep ore. The
+    /// frequencies at those levels are the same as in a full scan, and the
+    /// levels of the JER format keep their offset.
+    #[arg(
+        long,
+        value_name = "FROM,TO",
+        value_parser = parse_level_range,
+        allow_hyphen_values = true
+    )]
+    y_range: Option<RangeInclusive<isize>>,
+
+    /// Don't count the bedrock floor and roof of the nether, nor what's above
+    /// the roof, so that the frequencies in minecraft:the_nether only cover
+    /// the levels in between (y=5 to y=122).
+    #[arg(long)]
+    skip_nether_bedrock: bool,
+
     /// If not none, only blocks with a normalized frequency above this value
     /// will be exported. Normalized frequency is the sum of frequencies by
-    /// level divided by 255 (even in 1.18+ worlds which are higher than that).
-    /// For example, a value of 0.01 means retain blocks more common that 1
-    /// in 100 (which is ~655 such blocks per 255-height chunk). The default
-    /// value is 1e-7, which is about 26 blocks pre 4096 chunks.
+    /// level divided by 255 (even in 1.18+ worlds which are higher than that,
+    /// see --rarity-height). For example, a value of 0.01 means retain blocks
+    /// more common that 1 in 100 (which is ~655 such blocks per 255-height
+    /// chunk). The default value is 1e-7, which is about 26 blocks pre 4096
+    /// chunks.
     /// Some comparisons: minecraft:emerald_ore is ~3e-6,
     /// minecraft:deepslate_emerald_ore (1.18) is ~2e-7,
     /// minecraft:ancient_debris is ~2e-5.
     #[arg(long, required = false, default_value = "1e-7")]
     only_blocks_above: Option<f64>,
 
+    /// The world height the sum of frequencies is divided by for
+    /// --only-blocks-above, or 'auto' to use the height of each dimension as
+    /// detected from the sections of its chunks, whichever levels were
+    /// counted. The default of 255 keeps
+    /// the cutoff the same across versions, but in taller dimensions it
+    /// overstates how common blocks are; with 'auto', a block in a 640-level
+    /// dimension needs 640/255 (~2.5) times as many occurrences per chunk to
+    /// be kept. With a lower height, more blocks are kept.
+    #[arg(long, value_name = "HEIGHT", default_value = "255", value_parser = parse_rarity_height)]
+    rarity_height: RarityHeight,
+
+    /// Detect the world version from the chunks as they're counted, instead
+    /// of in a separate pass over the first regions before the scan.
+    #[arg(long)]
+    single_pass: bool,
+
+    /// Also write which regions were scanned, not found or failed to load to
+    /// <OUTFOLDER>/<dimension>/scanned_regions.json, to check the coverage of
+    /// a scan.
+    #[arg(long)]
+    region_manifest: bool,
+
+    /// Also write the regions that failed to load, along with the error, to
+    /// <OUTFOLDER>/failed-regions.csv, to find the corrupt region files. They're
+    /// logged at the end of the scan either way.
+    #[arg(long)]
+    failed_regions: bool,
+
+    /// Record the counts of every region in this file as soon as it's scanned,
+    /// so that an interrupted scan can be resumed by running the same command
+    /// again: the regions already in the file are then not scanned again. The
+    /// file is deleted once every output is written. A checkpoint made with
+    /// another zone or options changing what's counted isn't resumed.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    checkpoint: Option<PathBuf>,
+
+    /// Only scan the region files whose name matches this glob pattern, for
+    /// example 'r.0.*.mca' for the regions with x=0. Applied on top of --zone.
+    #[arg(long, value_name = "PATTERN")]
+    region_glob: Option<String>,
+
+    /// Only scan a random share of the regions of each dimension, for example
+    /// 0.1 for every tenth region. Useful to get quick estimates for huge
+    /// worlds. See also --sample-seed.
+    #[arg(long, value_name = "FRACTION")]
+    sample_fraction: Option<f64>,
+
+    /// Scan at most this many regions per dimension, chosen randomly. Can be
+    /// combined with --sample-fraction. See also --sample-seed.
+    #[arg(long)]
+    max_regions: Option<usize>,
+
+    /// The seed used to choose the regions for --sample-fraction and
+    /// --max-regions. The same seed chooses the same regions on every run.
+    /// If not provided, a random seed is used and logged.
+    #[arg(long)]
+    sample_seed: Option<u64>,
+
+    /// How the server that saved the world lays out the folders of its
+    /// dimensions.
+    #[arg(long, value_enum, default_value_t)]
+    server_type: ServerType,
+
     /// How to handle protochunks (chunks with a status other than
     /// minecraft:full, meaning they aren't fully generated).
     #[arg(long, required=false, value_enum, default_value_t=ProtoOption::Skip)]
     proto: ProtoOption,
 
+    /// Only count the chunks with exactly one of these statuses, such as
+    /// 'minecraft:noise' or 'minecraft:surface', to see the terrain at a
+    /// stage of world generation. Replaces --proto.
+    #[arg(long, num_args = 1.., value_name = "STATUS", conflicts_with = "proto")]
+    status: Option<Vec<String>>,
+
+    /// A file listing the block IDs to count - all other blocks are ignored,
+    /// which makes scanning faster. Either an existing JER world-gen.json (the
+    /// blocks it mentions are used) or a text file with one block ID per line.
+    #[arg(long, value_name = "FILE", value_hint=ValueHint::FilePath)]
+    known_blocks: Option<PathBuf>,
+
+    /// Only count the blocks whose ID matches one of these glob patterns, for
+    /// example '*_ore' or 'create:*'. Can be combined with --exclude and
+    /// --known-blocks. The frequencies are still relative to the whole area
+    /// scanned.
+    #[arg(long, num_args = 1.., value_name = "PATTERN", value_parser = parse_block_pattern)]
+    include: Vec<glob::Pattern>,
+
+    /// Don't count the blocks whose ID matches one of these glob patterns, for
+    /// example 'minecraft:*stone*'.
+    #[arg(long, num_args = 1.., value_name = "PATTERN", value_parser = parse_block_pattern)]
+    exclude: Vec<glob::Pattern>,
+
+    /// Normalize block names before counting them: lowercase them and add the
+    /// 'minecraft:' namespace to names without one. Useful for worlds with
+    /// inconsistently written block IDs, which would otherwise be counted
+    /// separately.
+    #[arg(long)]
+    normalize_names: bool,
+
+    /// Count every state of a block separately, keyed like
+    /// 'minecraft:furnace[facing=north;lit=true]', to tell apart e.g. lit and
+    /// unlit redstone ore. The waterlogged and powered properties aren't read.
+    /// The JER format still sums up the states of each block. Makes the scan
+    /// slower.
+    #[arg(long)]
+    track_properties: bool,
+
+    /// Read region files by memory-mapping them rather than through regular
+    /// file reads. Can be faster for very large regions.
+    #[cfg(feature = "mmap")]
+    #[arg(long)]
+    mmap: bool,
+
+    /// The format of the region files. Servers running LinearPaper or its
+    /// forks store regions as `.linear` files instead of `.mca` ones. Only
+    /// supported when counting blocks; --mmap doesn't apply to linear regions.
+    #[cfg(feature = "linear")]
+    #[arg(long, value_enum, default_value_t)]
+    region_format: RegionFormat,
+
+    /// Additionally render a PNG chart per dimension, plotting the frequency
+    /// by level of the most common blocks. Combine with --known-blocks to
+    /// chart specific blocks.
+    #[cfg(feature = "charts")]
+    #[arg(long)]
+    charts: bool,
+
+    /// The number of blocks to plot on each chart.
+    #[cfg(feature = "charts")]
+    #[arg(long, default_value_t = 10)]
+    chart_blocks: usize,
+
+    /// Additionally render an SVG chart of the frequency by level of every
+    /// exported block into this folder, in a subfolder per dimension, like
+    /// the plots of JER.
+    #[cfg(feature = "charts")]
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    plot_dir: Option<PathBuf>,
+
+    /// Only count blocks with air on at least one of their six sides, e.g. to
+    /// tell how many ores can be seen in caves. The frequencies are still
+    /// relative to the whole area scanned. Blocks at the edges of regions
+    /// aren't checked against the neighbouring regions, and the scan is a few
+    /// times slower.
+    #[arg(long)]
+    exposed_only: bool,
+
+    /// Only count the chunks where players have spent at least this many
+    /// ticks in total (20 ticks are a second), according to their
+    /// InhabitedTime. Useful to see what's around the areas that are actually
+    /// played in. The frequencies are relative to the chunks counted.
+    #[arg(long, value_name = "TICKS")]
+    min_inhabited_time: Option<i64>,
+
+    /// Instead of counting blocks, sum up the InhabitedTime (the number of
+    /// ticks players spent nearby) of the chunks of every region and write
+    /// them to inhabited-time.csv, which can be used as a heatmap of player
+    /// activity. --format is ignored.
+    #[arg(long)]
+    inhabited_time: bool,
+
+    /// What to count. Entities are read from the entity region files that
+    /// Minecraft 1.17+ keeps next to the block ones, and their counts are
+    /// written to entities.csv, ignoring --format. Block entities (chests,
+    /// spawners...) are read from the chunks and written to
+    /// block-entities.csv.
+    #[arg(long, value_enum, default_value_t = ScanTarget::Blocks)]
+    scan: ScanTarget,
+
+    /// With --scan entities, also write the entity counts of every region to
+    /// entities-per-region.csv, densest first, to find where entities (like
+    /// dropped items) pile up.
+    #[arg(long)]
+    entities_per_region: bool,
+
+    /// With --scan block-entities, also write the position of the block
+    /// entities with these IDs (e.g. minecraft:mob_spawner) to
+    /// block-entity-locations.csv. Without IDs, every block entity is located.
+    #[arg(long, num_args = 0.., value_name = "ID")]
+    locate: Option<Vec<String>>,
+
+    /// Instead of counting blocks, read the MOTION_BLOCKING heightmap of the
+    /// chunks and write the distribution of the surface height (the Y of the
+    /// highest block that blocks movement or holds fluid) of every dimension to
+    /// surface-heights.csv. --format is ignored.
+    #[arg(long, conflicts_with = "inhabited_time")]
+    surface_heights: bool,
+
+    /// Instead of the frequencies of all blocks, count this block in every
+    /// chunk and write how many chunks have each count of it to
+    /// chunk-histogram.csv, showing how much it varies between chunks.
+    /// --format is ignored.
+    #[arg(
+        long,
+        value_name = "BLOCK",
+        conflicts_with_all = ["inhabited_time", "surface_heights"]
+    )]
+    chunk_histogram: Option<String>,
+
+    /// Instead of the frequencies of all blocks, count this block in every
+    /// chunk of a dimension and write the counts to heatmap.csv, as a grid with
+    /// a column per chunk X and a row per chunk Z, to see where it
+    /// concentrates. With --charts, the grid is also drawn to heatmap.png.
+    /// --format is ignored.
+    #[arg(
+        long,
+        value_name = "BLOCK",
+        conflicts_with_all = ["inhabited_time", "surface_heights", "chunk_histogram", "veins"]
+    )]
+    heatmap: Option<String>,
+
+    /// Instead of the frequencies of all blocks, find the veins of these
+    /// blocks (groups of the same block touching by their faces) and write
+    /// how many veins of each size there are to veins.csv. Veins crossing the
+    /// border of a region are counted as one vein on each side. --format is
+    /// ignored.
+    #[arg(
+        long,
+        num_args = 1..,
+        value_name = "BLOCK",
+        conflicts_with_all = ["inhabited_time", "surface_heights", "chunk_histogram"]
+    )]
+    veins: Option<Vec<String>>,
+
+    /// Log the first time each kind of block is found (up to 500 kinds), to
+    /// check during a scan that the blocks of a world are read as expected.
+    /// Also logs every 100th chunk of each region.
+    #[arg(short = 'v', long, conflicts_with = "silence_warnings")]
+    verbose: bool,
+
+    /// Don't print the non-fatal warnings, such as about regions failing to
+    /// load or heights unsupported by JER, nor the detected world version.
+    /// Errors are still printed. Meant for running the scanner from scripts.
+    #[arg(long)]
+    silence_warnings: bool,
+
+    /// Before exporting, fold modded blocks into their vanilla equivalents
+    /// (e.g. the copper ores of various mods into minecraft:copper_ore), so
+    /// that ore rates of modpacks using different mods can be compared. A
+    /// small mapping for common mods is built in, see also --canonical-map.
+    /// The formats holding the exact results (json, binary, ndjson, sqlite
+    /// and arrow) are left as scanned.
+    #[arg(long)]
+    canonicalize: bool,
+
+    /// With --canonicalize, also fold in the modded ores that only stand in
+    /// for a vanilla one but drop something else, like Thermal's apatite for
+    /// lapis and BYG's pendorite for ancient debris. Implies --canonicalize.
+    #[arg(long)]
+    canonicalize_approximate: bool,
+
+    /// A file with additional block mappings for --canonicalize, which
+    /// override the built-in ones. Each line has the form
+    /// 'modid:some_ore=minecraft:some_ore'. Implies --canonicalize.
+    #[arg(long, value_name = "FILE", value_hint=ValueHint::FilePath)]
+    canonical_map: Option<PathBuf>,
+
+    /// A soft cap, in megabytes, on the memory used for the block counts of
+    /// each dimension. The usage is estimated from the number of distinct
+    /// blocks and levels found; when it gets close to the cap, a warning is
+    /// logged. Worlds with thousands of modded blocks can take up a lot, in
+    /// which case --known-blocks limits what gets counted.
+    #[arg(long, value_name = "MB")]
+    max_memory_mb: Option<u64>,
+
+    /// Scan the regions in batches of this many, merging the block counts of
+    /// each batch before starting the next, instead of all at once. The
+    /// memory taken by the counts is then roughly this many times that of a
+    /// single region's, which helps with huge scans on machines with little
+    /// RAM. Should be at least --threads to keep them all busy.
+    #[arg(long, value_name = "N")]
+    max_regions_in_flight: Option<NonZeroUsize>,
+
+    /// Pause for this many milliseconds before reading each region, so that a
+    /// scan of the world of a running server doesn't hog the disk and lag the
+    /// server. Each thread pauses separately, so pair this with a low
+    /// --threads, such as 1 or 2.
+    #[arg(long, value_name = "MS")]
+    throttle: Option<u64>,
+
+    /// Don't ask for confirmation before scanning every region of a world with
+    /// more than 1000 of them, which happens when no --zone is given.
+    #[arg(short = 'y', long)]
+    yes: bool,
+
+    /// Check the dimensions and count the region files that would be scanned
+    /// in each, along with the chunk area they cover, then exit without
+    /// reading any chunk. Fails if the folder of any dimension is missing.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Print the IDs of the dimensions found in the save folders along with
+    /// their number of regions, then exit. Useful to find what to pass to
+    /// --dims for modded dimensions.
+    #[arg(long, conflicts_with = "dims")]
+    list_dims: bool,
+
+    /// Print the available export formats along with the files they produce,
+    /// then exit.
+    #[arg(long, exclusive = true)]
+    list_formats: bool,
+
     /// Number of worker threads to use for scanning dimensions. If
     /// set to zero, will be chosen automatically by rayon.
     #[arg(short = 't', long, default_value_t = 0)]
     threads: usize,
+
+    /// Also count the chunks of each region in parallel, instead of only
+    /// scanning several regions at once. Faster when there are fewer regions
+    /// than threads, such as with a small --zone, but slightly slower
+    /// otherwise. Has no effect with --exposed-only.
+    #[arg(long)]
+    parallel_chunks: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Scan the blocks of a world, which is also what running without a
+    /// subcommand does.
+    Scan(Box<ScanArgs>),
+    /// Merge the results of previous scans exported in the json, binary or
+    /// tall-csv format, for example of different zones or dimensions, into one
+    /// output. Frequencies of the same dimension are weighted by the area they
+    /// were counted over.
+    Merge(MergeArgs),
+    /// Compare two worlds, or the results of two previous scans, writing how
+    /// the frequency of every block changed on every level. Useful to see what
+    /// changing the configs of a modpack did to its ores.
+    Compare(CompareArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct MergeArgs {
+    /// The files to merge, as exported with --format json, binary or tall-csv
+    /// with --csv-metadata (told apart by the .bin and .csv extensions), or
+    /// JER world-gen.json files.
+    #[arg(required = true, num_args = 1.., value_name = "FILE", value_hint=ValueHint::FilePath)]
+    files: Vec<PathBuf>,
+
+    /// The area (in columns) each JER file was scanned over, in the order the
+    /// files are given, to weight them by. JER files don't record it, so
+    /// without this they all weigh the same, and can't be merged with the
+    /// other exports.
+    #[arg(long, num_args = 1.., value_name = "AREA")]
+    jer_areas: Option<Vec<u64>>,
+
+    /// The JER files are from worlds before 1.18, so their levels start at
+    /// y=0 rather than y=-64.
+    #[arg(long)]
+    jer_pre_118: bool,
+
+    /// The format to export the merged results to.
+    #[arg(short='f', long, value_enum, default_value_t=ExportFormat::Json)]
+    format: ExportFormat,
+
+    /// Precede the header of the tall CSV with the metadata comment lines, so
+    /// that it can be merged again. Only supported for tall-csv.
+    #[arg(long)]
+    csv_metadata: bool,
+
+    /// The folder to put the output file in. Will be created if missing. With
+    /// '-', the output is written to stdout instead.
+    #[arg(long="output", value_name = "OUTFOLDER", value_hint=ValueHint::DirPath, default_value="output")]
+    output_folder: PathBuf,
+
+    #[command(flatten)]
+    mining: MiningArgs,
+
+    #[command(flatten)]
+    block_meta: BlockMetaArgs,
+
+    #[command(flatten)]
+    block_values: BlockValueArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct CompareArgs {
+    /// The two worlds to compare, each either a save folder to scan or a file
+    /// exported with --format json, binary or tall-csv (with --csv-metadata).
+    /// The deltas are the frequencies in the second minus the ones in the
+    /// first.
+    #[arg(required = true, num_args = 2, value_names = ["A", "B"], value_hint=ValueHint::AnyPath)]
+    inputs: Vec<PathBuf>,
+
+    /// The dimension IDs to scan in the save folders. Required if any of the
+    /// inputs is one.
+    #[arg(short = 'd', long, value_name = "DIMENSION_ID", num_args = 1..)]
+    dims: Vec<String>,
+
+    /// The zone to scan in the save folders, like for a scan.
+    #[arg(
+        short = 'z',
+        long,
+        value_names = ["FROM_X", "TO_X", "FROM_Z", "TO_Z"],
+        num_args = 1..=4,
+        value_delimiter = ',',
+        allow_hyphen_values = true
+    )]
+    zone: Option<Vec<isize>>,
+
+    /// How to handle protochunks when scanning the save folders.
+    #[arg(long, value_enum, default_value_t=ProtoOption::Skip)]
+    proto: ProtoOption,
+
+    /// How the server that saved the worlds lays out the folders of its
+    /// dimensions.
+    #[arg(long, value_enum, default_value_t)]
+    server_type: ServerType,
+
+    /// The format to write the differences in.
+    #[arg(short = 'f', long, value_enum, default_value_t = DiffFormat::Csv)]
+    format: DiffFormat,
+
+    /// The folder to put the output file in. Will be created if missing. With
+    /// '-', the output is written to stdout instead.
+    #[arg(long="output", value_name = "OUTFOLDER", value_hint=ValueHint::DirPath, default_value="output")]
+    output_folder: PathBuf,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum DiffFormat {
+    /// world-gen-diff.csv with a row per dimension, block and level
+    Csv,
+    /// world-gen-diff.json with the frequencies of every block by dimension
+    Json,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum ScanTarget {
+    /// The blocks of the chunks
+    Blocks,
+    /// The entities, by their ID
+    Entities,
+    /// The block entities, like chests and spawners, by their ID
+    BlockEntities,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
@@ -89,286 +716,2432 @@ enum ExportFormat {
     /// world-gen.csv file in CSV format - a row per each level
     /// and per each resource
     TallCSV,
+    /// world-gen-anomalies.csv listing the blocks whose distribution deviates
+    /// from the --baseline the most, with the per-level differences
+    Anomalies,
+    /// world-gen-data.json holding the exact results along with the scan
+    /// metadata, which can be combined with the merge subcommand
+    Json,
+    /// world-gen-sections.csv with the number of blocks of each kind in every
+    /// 16 block tall section, for coarse vertical density maps
+    Sections,
+    /// world-gen-peaks.csv with only the level where each block is the most
+    /// common, for quickly looking up where to mine an ore
+    Peaks,
+    /// world-gen-spread.csv with the mean level of each block and the standard
+    /// deviation of its levels, telling how spread out vertically it is
+    Spread,
+    /// world-gen-mining-rate.csv with the level where mining finds the most of
+    /// each block and how many are found per hour there, see --mining-pattern
+    MiningRate,
+    /// world-gen-namespaces.csv with the totals of the blocks of every mod
+    /// (by the namespace of the block IDs) in each dimension
+    Namespaces,
+    /// world-gen-cumulative.csv with the share of each block found at or
+    /// below every level, for questions like "below which Y is 90% of it"
+    Cumulative,
+    /// world-gen-density.csv with the number of non-air blocks on every
+    /// level, densest first, showing where the bulk of the terrain is
+    Density,
+    /// world-gen-extremes.csv with the lowest and highest level each block
+    /// was found on, for spotting generation out of the intended range
+    Extremes,
+    /// world-gen-value.csv with the value found per block mined on every
+    /// level, weighting the blocks by the --block-values
+    Value,
+    /// world-gen-biomes.csv with the share of the columns of each dimension
+    /// every biome is found in, instead of the block frequencies
+    Biomes,
+    /// world-gen.ndjson with a JSON object per block and level, written out as
+    /// it's produced, for streaming it into tools like jq
+    Ndjson,
+    /// world-gen-data.bin holding the exact results like the json format, but
+    /// much faster to read back with the merge subcommand
+    Binary,
+    /// report.html, a self-contained web page with a chart of every block by
+    /// level, collapsible by dimension, for sharing the results
+    Html,
+    /// world-gen.sqlite, an SQLite database with tables of the dimensions,
+    /// the blocks and the frequencies, for querying the results with SQL
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+    /// world-gen.arrow, an Apache Arrow IPC file with the rows of the tall
+    /// CSV, far more compact and directly loadable into pandas or polars
+    #[cfg(feature = "arrow")]
+    Arrow,
 }
-fn init() -> Result<()> {
-    if std::env::var_os("RUST_LOG").is_none() {
-        std::env::set_var("RUST_LOG", "info");
-    }
-    pretty_env_logger::init();
-    color_eyre::install()?;
-    Ok(())
+
+/// The assumptions of the mining-rate format.
+#[derive(clap::Args, Debug)]
+struct MiningArgs {
+    /// For the mining-rate format, how the player mines. The walls, floor and
+    /// ceiling of the tunnel are assumed to be checked for ores as well.
+    #[arg(long, value_enum, default_value_t)]
+    mining_pattern: MiningPattern,
+
+    /// For the mining-rate format, the number of blocks the player mines per
+    /// hour.
+    #[arg(long, value_name = "BLOCKS", default_value_t = 3600.)]
+    mined_per_hour: f64,
 }
-fn main() -> Result<()> {
-    init()?;
 
-    let args = Args::parse();
-    ensure!(
-        args.path.exists(),
-        "It doesn't seem like the path {:?} exists!",
-        args.path
-    );
-    std::fs::create_dir_all(&args.output_folder).with_context(|| {
-        format!(
-            "Failed to create the output directory {:?}",
-            &args.output_folder
-        )
-    })?;
+/// The hardness and tool columns of the per-block formats.
+#[derive(clap::Args, Debug)]
+struct BlockMetaArgs {
+    /// Add the hardness of every block and the tool (and its tier) needed to
+    /// mine it as columns, for the peaks, spread and mining-rate formats.
+    /// Blocks missing from the built-in table of vanilla blocks and from
+    /// --block-meta-file get empty columns.
+    #[arg(long)]
+    with_block_meta: bool,
 
-    let zone: Option<Zone> = if let Some(coords) = args.zone {
-        // Necessary check because this seems to not be possible to describe in clap v4
-        // - it used to be num_values.
+    /// A CSV with a 'block,hardness,tool,tool_level' header, adding modded
+    /// blocks to the built-in table or overriding its entries.
+    #[arg(long, value_name = "FILE", value_hint=ValueHint::FilePath, requires = "with_block_meta")]
+    block_meta_file: Option<PathBuf>,
+}
+
+impl BlockMetaArgs {
+    /// The table to add the columns from, if --with-block-meta is set.
+    fn load(&self, format: ExportFormat) -> Result<Option<BlockMetaTable>> {
+        if !self.with_block_meta {
+            return Ok(None);
+        }
         ensure!(
-            coords.len() == 4,
-            "Wrong number of zone values! Expected: 4, got: {}. See --help or examples on the \
-             repo for details.",
-            coords.len()
+            matches!(
+                format,
+                ExportFormat::Peaks | ExportFormat::Spread | ExportFormat::MiningRate
+            ),
+            "--with-block-meta is only supported for the peaks, spread and mining-rate formats."
         );
-        Some(Zone::from(coords))
-    } else {
-        None
-    };
+        let mut table = BlockMetaTable::vanilla();
+        if let Some(path) = &self.block_meta_file {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the block metadata file {:?}", path))?;
+            table
+                .extend_from_csv(&contents)
+                .map_err(|e| eyre!("Invalid block metadata file {:?}: {}", path, e))?;
+        }
+        Ok(Some(table))
+    }
+}
 
-    let mut paths_to_scan = vec![];
-    for dimension in &args.dims {
-        match get_path_from_dimension(dimension) {
-            Some(suffix) => {
-                let mut full_path = args.path.clone();
-                full_path.push(suffix);
-                paths_to_scan.push((dimension.as_str(), full_path.clone()));
-                if !full_path.exists() {
-                    bail!(
-                        "Dimension name `{}` resolved to path `{}`, but this path doesn't exist! \
-                         Perhaps you misspelled a dimension name (note in particular that that \
-                         the vanilla dimensions are spelled `the_nether` and `the_end`), or tried \
-                         to scan a dimension that wasn't generated yet for this world.",
-                        dimension,
-                        full_path.to_string_lossy()
-                    );
-                }
-            }
-            None => {
-                bail!("Wasn't able to parse dimension: {}", dimension);
-            }
+/// The weights of the value format.
+#[derive(clap::Args, Debug)]
+struct BlockValueArgs {
+    /// For the value format, a CSV with a 'block,value' header giving the
+    /// value of every block of interest, like 'minecraft:emerald_ore,10'.
+    /// Other blocks are worth nothing.
+    #[arg(long, value_name = "FILE", value_hint=ValueHint::FilePath)]
+    block_values: Option<PathBuf>,
+}
+
+impl BlockValueArgs {
+    /// The values of the blocks, which are needed exactly for the value format.
+    fn load(&self, format: ExportFormat) -> Result<Option<HashMap<String, f64>>> {
+        ensure!(
+            (format == ExportFormat::Value) == self.block_values.is_some(),
+            "--block-values has to be passed if and only if the value format is used."
+        );
+        let Some(path) = &self.block_values else {
+            return Ok(None);
         };
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the block values file {:?}", path))?;
+        let values = parse_block_values(&contents)
+            .map_err(|e| eyre!("Invalid block values file {:?}: {}", path, e))?;
+        Ok(Some(values))
     }
+}
 
-    if let Some(x) = args.only_blocks_above {
-        if x <= 0. {
-            bail!(
-                "Value of only_blocks_above must be positive if passed, got {}",
-                x
-            );
+impl ExportFormat {
+    /// Whether the format holds the results as scanned, for reading them back
+    /// or processing them further, rather than a summary of them.
+    fn keeps_exact_results(self) -> bool {
+        match self {
+            ExportFormat::Json | ExportFormat::Binary | ExportFormat::Ndjson => true,
+            #[cfg(feature = "sqlite")]
+            ExportFormat::Sqlite => true,
+            #[cfg(feature = "arrow")]
+            ExportFormat::Arrow => true,
+            _ => false,
         }
     }
 
-    if args.threads != 0 {
-        // Set rayon thread limit
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(args.threads)
-            .build_global()
-            .context("Unable to set thread count!")?;
+    /// The name of the file this format is written to in the output folder.
+    fn filename(self) -> &'static str {
+        match self {
+            ExportFormat::Jer => "world-gen.json",
+            ExportFormat::TallCSV => "world-gen.csv",
+            ExportFormat::Anomalies => "world-gen-anomalies.csv",
+            ExportFormat::Json => "world-gen-data.json",
+            ExportFormat::Sections => "world-gen-sections.csv",
+            ExportFormat::Peaks => "world-gen-peaks.csv",
+            ExportFormat::Spread => "world-gen-spread.csv",
+            ExportFormat::MiningRate => "world-gen-mining-rate.csv",
+            ExportFormat::Namespaces => "world-gen-namespaces.csv",
+            ExportFormat::Cumulative => "world-gen-cumulative.csv",
+            ExportFormat::Density => "world-gen-density.csv",
+            ExportFormat::Extremes => "world-gen-extremes.csv",
+            ExportFormat::Value => "world-gen-value.csv",
+            ExportFormat::Biomes => "world-gen-biomes.csv",
+            ExportFormat::Ndjson => "world-gen.ndjson",
+            ExportFormat::Binary => "world-gen-data.bin",
+            ExportFormat::Html => "report.html",
+            #[cfg(feature = "sqlite")]
+            ExportFormat::Sqlite => "world-gen.sqlite",
+            #[cfg(feature = "arrow")]
+            ExportFormat::Arrow => "world-gen.arrow",
+        }
     }
+}
 
-    let mut results_by_dim = scan_multiple(&paths_to_scan, zone, args.proto);
+/// The number of regions above which scanning a whole world has to be
+/// confirmed, as it would take a long while.
+const CONFIRM_ABOVE_REGIONS: usize = 1000;
 
-    if let Some(only_blocks_above) = args.only_blocks_above {
-        let before: usize = results_by_dim
-            .iter()
-            .map(|(f, _)| f.frequencies.len())
-            .sum();
-        remove_too_rare(&mut results_by_dim, only_blocks_above);
-        let after: usize = results_by_dim
-            .iter()
-            .map(|(f, _)| f.frequencies.len())
-            .sum();
-        info!(
-            "Filtered results by normalized frequency. {} block-dim pairs out of {} were retained.",
-            after, before
+fn print_formats() {
+    for format in ExportFormat::value_variants() {
+        let value = format.to_possible_value().expect("no variants are skipped");
+        // Only the first line of the doc comment, to keep this to one line per format.
+        let description = value
+            .get_help()
+            .map(|help| help.to_string())
+            .unwrap_or_default();
+        let description = description.lines().next().unwrap_or_default();
+        println!(
+            "{:<12} {:<26} {}",
+            value.get_name(),
+            format.filename(),
+            description
         );
     }
-
-    let (filename, data) = match args.format {
-        ExportFormat::Jer => {
-            let json_string = generate_JER_json(&results_by_dim)?;
-            ("world-gen.json", json_string)
+}
+/// Prints the dimensions found in every world, with their number of regions.
+fn print_dimensions(save_paths: &[PathBuf], server_type: ServerType) {
+    for save_path in save_paths {
+        let dimensions = world_dimensions(save_path, server_type);
+        if dimensions.is_empty() {
+            soft_warn!(
+                "No region folders were found in {:?}. Is it the folder the 'region' folder is \
+                 in?",
+                save_path
+            );
+            continue;
         }
-        ExportFormat::TallCSV => {
-            let csv_string = generate_tall_csv(&results_by_dim);
-            ("world-gen.csv", csv_string)
+        if save_paths.len() > 1 {
+            println!("{}:", save_path.display());
         }
-    };
-    let path = std::path::absolute(args.output_folder.join(filename))?;
-    std::fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(&path)?
-        .write_all(data.as_bytes())?;
-    info!("Wrote output to {:?}.", &path);
-    Ok(())
+        for (dimension, path) in dimensions {
+            let regions =
+                region_coords(&RegionFolder::new(path.clone()), None).map_or(0, |c| c.len());
+            println!(
+                "{:<40} {:>6} regions  {}",
+                dimension,
+                regions,
+                path.display()
+            );
+        }
+    }
 }
 
-fn scan_multiple(
-    dim_paths: &[(&str, std::path::PathBuf)],
-    zone: Option<Zone>,
-    proto: ProtoOption,
-) -> Vec<(BlockFrequencies, RegionVersion)> {
-    let mut results_by_dim = vec![];
-    for (dim, path) in dim_paths {
-        info!(
-            "Starting to scan dimension: {}, at {}.",
-            dim,
-            path.to_string_lossy()
-        );
-        match process_zone_in_folder(path, zone, dim, proto) {
-            DimensionScanResult::Ok(res) => results_by_dim.push(res),
-            DimensionScanResult::NoRegionsPresent => {
-                warn!(
-                    "No regions were found in dimension {} located at '{}'. The zone specified \
-                     has no regions, or the dimension isn't generated at all.",
-                    dim,
-                    path.display()
-                )
-            }
-            DimensionScanResult::NoChunksFound => {
-                warn!(
-                    "Zero scannable chunks found in dimension {} located at '{}', despite regions \
-                     being found. This might be caused by the world being of a minecraft version \
-                     that's not supported, or it might be that the existing regions in the zone \
-                     are all chunkless.",
-                    dim,
-                    path.display()
-                )
-            }
+/// The dimensions of a world, also looking in the folders next to it that
+/// Bukkit servers put the nether and the end in.
+fn world_dimensions(save_path: &Path, server_type: ServerType) -> Vec<(String, PathBuf)> {
+    let mut dimensions = discover_dimensions(save_path);
+    if server_type == ServerType::Bukkit {
+        for dimension in ["minecraft:the_nether", "minecraft:the_end"] {
+            let folder = get_save_folder_for_dimension(save_path, dimension, server_type);
+            dimensions.extend(
+                discover_dimensions(&folder)
+                    .into_iter()
+                    .filter(|(found, _)| found == dimension),
+            );
         }
     }
-    results_by_dim
+    dimensions
 }
-enum DimensionScanResult {
-    Ok((BlockFrequencies, RegionVersion)),
-    NoRegionsPresent,
-    NoChunksFound,
-}
-
-fn process_zone_in_folder<S: AsRef<std::path::Path> + std::marker::Sync>(
-    path: S,
-    zone: Option<Zone>,
-    dimension: &str,
-    proto: ProtoOption,
-) -> DimensionScanResult {
-    // RegionFileLoader takes specifically a PathBuf, so we have to clone this one
-    // for each thread.
-    let regionfolder: std::path::PathBuf = std::path::PathBuf::from(path.as_ref());
-    let loader = RegionFileLoader::new(regionfolder.clone());
 
-    let coords = region_coords(&loader, zone);
+/// The arguments that can be read from a --config file. Unknown keys are an
+/// error, so that a typo doesn't silently do nothing.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    path: Option<Vec<PathBuf>>,
+    dims: Option<Vec<String>>,
+    zone: Option<Vec<isize>>,
+    format: Option<String>,
+    threads: Option<usize>,
+    max_regions_in_flight: Option<NonZeroUsize>,
+    only_blocks_above: Option<f64>,
+    proto: Option<String>,
+}
 
-    let start = Instant::now();
-    let verbose = false;
+impl ConfigFile {
+    fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read the config file {path:?}"))?;
+        Self::parse(&text, path)
+    }
 
-    let version = determine_version(&loader, zone);
-    info!(
-        "World version detected as {}.",
-        if matches!(version, RegionVersion::AtLeast118) {
-            "at least 1.18"
-        } else {
-            "pre-1.18"
+    /// Parses the `text` of the config file at `path`. Relative save folders
+    /// are taken to be relative to the folder of the file, so that it works
+    /// from wherever the scanner is run.
+    fn parse(text: &str, path: &Path) -> Result<Self> {
+        let mut config: Self =
+            toml::from_str(text).wrap_err_with(|| format!("Invalid config file {path:?}"))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        for save_path in config.path.iter_mut().flatten() {
+            *save_path = base.join(&*save_path);
         }
-    );
-
-    let (total_freqs, valid_regions, seen_regions) = coords
-        .par_iter()
-        .map(|(x, z)| (x.0, z.0))
-        .map(|(reg_x, reg_z)| {
-            let s = regionfolder.clone();
-            let regions = RegionFileLoader::new(s);
+        Ok(config)
+    }
 
-            match regions.region(RCoord(reg_x), RCoord(reg_z)) {
-                Ok(Some(mut region)) => {
-                    info!("Processing region ({}, {}).", reg_x, reg_z);
-                    (
-                        RegionResult::Ok(count_frequencies(&mut region, verbose, dimension, proto)),
-                        1,
-                        1usize,
-                    )
-                }
-                Ok(None) => {
-                    info!("Region ({}, {}) not found.", reg_x, reg_z);
-                    (RegionResult::Ignore, 0, 1)
+    /// Fills in the arguments from the file, except the ones that were given
+    /// on the command line.
+    fn apply(self, args: &mut ScanArgs, matches: &clap::ArgMatches) -> Result<()> {
+        let from_file = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+        if let Some(path) = self.path.filter(|_| from_file("path")) {
+            args.path = path;
+        }
+        if let Some(dims) = self.dims.filter(|_| from_file("dims") && !args.all_dims) {
+            args.dims = dims;
+        }
+        if let Some(zone) = self.zone.filter(|_| from_file("zone")) {
+            args.zone = Some(zone);
+        }
+        if let Some(format) = self.format.filter(|_| from_file("format")) {
+            args.format = ExportFormat::from_str(&format, true)
+                .map_err(|e| eyre!("Invalid format in the config file: {e}"))?;
+        }
+        if let Some(threads) = self.threads.filter(|_| from_file("threads")) {
+            args.threads = threads;
+        }
+        if let Some(max) = self
+            .max_regions_in_flight
+            .filter(|_| from_file("max_regions_in_flight"))
+        {
+            args.max_regions_in_flight = Some(max);
+        }
+        if let Some(threshold) = self
+            .only_blocks_above
+            .filter(|_| from_file("only_blocks_above"))
+        {
+            args.only_blocks_above = Some(threshold);
+        }
+        if let Some(proto) = self.proto.filter(|_| from_file("proto")) {
+            args.proto = ProtoOption::from_str(&proto, true)
+                .map_err(|e| eyre!("Invalid proto option in the config file: {e}"))?;
+        }
+        // clap only sees the arguments given on the command line.
+        ensure!(
+            args.zone.is_none() || args.polygon.is_none(),
+            "The zone of the config file can't be combined with --polygon."
+        );
+        Ok(())
+    }
+}
+
+#[test]
+fn test_config_file() {
+    let config = ConfigFile::parse(
+        "path = [\"saves/World\", \"/srv/world\"]\nformat = \"tall-csv\"\nthreads = 2\n\
+         zone = [-1, 1, -1, 1]",
+        Path::new("configs/scan.toml"),
+    )
+    .unwrap();
+    assert_eq!(
+        config.path.as_deref().unwrap(),
+        [Path::new("configs/saves/World"), Path::new("/srv/world")]
+    );
+    assert!(ConfigFile::parse("thread = 2", Path::new("scan.toml")).is_err());
+
+    let scan_args = |cli: &[&str]| {
+        let matches = Args::command()
+            .try_get_matches_from([&["region_scanner", "--config", "scan.toml"], cli].concat())
+            .unwrap();
+        let args = Args::from_arg_matches(&matches).unwrap();
+        (args.scan, matches)
+    };
+    let (mut args, matches) = scan_args(&["--threads", "8"]);
+    ConfigFile::parse("format = \"tall-csv\"\nthreads = 2", Path::new("scan.toml"))
+        .unwrap()
+        .apply(&mut args, &matches)
+        .unwrap();
+    // The command line wins over the file, which wins over the defaults.
+    assert_eq!(args.threads, 8);
+    assert_eq!(args.format, ExportFormat::TallCSV);
+
+    let (mut args, matches) = scan_args(&["--polygon", "0,0 16,0 0,16"]);
+    assert!(config.apply(&mut args, &matches).is_err());
+}
+
+/// Sets up logging and error reporting. With `quiet`, only warnings and
+/// errors are logged by default.
+fn init(quiet: bool) -> Result<()> {
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", if quiet { "warn" } else { "info" });
+    }
+    pretty_env_logger::init();
+    color_eyre::install()?;
+    Ok(())
+}
+/// Where a scan writes its output.
+fn scan_output(args: &ScanArgs) -> &Path {
+    args.output_file.as_deref().unwrap_or(&args.output_folder)
+}
+
+fn main() -> Result<()> {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let output_folder = match &args.command {
+        Some(Command::Merge(merge_args)) => &merge_args.output_folder,
+        Some(Command::Compare(compare_args)) => &compare_args.output_folder,
+        Some(Command::Scan(scan_args)) => scan_output(scan_args),
+        None => scan_output(&args.scan),
+    };
+    init(is_stdout(output_folder))?;
+
+    let (mut args, matches) = match args.command {
+        Some(Command::Merge(merge_args)) => return merge(merge_args),
+        Some(Command::Compare(compare_args)) => return compare(compare_args),
+        Some(Command::Scan(scan_args)) => (*scan_args, matches.subcommand_matches("scan").unwrap()),
+        None => (args.scan, &matches),
+    };
+    silence_warnings(args.silence_warnings);
+    if args.list_formats {
+        print_formats();
+        return Ok(());
+    }
+    #[cfg(feature = "archive")]
+    let archive = match &args.archive {
+        Some(path) => Some(
+            ZipWorld::open(path, args.world.as_deref())
+                .with_context(|| format!("Failed to open the archive {:?}", path))?,
+        ),
+        None => None,
+    };
+    #[cfg(not(feature = "archive"))]
+    let archive: Option<std::convert::Infallible> = None;
+    if let Some(config) = args.config.clone() {
+        ConfigFile::load(&config)?.apply(&mut args, matches)?;
+        ensure!(
+            !args.path.is_empty() || archive.is_some(),
+            "No save folder to scan! Give one with --path or in {:?}.",
+            config
+        );
+        ensure!(
+            !args.dims.is_empty() || args.list_dims || args.all_dims,
+            "No dimensions to scan! Give them with --dims or in {:?}.",
+            config
+        );
+    }
+    for save_path in &args.path {
+        ensure!(
+            save_path.exists(),
+            "It doesn't seem like the path {:?} exists!",
+            save_path
+        );
+    }
+    if args.list_dims {
+        print_dimensions(&args.path, args.server_type);
+        return Ok(());
+    }
+    if args.all_dims {
+        args.dims = args
+            .path
+            .iter()
+            .flat_map(|save_path| world_dimensions(save_path, args.server_type))
+            .map(|(dimension, _)| dimension)
+            .unique()
+            .collect();
+        ensure!(
+            !args.dims.is_empty(),
+            "No dimensions were found in {:?}. Is it the folder the 'region' folder is in?",
+            args.path
+        );
+        info!(
+            "Found {} dimensions to scan: {}.",
+            args.dims.len(),
+            args.dims.join(", ")
+        );
+    }
+    if let Some(output_file) = &args.output_file {
+        // From here on, the file is written like any other output, just into
+        // its own folder.
+        args.output_folder = output_folder_of(output_file);
+    }
+
+    let zone: Option<Zone> = if let Some(coords) = args.zone {
+        // Necessary check because this seems to not be possible to describe in clap v4
+        // - it used to be num_values.
+        ensure!(
+            coords.len() == 4,
+            "Wrong number of zone values! Expected: 4, got: {}. See --help or examples on the \
+             repo for details.",
+            coords.len()
+        );
+        Some(Zone::try_from(coords)?)
+    } else {
+        args.polygon.as_ref().map(Polygon::bounding_zone)
+    };
+    let counts_blocks = args.scan == ScanTarget::Blocks
+        && !args.inhabited_time
+        && !args.surface_heights
+        && args.chunk_histogram.is_none()
+        && args.heatmap.is_none()
+        && args.veins.is_none();
+    ensure!(
+        args.polygon.is_none() || counts_blocks,
+        "--polygon is only supported when counting blocks."
+    );
+    ensure!(
+        args.checkpoint.is_none() || (counts_blocks && args.format != ExportFormat::Biomes),
+        "--checkpoint is only supported when counting blocks."
+    );
+    ensure!(
+        archive.is_none() || (counts_blocks && args.format != ExportFormat::Biomes),
+        "--archive is only supported when counting blocks."
+    );
+    ensure!(
+        !args.failed_regions || (counts_blocks && args.format != ExportFormat::Biomes),
+        "--failed-regions is only supported when counting blocks."
+    );
+    #[cfg(feature = "linear")]
+    ensure!(
+        args.region_format == RegionFormat::Mca
+            || (counts_blocks && args.format != ExportFormat::Biomes && archive.is_none()),
+        "--region-format linear is only supported when counting blocks in a save folder."
+    );
+
+    ensure!(
+        args.scan == ScanTarget::Blocks
+            || !(args.inhabited_time
+                || args.surface_heights
+                || args.chunk_histogram.is_some()
+                || args.heatmap.is_some()
+                || args.veins.is_some()),
+        "--inhabited-time, --surface-heights, --chunk-histogram, --heatmap and --veins can't be \
+         combined with --scan entities or --scan block-entities."
+    );
+    ensure!(
+        !args.entities_per_region || args.scan == ScanTarget::Entities,
+        "--entities-per-region is only supported with --scan entities."
+    );
+    ensure!(
+        args.locate.is_none() || args.scan == ScanTarget::BlockEntities,
+        "--locate is only supported with --scan block-entities."
+    );
+
+    // Scanning a dimension twice would only duplicate the work and the output.
+    let dims: Vec<&String> = args.dims.iter().unique().collect();
+    if dims.len() < args.dims.len() {
+        soft_warn!(
+            "Some dimensions were passed more than once, they'll only be scanned once: {}.",
+            args.dims.iter().duplicates().join(", ")
+        );
+    }
+
+    ensure!(
+        args.path.len() == 1
+            || !(args.inhabited_time
+                || args.surface_heights
+                || args.chunk_histogram.is_some()
+                || args.heatmap.is_some()
+                || args.veins.is_some()
+                || args.scan != ScanTarget::Blocks),
+        "Only blocks can be counted across several worlds at once."
+    );
+
+    let mut paths_to_scan = vec![];
+    #[cfg(feature = "archive")]
+    if let Some(world) = &archive {
+        for dimension in &dims {
+            let Some(folder) = get_path_from_dimension(dimension) else {
+                bail!("Wasn't able to parse dimension: {}", dimension);
+            };
+            ensure!(
+                world.region_folder(&folder).exists(),
+                "Dimension name `{}` resolved to `{}{}` in the archive, but there are no region \
+                 files there! Perhaps you misspelled a dimension name, or the dimension wasn't \
+                 generated yet in this world.",
+                dimension,
+                world.root(),
+                folder.to_string_lossy()
+            );
+            paths_to_scan.push((dimension.as_str(), folder));
+        }
+    }
+    // Keyed by the canonical path, so that the same folder reached through
+    // different worlds or dimensions (e.g. with symlinks) is caught.
+    let mut resolved_paths: HashMap<PathBuf, (&str, PathBuf)> = HashMap::new();
+    for (save_path, dimension) in args.path.iter().cartesian_product(&dims) {
+        let resolved = match args.scan {
+            ScanTarget::Blocks | ScanTarget::BlockEntities => get_path_from_dimension(dimension),
+            ScanTarget::Entities => get_entities_path_from_dimension(dimension),
+        };
+        match resolved {
+            Some(suffix) => {
+                let mut full_path =
+                    get_save_folder_for_dimension(save_path, dimension, args.server_type);
+                full_path.push(&suffix);
+                if args.all_dims && !full_path.exists() {
+                    // Found in another of the worlds, or has no entities folder.
+                    continue;
+                }
+                if args.scan == ScanTarget::Entities
+                    && !full_path.exists()
+                    && get_path_from_dimension(dimension).is_some_and(|blocks| {
+                        get_save_folder_for_dimension(save_path, dimension, args.server_type)
+                            .join(blocks)
+                            .exists()
+                    })
+                {
+                    soft_warn!(
+                        "Dimension `{}` has no entities folder at `{}`, skipping it. Worlds from \
+                         before 1.17 keep the entities in the chunks, and dimensions where no \
+                         entities were saved may have none.",
+                        dimension,
+                        full_path.to_string_lossy()
+                    );
+                    continue;
+                }
+                if !full_path.exists() {
+                    let bukkit_path =
+                        get_save_folder_for_dimension(save_path, dimension, ServerType::Bukkit)
+                            .join(&suffix);
+                    ensure!(
+                        args.server_type == ServerType::Bukkit || !bukkit_path.exists(),
+                        "Dimension name `{}` resolved to path `{}`, which doesn't exist, but \
+                         `{}` does. If the world is from a Bukkit, Spigot or Paper server, pass \
+                         --server-type bukkit.",
+                        dimension,
+                        full_path.to_string_lossy(),
+                        bukkit_path.to_string_lossy()
+                    );
+                    bail!(
+                        "Dimension name `{}` resolved to path `{}`, but this path doesn't exist! \
+                         Perhaps you misspelled a dimension name (note in particular that that \
+                         the vanilla dimensions are spelled `the_nether` and `the_end`), or tried \
+                         to scan a dimension that wasn't generated yet for this world. Entity \
+                         region files only exist in worlds from 1.17 on.",
+                        dimension,
+                        full_path.to_string_lossy()
+                    );
+                }
+                let canonical = full_path
+                    .canonicalize()
+                    .unwrap_or_else(|_| full_path.clone());
+                if let Some((first_dimension, first_path)) = resolved_paths.get(&canonical) {
+                    soft_warn!(
+                        "Dimension `{}` in {:?} resolved to the same folder as dimension `{}` in \
+                         {:?}, so it won't be scanned again.",
+                        dimension,
+                        full_path,
+                        first_dimension,
+                        first_path
+                    );
+                    continue;
+                }
+                resolved_paths.insert(canonical, (dimension.as_str(), full_path.clone()));
+                paths_to_scan.push((dimension.as_str(), full_path));
+            }
+            None => {
+                bail!("Wasn't able to parse dimension: {}", dimension);
+            }
+        };
+    }
+
+    ensure!(
+        args.heatmap.is_none() || paths_to_scan.len() <= 1,
+        "--heatmap maps a single dimension, but {} were passed.",
+        paths_to_scan.len()
+    );
+    #[cfg(feature = "charts")]
+    ensure!(
+        !(args.charts && is_stdout(&args.output_folder)),
+        "--charts needs an output folder to put the images in, it can't be used with --output -."
+    );
+
+    ensure!(
+        !args.confidence_intervals || args.format == ExportFormat::TallCSV,
+        "--confidence-intervals is only supported for the tall-csv format."
+    );
+    ensure!(
+        !args.csv_metadata || args.format == ExportFormat::TallCSV,
+        "--csv-metadata is only supported for the tall-csv format."
+    );
+    let block_meta = args.block_meta.load(args.format)?;
+    let block_values = args.block_values.load(args.format)?;
+
+    ensure!(
+        (args.format == ExportFormat::Anomalies) == args.baseline.is_some(),
+        "--baseline has to be passed if and only if the anomalies format is used."
+    );
+    let baseline = if let Some(baseline_path) = &args.baseline {
+        let contents = std::fs::read_to_string(baseline_path)
+            .with_context(|| format!("Failed to read the baseline file {:?}", baseline_path))?;
+        let entries: Vec<BlockJERDistributionData> = serde_json::from_str(&contents)
+            .with_context(|| format!("The baseline {:?} isn't a JER file", baseline_path))?;
+        Some(entries)
+    } else {
+        None
+    };
+
+    ensure!(
+        args.merge_into.is_none() || args.format == ExportFormat::Jer,
+        "--merge-into is only supported for the jer format."
+    );
+    let merge_into = if let Some(merge_path) = &args.merge_into {
+        let contents = std::fs::read_to_string(merge_path)
+            .with_context(|| format!("Failed to read the file to merge into {:?}", merge_path))?;
+        let entries: Vec<BlockJERDistributionData> = serde_json::from_str(&contents)
+            .with_context(|| format!("The file to merge into {:?} isn't a JER file", merge_path))?;
+        Some(entries)
+    } else {
+        None
+    };
+
+    if let Some(x) = args.only_blocks_above {
+        if x <= 0. {
+            bail!(
+                "Value of only_blocks_above must be positive if passed, got {}",
+                x
+            );
+        }
+    }
+
+    let block_whitelist = if let Some(known_blocks) = &args.known_blocks {
+        let contents = std::fs::read_to_string(known_blocks)
+            .with_context(|| format!("Failed to read the known blocks file {:?}", known_blocks))?;
+        let mut whitelist = parse_block_whitelist(&contents);
+        if args.normalize_names {
+            whitelist = whitelist
+                .iter()
+                .map(|name| normalize_block_name(name).into_owned())
+                .collect();
+        }
+        ensure!(
+            !whitelist.is_empty(),
+            "The known blocks file {:?} doesn't list any blocks!",
+            known_blocks
+        );
+        info!(
+            "Only counting the {} blocks listed in {:?}.",
+            whitelist.len(),
+            known_blocks
+        );
+        Some(whitelist)
+    } else {
+        None
+    };
+    let region_glob = args
+        .region_glob
+        .as_deref()
+        .map(glob::Pattern::new)
+        .transpose()
+        .context("Invalid --region-glob pattern")?;
+    if let Some(fraction) = args.sample_fraction {
+        ensure!(
+            fraction > 0. && fraction <= 1.,
+            "--sample-fraction must be in the (0, 1] range, got {}",
+            fraction
+        );
+    }
+    let sampling = RegionSampling {
+        fraction: args.sample_fraction,
+        max_regions: args.max_regions,
+        seed: args.sample_seed.unwrap_or_else(rand::random),
+    };
+    if sampling.is_active() {
+        info!(
+            "Scanning a sample of the regions, chosen with seed {}. Pass --sample-seed {} to \
+             scan the same sample again.",
+            sampling.seed, sampling.seed
+        );
+    }
+    let options = ScanOptions {
+        proto: args.proto,
+        statuses: args.status.as_ref().map(|statuses| {
+            statuses
+                .iter()
+                .map(|status| normalize_block_name(status).into_owned())
+                .collect()
+        }),
+        sampling,
+        region_glob,
+        block_whitelist,
+        include: args.include,
+        exclude: args.exclude,
+        normalize_names: args.normalize_names,
+        track_properties: args.track_properties,
+        #[cfg(feature = "mmap")]
+        mmap: args.mmap,
+        #[cfg(feature = "linear")]
+        region_format: args.region_format,
+        max_memory_mb: args.max_memory_mb,
+        max_regions_in_flight: args.max_regions_in_flight,
+        throttle: args.throttle.map(Duration::from_millis),
+        exposed_only: args.exposed_only,
+        single_pass: args.single_pass,
+        min_inhabited_ticks: args.min_inhabited_time,
+        polygon: args.polygon,
+        excluded_levels: args.exclude_y,
+        y_range: args.y_range,
+        skip_nether_bedrock: args.skip_nether_bedrock,
+        verbose: args.verbose,
+        parallel_chunks: args.parallel_chunks,
+    };
+
+    let template = args.out_template.as_ref().map(|template| OutputTemplate {
+        template: template.clone(),
+        dims: dims.iter().map(|dim| sanitize_file_name(dim)).join("+"),
+        world: args
+            .path
+            .iter()
+            .map(|path| {
+                std::path::absolute(path)
+                    .ok()
+                    .and_then(|path| {
+                        path.file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                    })
+                    .unwrap_or_default()
+            })
+            .join("+"),
+        time: SystemTime::now(),
+    });
+    // Checked before scanning, so that a typo doesn't waste a long scan.
+    let output_name = |format: &str, default: &str| match (&args.output_file, &template) {
+        (Some(output_file), _) => output_file_name(output_file, default),
+        (None, Some(template)) => template.fill(format),
+        (None, None) => Ok(default.to_owned()),
+    };
+    let format_name = args
+        .format
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_owned());
+    let (kind, default_name) = if args.inhabited_time {
+        ("inhabited-time", "inhabited-time.csv")
+    } else if args.scan == ScanTarget::Entities {
+        ("entities", "entities.csv")
+    } else if args.scan == ScanTarget::BlockEntities {
+        ("block-entities", "block-entities.csv")
+    } else if args.surface_heights {
+        ("surface-heights", "surface-heights.csv")
+    } else if args.chunk_histogram.is_some() {
+        ("chunk-histogram", "chunk-histogram.csv")
+    } else if args.heatmap.is_some() {
+        ("heatmap", "heatmap.csv")
+    } else if args.veins.is_some() {
+        ("veins", "veins.csv")
+    } else {
+        (format_name.as_str(), args.format.filename())
+    };
+    let filename = output_name(kind, default_name)?;
+    ensure!(
+        !(args.locate.is_some() && is_stdout(&args.output_folder)),
+        "--locate writes a second file, so it can't be used with --output -."
+    );
+    ensure!(
+        !(args.entities_per_region && is_stdout(&args.output_folder)),
+        "--entities-per-region writes a second file, so it can't be used with --output -."
+    );
+    ensure!(
+        !args.split_by_dim || (counts_blocks && args.format == ExportFormat::Jer),
+        "--split-by-dim is only supported with the JER format."
+    );
+    ensure!(
+        !(args.split_by_dim && is_stdout(&args.output_folder)),
+        "--split-by-dim writes a file per dimension, so it can't be used with --output -."
+    );
+
+    if args.threads != 0 {
+        // Set rayon thread limit
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(args.threads)
+            .build_global()
+            .context("Unable to set thread count!")?;
+    }
+    if let Some(throttle) = args.throttle {
+        info!(
+            "Pausing for {} ms before each region, on each of {} threads.",
+            throttle,
+            rayon::current_num_threads()
+        );
+    }
+    let regions_in = |path: &Path, zone: Option<Zone>| {
+        #[cfg(feature = "archive")]
+        if let Some(world) = &archive {
+            return regions_found(&world.region_folder(path), zone, &options);
+        }
+        #[cfg(feature = "linear")]
+        if options.region_format == RegionFormat::Linear {
+            return regions_found(&LinearRegionFolder::new(path.to_path_buf()), zone, &options);
+        }
+        regions_found(&RegionFolder::new(path.to_path_buf()), zone, &options)
+    };
+    if args.dry_run {
+        let mut total = 0;
+        for (dimension, path) in &paths_to_scan {
+            let regions = regions_in(path, zone)
+                .wrap_err_with(|| format!("Failed to list the regions in {path:?}"))?;
+            total += regions;
+            println!(
+                "{:<40} {:>6} regions {:>9} chunks  {}",
+                dimension,
+                regions,
+                regions * 1024,
+                path.display()
+            );
+        }
+        println!(
+            "Would scan {} regions, up to {} chunks or {} columns.",
+            total,
+            total * 1024,
+            total * 512 * 512
+        );
+        if let Some(zone) = zone {
+            println!("The zone given spans {} regions.", zone.size());
+        }
+        return Ok(());
+    }
+    if zone.is_none() && !args.yes {
+        let regions = paths_to_scan
+            .iter()
+            .map(|(_, path)| regions_in(path, None).unwrap_or(0))
+            .sum();
+        confirm_whole_world_scan(regions)?;
+    }
+    // Only created now, so that a dry run leaves no trace.
+    create_output_folder(&args.output_folder)?;
+
+    if args.inhabited_time {
+        let activity = scan_inhabited_time(&paths_to_scan, zone, &options);
+        let data = generate_inhabited_time_csv(&activity);
+        return write_output(&args.output_folder, &filename, &data);
+    }
+    if args.scan == ScanTarget::Entities {
+        let (counts, per_region) =
+            scan_entities(&paths_to_scan, zone, args.entities_per_region, &options);
+        let data = generate_entities_csv(&counts);
+        write_output(&args.output_folder, &filename, &data)?;
+        if args.entities_per_region {
+            let data = generate_region_entities_csv(&per_region);
+            write_output(&args.output_folder, "entities-per-region.csv", &data)?;
+        }
+        return Ok(());
+    }
+    if args.scan == ScanTarget::BlockEntities {
+        let counts = scan_block_entities(&paths_to_scan, zone, args.locate.as_deref(), &options);
+        let data = generate_block_entities_csv(&counts);
+        write_output(&args.output_folder, &filename, &data)?;
+        if args.locate.is_some() {
+            let data = generate_block_entity_locations_csv(&counts);
+            write_output(&args.output_folder, "block-entity-locations.csv", &data)?;
+        }
+        return Ok(());
+    }
+    if args.surface_heights {
+        let heights = scan_surface_heights(&paths_to_scan, zone, &options);
+        let data = generate_surface_heights_csv(&heights);
+        return write_output(&args.output_folder, &filename, &data);
+    }
+
+    if let Some(block) = &args.chunk_histogram {
+        let histograms = scan_chunk_histogram(&paths_to_scan, zone, block, &options);
+        let data = generate_chunk_histogram_csv(&histograms);
+        return write_output(&args.output_folder, &filename, &data);
+    }
+
+    if let Some(block) = &args.heatmap {
+        let Some((dim, path)) = paths_to_scan.first() else {
+            bail!("No dimension to map.");
+        };
+        let heatmap = scan_heatmap(dim, path, zone, block, &options);
+        let data = generate_heatmap_csv(&heatmap);
+        write_output(&args.output_folder, &filename, &data)?;
+        #[cfg(feature = "charts")]
+        if args.charts && !heatmap.counts.is_empty() {
+            let png = args.output_folder.join("heatmap.png");
+            generate_heatmap_png(&heatmap, &png)
+                .map_err(|e| eyre!("Failed to render the heatmap: {e}"))?;
+            info!("Wrote the heatmap to {:?}.", png);
+        }
+        return Ok(());
+    }
+
+    if let Some(blocks) = &args.veins {
+        let stats = scan_veins(&paths_to_scan, zone, blocks, &options);
+        let data = generate_veins_csv(&stats);
+        return write_output(&args.output_folder, &filename, &data);
+    }
+
+    if args.format == ExportFormat::Biomes {
+        let counts = scan_biomes(&paths_to_scan, zone, &options);
+        let data = generate_biomes_csv(&counts);
+        return write_output(&args.output_folder, &filename, &data);
+    }
+
+    ensure!(
+        !(args.region_manifest && is_stdout(&args.output_folder)),
+        "--region-manifest needs an output folder to write to, it can't be used with --output -."
+    );
+    ensure!(
+        !(args.failed_regions && is_stdout(&args.output_folder)),
+        "--failed-regions writes a second file, so it can't be used with --output -."
+    );
+    let manifest_folder = args.region_manifest.then_some(args.output_folder.as_path());
+    let checkpoint = args
+        .checkpoint
+        .as_deref()
+        .map(|path| Checkpoint::open(path, options.counting_fingerprint(zone)))
+        .transpose()?;
+    let log_event = |event: ScanEvent| log_scan_event(event, zone, &options);
+    let hooks = ScanHooks {
+        checkpoint: checkpoint.as_ref().map(|c| c as &dyn RegionCheckpoint),
+        on_event: Some(&log_event),
+    };
+    let results = match &archive {
+        #[cfg(feature = "archive")]
+        Some(world) => scan_archive(world, &paths_to_scan, zone, &options, &hooks),
+        _ => scan_dimensions(&paths_to_scan, zone, &options, &hooks),
+    };
+    log_outcomes(&results);
+    log_failed_regions(&results);
+    if let Some(folder) = manifest_folder {
+        write_manifests(&results.manifests, folder)?;
+    }
+    if args.failed_regions {
+        let data = generate_failed_regions_csv(&results);
+        write_output(&args.output_folder, "failed-regions.csv", &data)?;
+    }
+    let mut results_by_dim = results.frequencies;
+    if args.path.len() > 1 {
+        results_by_dim = merge_results(results_by_dim);
+        info!(
+            "Combined the results of {} worlds into {} dimensions.",
+            args.path.len(),
+            results_by_dim.len()
+        );
+    }
+
+    let canonicalize =
+        args.canonicalize || args.canonicalize_approximate || args.canonical_map.is_some();
+    if canonicalize && !args.split_by_dim && args.format.keeps_exact_results() {
+        info!(
+            "Not folding blocks into their canonical equivalents, as the {:?} format keeps the \
+             exact results.",
+            args.format
+        );
+    } else if canonicalize {
+        let mut mapping = default_canonical_blocks(args.canonicalize_approximate);
+        if let Some(map_path) = &args.canonical_map {
+            let contents = std::fs::read_to_string(map_path)
+                .with_context(|| format!("Failed to read the block mapping {:?}", map_path))?;
+            let overrides = parse_canonical_blocks(&contents).map_err(|line| {
+                eyre!(
+                    "Line {} of the block mapping {:?} is malformed.",
+                    line,
+                    map_path
+                )
+            })?;
+            mapping.extend(overrides);
+        }
+        canonicalize_blocks(&mut results_by_dim, &mapping);
+    }
+
+    if let Some(only_blocks_above) = args.only_blocks_above {
+        let before: usize = results_by_dim
+            .iter()
+            .map(|(f, _)| f.frequencies.len())
+            .sum();
+        match args.rarity_height {
+            RarityHeight::Fixed(height) => {
+                remove_too_rare_by_height(&mut results_by_dim, only_blocks_above, height)?
+            }
+            RarityHeight::Detected => {
+                for result in &mut results_by_dim {
+                    let Some(height) = detected_world_height(&result.0) else {
+                        continue;
+                    };
+                    remove_too_rare_by_height(
+                        std::slice::from_mut(result),
+                        only_blocks_above,
+                        height,
+                    )?;
+                }
+            }
+        }
+        let after: usize = results_by_dim
+            .iter()
+            .map(|(f, _)| f.frequencies.len())
+            .sum();
+        info!(
+            "Filtered results by normalized frequency. {} block-dim pairs out of {} were retained.",
+            after, before
+        );
+    }
+
+    if args.split_by_dim {
+        for result in &results_by_dim {
+            let data = generate_JER_json(std::slice::from_ref(result))?;
+            let filename = format!(
+                "world-gen.{}.json",
+                dimension_file_stem(&result.0.dimension)
+            );
+            write_output(&args.output_folder, &filename, &data)?;
+        }
+    } else if args.format == ExportFormat::Ndjson {
+        write_output_with(&args.output_folder, &filename, |writer| {
+            write_ndjson(&results_by_dim, writer)
+        })?;
+    } else {
+        let data = match args.format {
+            ExportFormat::TallCSV => generate_tall_csv(
+                &results_by_dim,
+                args.confidence_intervals,
+                args.csv_metadata,
+            )
+            .into_bytes(),
+            ExportFormat::Jer if merge_into.is_some() => {
+                let existing = merge_into.expect("checked by the guard");
+                info!("Merging into {} existing entries.", existing.len());
+                generate_JER_json_merged_into(existing, args.merge_into_area, &results_by_dim)?
+                    .into_bytes()
+            }
+            ExportFormat::Anomalies => {
+                let entries = baseline.expect("checked above");
+                // The baseline is assumed to be of the same version as the scanned world.
+                let baseline = frequencies_from_JER(&entries, |dim| {
+                    results_by_dim
+                        .iter()
+                        .find(|(f, _)| f.dimension == dim)
+                        .map_or(jer_offset(RegionVersion::AtLeast118, None), |(f, v)| {
+                            jer_offset(*v, f.min_y)
+                        })
+                });
+                let anomalies = find_anomalies(&results_by_dim, &baseline, args.anomaly_threshold);
+                info!("Found {} anomalous blocks.", anomalies.len());
+                generate_anomalies_csv(&anomalies).into_bytes()
+            }
+            format => render(
+                format,
+                &results_by_dim,
+                args.csv_metadata,
+                &args.mining,
+                block_meta.as_ref(),
+                block_values.as_ref(),
+            )?,
+        };
+        write_output(&args.output_folder, &filename, &data)?;
+    }
+
+    #[cfg(feature = "charts")]
+    if args.charts {
+        let charts = generate_charts(&results_by_dim, &args.output_folder, args.chart_blocks)
+            .map_err(|e| eyre!("Failed to render the charts: {e}"))?;
+        info!(
+            "Wrote {} charts to {:?}.",
+            charts.len(),
+            &args.output_folder
+        );
+    }
+    #[cfg(feature = "charts")]
+    if let Some(plot_dir) = &args.plot_dir {
+        let plots = generate_block_plots(&results_by_dim, plot_dir)
+            .map_err(|e| eyre!("Failed to render the plots: {e}"))?;
+        info!("Wrote {} plots to {:?}.", plots.len(), plot_dir);
+    }
+    // Only now that every output is written is the scan safe to lose.
+    if let Some(path) = &args.checkpoint {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to delete the checkpoint {:?}", path))?;
+    }
+    Ok(())
+}
+
+/// Renders the results in one of the formats that need nothing else.
+fn render(
+    format: ExportFormat,
+    results_by_dim: &[(BlockFrequencies, RegionVersion)],
+    csv_metadata: bool,
+    mining: &MiningArgs,
+    block_meta: Option<&BlockMetaTable>,
+    block_values: Option<&HashMap<String, f64>>,
+) -> Result<Vec<u8>> {
+    let text = match format {
+        ExportFormat::Binary => return Ok(generate_binary(results_by_dim)?),
+        ExportFormat::Ndjson => {
+            let mut data = vec![];
+            write_ndjson(results_by_dim, &mut data)?;
+            return Ok(data);
+        }
+        #[cfg(feature = "sqlite")]
+        ExportFormat::Sqlite => return Ok(generate_sqlite(results_by_dim)?),
+        #[cfg(feature = "arrow")]
+        ExportFormat::Arrow => return Ok(generate_arrow(results_by_dim)?),
+        ExportFormat::Jer => generate_JER_json(results_by_dim)?,
+        ExportFormat::TallCSV => generate_tall_csv(results_by_dim, false, csv_metadata),
+        ExportFormat::Json => generate_json(results_by_dim)?,
+        ExportFormat::Sections => generate_sections_csv(results_by_dim),
+        ExportFormat::Peaks => generate_peaks_csv(results_by_dim, block_meta),
+        ExportFormat::Spread => generate_spread_csv(results_by_dim, block_meta),
+        ExportFormat::MiningRate => generate_mining_rate_csv(
+            results_by_dim,
+            mining.mining_pattern,
+            mining.mined_per_hour,
+            block_meta,
+        ),
+        ExportFormat::Namespaces => generate_namespaces_csv(results_by_dim),
+        ExportFormat::Cumulative => generate_cumulative_csv(results_by_dim),
+        ExportFormat::Density => generate_density_csv(results_by_dim),
+        ExportFormat::Extremes => generate_extremes_csv(results_by_dim),
+        ExportFormat::Html => generate_html_report(results_by_dim),
+        ExportFormat::Value => generate_value_csv(
+            results_by_dim,
+            block_values.expect("checked by BlockValueArgs::load"),
+        ),
+        ExportFormat::Anomalies => {
+            bail!("The anomalies format needs a --baseline, which is only supported when scanning.")
+        }
+        ExportFormat::Biomes => {
+            bail!("The biomes format is counted from the chunks, so it's only supported when scanning.")
+        }
+    };
+    Ok(text.into_bytes())
+}
+
+/// Parses the vertices of --polygon, like '0,0 16,0 0,16'.
+fn parse_polygon(vertices: &str) -> std::result::Result<Polygon, String> {
+    let vertices = vertices
+        .split_whitespace()
+        .map(|vertex| {
+            let (x, z) = vertex
+                .split_once(',')
+                .ok_or_else(|| format!("expected a vertex like '10,-20', got '{vertex}'"))?;
+            let parse = |coord: &str| {
+                coord
+                    .parse::<isize>()
+                    .map_err(|e| format!("invalid coordinate '{coord}': {e}"))
+            };
+            Ok((parse(x)?, parse(z)?))
+        })
+        .collect::<std::result::Result<Vec<_>, String>>()?;
+    Polygon::new(vertices).ok_or_else(|| {
+        "a polygon needs at least 3 vertices, and has to be more than a line".to_owned()
+    })
+}
+
+/// Parses a range of levels for --exclude-y and --y-range, like '-64,-60'.
+fn parse_level_range(range: &str) -> std::result::Result<RangeInclusive<isize>, String> {
+    let (from, to) = range
+        .split_once(',')
+        .ok_or_else(|| format!("expected a range like '-64,-60', got '{range}'"))?;
+    let parse = |level: &str| {
+        level
+            .trim()
+            .parse::<isize>()
+            .map_err(|e| format!("invalid level '{level}': {e}"))
+    };
+    let (from, to) = (parse(from)?, parse(to)?);
+    if to < from {
+        return Err(format!("the range {from} to {to} is empty"));
+    }
+    Ok(from..=to)
+}
+
+/// The world height to use for --only-blocks-above.
+#[derive(Clone, Copy, Debug)]
+enum RarityHeight {
+    Fixed(f64),
+    /// The height of each dimension, see [`detected_world_height`].
+    Detected,
+}
+
+/// Parses --rarity-height: a positive number of levels, or 'auto'.
+fn parse_rarity_height(height: &str) -> std::result::Result<RarityHeight, String> {
+    if height.eq_ignore_ascii_case("auto") {
+        return Ok(RarityHeight::Detected);
+    }
+    match height.parse::<f64>() {
+        Ok(height) if height > 0. => Ok(RarityHeight::Fixed(height)),
+        _ => Err(format!(
+            "expected a positive height or 'auto', got '{height}'"
+        )),
+    }
+}
+
+/// Parses a glob pattern for --include and --exclude.
+fn parse_block_pattern(pattern: &str) -> std::result::Result<glob::Pattern, String> {
+    glob::Pattern::new(pattern).map_err(|e| format!("invalid pattern '{pattern}': {e}"))
+}
+
+/// The values for the placeholders of --out-template.
+struct OutputTemplate {
+    template: String,
+    dims: String,
+    world: String,
+    time: SystemTime,
+}
+
+impl OutputTemplate {
+    /// Fills in the template for an output of the given format.
+    fn fill(&self, format: &str) -> Result<String> {
+        let secs = self
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs() as i64);
+        let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+        let secs_of_day = secs.rem_euclid(86400);
+        let date = format!("{year:04}-{month:02}-{day:02}");
+        let timestamp = format!(
+            "{date}_{:02}-{:02}-{:02}",
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60
+        );
+        let filled = self
+            .template
+            .replace("{dim}", &self.dims)
+            .replace("{world}", &self.world)
+            .replace("{format}", format)
+            .replace("{date}", &date)
+            .replace("{timestamp}", &timestamp);
+        if let Some(start) = filled.find('{') {
+            if let Some(len) = filled[start..].find('}') {
+                bail!(
+                    "Unknown placeholder {} in --out-template. The supported ones are {{dim}}, \
+                     {{world}}, {{format}}, {{date}} and {{timestamp}}.",
+                    &filled[start..=start + len]
+                );
+            }
+        }
+        ensure!(
+            !filled.contains(['/', '\\']),
+            "--out-template has to produce a file name, not a path: got {:?}",
+            filled
+        );
+        Ok(filled)
+    }
+}
+
+/// Converts a number of days since 1970-01-01 to a (year, month, day) date in
+/// the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[test]
+fn test_civil_from_days() {
+    assert_eq!(civil_from_days(0), (1970, 1, 1));
+    assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    assert_eq!(civil_from_days(10956), (1999, 12, 31));
+    assert_eq!(civil_from_days(10957), (2000, 1, 1));
+    assert_eq!(civil_from_days(19782), (2024, 2, 29));
+    assert_eq!(civil_from_days(19783), (2024, 3, 1));
+    // 1900 wasn't a leap year.
+    assert_eq!(civil_from_days(-25508), (1900, 3, 1));
+}
+
+#[test]
+fn test_output_template() {
+    let template = |template: &str| OutputTemplate {
+        template: template.to_owned(),
+        dims: "minecraft_overworld+minecraft_the_nether".to_owned(),
+        world: "World".to_owned(),
+        time: SystemTime::UNIX_EPOCH + Duration::from_secs(1709211909),
+    };
+    assert_eq!(
+        template("{world}-{dim}.{format}").fill("jer").unwrap(),
+        "World-minecraft_overworld+minecraft_the_nether.jer"
+    );
+    assert_eq!(
+        template("scan_{date}_{timestamp}.csv")
+            .fill("tall-csv")
+            .unwrap(),
+        "scan_2024-02-29_2024-02-29_13-05-09.csv"
+    );
+    assert!(template("{world}_{version}.json").fill("jer").is_err());
+    assert!(template("out/{world}.json").fill("jer").is_err());
+}
+
+/// The folder that --output-file is in, which is also where everything else
+/// written alongside it goes.
+fn output_folder_of(output_file: &Path) -> PathBuf {
+    if is_stdout(output_file) {
+        return output_file.to_owned();
+    }
+    match output_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// The name of the file for --output-file, checking that it has the same
+/// extension as `default`, the name the output would have had otherwise.
+fn output_file_name(output_file: &Path, default: &str) -> Result<String> {
+    let expected = Path::new(default).extension();
+    if is_stdout(output_file) {
+        return Ok(default.to_owned());
+    }
+    ensure!(
+        output_file.extension() == expected,
+        "The --output-file {:?} should have the extension .{} to match what's written to it.",
+        output_file,
+        expected.unwrap_or_default().to_string_lossy()
+    );
+    let name = output_file
+        .file_name()
+        .ok_or_else(|| eyre!("--output-file {:?} has to name a file.", output_file))?;
+    Ok(name.to_string_lossy().into_owned())
+}
+
+/// Whether the output folder given is `-`, standing for stdout.
+fn is_stdout(output_folder: &Path) -> bool {
+    output_folder == Path::new("-")
+}
+
+fn create_output_folder(output_folder: &Path) -> Result<()> {
+    if is_stdout(output_folder) {
+        return Ok(());
+    }
+    std::fs::create_dir_all(output_folder)
+        .with_context(|| format!("Failed to create the output directory {:?}", output_folder))
+}
+
+fn write_output(output_folder: &Path, filename: &str, data: impl AsRef<[u8]>) -> Result<()> {
+    write_output_with(output_folder, filename, |writer| {
+        writer.write_all(data.as_ref())
+    })
+}
+
+/// Like [`write_output`], but with `write` writing the output piece by piece
+/// into a buffered writer, so that it never has to be in memory as a whole.
+fn write_output_with(
+    output_folder: &Path,
+    filename: &str,
+    write: impl FnOnce(&mut dyn Write) -> std::io::Result<()>,
+) -> Result<()> {
+    if is_stdout(output_folder) {
+        let mut writer = std::io::BufWriter::new(std::io::stdout().lock());
+        write(&mut writer)
+            .and_then(|()| writer.flush())
+            .context("Failed to write the output to stdout")?;
+        return Ok(());
+    }
+    let path = std::path::absolute(output_folder.join(filename))?;
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&path)?;
+    let mut writer = std::io::BufWriter::new(file);
+    write(&mut writer).and_then(|()| writer.flush())?;
+    info!("Wrote output to {:?}.", &path);
+    Ok(())
+}
+
+fn merge(args: MergeArgs) -> Result<()> {
+    ensure!(
+        !args.csv_metadata || args.format == ExportFormat::TallCSV,
+        "--csv-metadata is only supported for the tall-csv format."
+    );
+    let block_meta = args.block_meta.load(args.format)?;
+    let block_values = args.block_values.load(args.format)?;
+    let jer_version = if args.jer_pre_118 {
+        RegionVersion::Pre118
+    } else {
+        RegionVersion::AtLeast118
+    };
+    let mut all_results = vec![];
+    let mut jer_files = 0;
+    for file in &args.files {
+        let Some(entries) = read_jer_file(file)? else {
+            all_results.extend(read_results(file)?);
+            continue;
+        };
+        let area = match &args.jer_areas {
+            Some(areas) => *areas.get(jer_files).ok_or_else(|| {
+                eyre!(
+                    "--jer-areas has {} areas, but there are more JER files.",
+                    areas.len()
+                )
+            })?,
+            None => 1,
+        };
+        jer_files += 1;
+        let results = frequencies_from_JER(&entries, |_| jer_offset(jer_version, None));
+        info!(
+            "Read {} dimensions from the JER file {:?}.",
+            results.len(),
+            file
+        );
+        all_results.extend(results.into_iter().map(|mut freqs| {
+            freqs.area = area;
+            (freqs, jer_version)
+        }));
+    }
+    match &args.jer_areas {
+        Some(areas) => ensure!(
+            areas.len() == jer_files,
+            "--jer-areas has {} areas, but there are {} JER files.",
+            areas.len(),
+            jer_files
+        ),
+        None => ensure!(
+            jer_files == 0 || jer_files == args.files.len(),
+            "JER files don't record the area they were scanned over, so they can only be merged \
+             with other exports if their areas are given with --jer-areas."
+        ),
+    }
+    let merged = merge_results(all_results);
+    for (freqs, _) in &merged {
+        info!(
+            "Dimension {}: {} chunks, {} kinds of blocks.",
+            freqs.dimension,
+            freqs.chunks_counted,
+            freqs.frequencies.len()
+        );
+    }
+    create_output_folder(&args.output_folder)?;
+    if args.format == ExportFormat::Ndjson {
+        return write_output_with(&args.output_folder, args.format.filename(), |writer| {
+            write_ndjson(&merged, writer)
+        });
+    }
+    let data = render(
+        args.format,
+        &merged,
+        args.csv_metadata,
+        &args.mining,
+        block_meta.as_ref(),
+        block_values.as_ref(),
+    )?;
+    write_output(&args.output_folder, args.format.filename(), &data)
+}
+
+/// Compares the results of two worlds, scanning the ones given as save folders.
+fn compare(args: CompareArgs) -> Result<()> {
+    let zone = args.zone.map(Zone::try_from).transpose()?;
+    let options = ScanOptions {
+        proto: args.proto,
+        ..Default::default()
+    };
+    let mut sides = vec![];
+    for input in &args.inputs {
+        let results = if input.is_dir() {
+            ensure!(
+                !args.dims.is_empty(),
+                "{:?} is a save folder, so the dimensions to scan in it have to be given with \
+                 --dims.",
+                input
+            );
+            let mut dim_paths = vec![];
+            for dimension in &args.dims {
+                let suffix = get_path_from_dimension(dimension)
+                    .ok_or_else(|| eyre!("Wasn't able to parse dimension: {}", dimension))?;
+                let path =
+                    get_save_folder_for_dimension(input, dimension, args.server_type).join(suffix);
+                ensure!(
+                    path.exists(),
+                    "Dimension `{}` resolved to path {:?}, which doesn't exist!",
+                    dimension,
+                    path
+                );
+                dim_paths.push((dimension.as_str(), path));
+            }
+            let log_event = |event: ScanEvent| log_scan_event(event, zone, &options);
+            let hooks = ScanHooks {
+                on_event: Some(&log_event),
+                ..Default::default()
+            };
+            let results = scan_dimensions(&dim_paths, zone, &options, &hooks);
+            log_outcomes(&results);
+            log_failed_regions(&results);
+            results.frequencies
+        } else {
+            read_results(input)?
+        };
+        sides.push(merge_results(results));
+    }
+    let [a, b] = &sides[..] else {
+        unreachable!("clap takes exactly two inputs");
+    };
+    let mut diffs = vec![];
+    for (freqs_a, _) in a {
+        let Some((freqs_b, _)) = b.iter().find(|(f, _)| f.dimension == freqs_a.dimension) else {
+            soft_warn!(
+                "Dimension {} is only in {:?}, not comparing it.",
+                freqs_a.dimension,
+                args.inputs[0]
+            );
+            continue;
+        };
+        let diff = diff_frequencies(freqs_a, freqs_b);
+        if diff.is_unchanged() {
+            info!("Dimension {}: no differences.", diff.dimension);
+        }
+        diffs.push(diff);
+    }
+    for (freqs_b, _) in b {
+        if !a.iter().any(|(f, _)| f.dimension == freqs_b.dimension) {
+            soft_warn!(
+                "Dimension {} is only in {:?}, not comparing it.",
+                freqs_b.dimension,
+                args.inputs[1]
+            );
+        }
+    }
+    create_output_folder(&args.output_folder)?;
+    match args.format {
+        DiffFormat::Csv => write_output(
+            &args.output_folder,
+            "world-gen-diff.csv",
+            generate_diff_csv(&diffs),
+        ),
+        DiffFormat::Json => write_output(
+            &args.output_folder,
+            "world-gen-diff.json",
+            serde_json::to_string_pretty(&diffs)?,
+        ),
+    }
+}
+
+/// Reads the entries of a file if it's a JER world-gen.json, rather than an
+/// export of another format.
+fn read_jer_file(file: &Path) -> Result<Option<Vec<BlockJERDistributionData>>> {
+    if file.extension().is_none_or(|ext| ext != "json") {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read the file {:?}", file))?;
+    // An empty list could be either, and is left to the other formats.
+    Ok(
+        serde_json::from_str::<Vec<BlockJERDistributionData>>(&contents)
+            .ok()
+            .filter(|entries| !entries.is_empty()),
+    )
+}
+
+/// Reads the results from a file exported in the json, binary or tall-csv
+/// format, told apart by the .bin and .csv extensions.
+fn read_results(file: &Path) -> Result<Vec<(BlockFrequencies, RegionVersion)>> {
+    let results = if file.extension().is_some_and(|ext| ext == "bin") {
+        let contents =
+            std::fs::read(file).with_context(|| format!("Failed to read the file {:?}", file))?;
+        parse_binary(&contents).with_context(|| {
+            format!(
+                "Failed to parse {:?} - only .bin files exported with --format binary can be read",
+                file
+            )
+        })?
+    } else {
+        read_text_results(file)?
+    };
+    info!("Read {} dimensions from {:?}.", results.len(), file);
+    Ok(results)
+}
+
+/// Reads the results from a file exported in the json or tall-csv format, told
+/// apart by the .csv extension.
+fn read_text_results(file: &Path) -> Result<Vec<(BlockFrequencies, RegionVersion)>> {
+    let contents = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read the file {:?}", file))?;
+    if file.extension().is_some_and(|ext| ext == "csv") {
+        parse_tall_csv(&contents).with_context(|| {
+            format!(
+                "Failed to parse {:?} - only CSV files exported with --format tall-csv and \
+                 --csv-metadata can be read",
+                file
+            )
+        })
+    } else {
+        parse_json(&contents).with_context(|| {
+            format!(
+                "Failed to parse {:?} - only files exported with --format json, tall-csv or \
+                 binary can be read",
+                file
+            )
+        })
+    }
+}
+
+/// Logs what happens during a scan, as the library leaves that to its caller.
+/// `zone` and `options` are the ones the scan was started with.
+fn log_scan_event(event: ScanEvent, zone: Option<Zone>, options: &ScanOptions) {
+    match event {
+        ScanEvent::DimensionStarted { dimension, folder } => info!(
+            "Starting to scan dimension: {}, at {}.",
+            dimension,
+            folder.to_string_lossy()
+        ),
+        ScanEvent::VersionDetected(version) => {
+            if !warnings_silenced() {
+                info!(
+                    "World version detected as {}.",
+                    if matches!(version, RegionVersion::AtLeast118) {
+                        "at least 1.18"
+                    } else {
+                        "pre-1.18"
+                    }
+                );
+            }
+        }
+        ScanEvent::RegionStarted { x, z } => info!("Processing region ({}, {}).", x, z),
+        ScanEvent::RegionFromCheckpoint { x, z } => {
+            info!("Region ({}, {}) was already scanned.", x, z)
+        }
+        ScanEvent::RegionNotFound { x, z } => info!("Region ({}, {}) not found.", x, z),
+        ScanEvent::RegionFailed { x, z, error } => {
+            soft_warn!("Region ({x}, {z}) failed to load! Error: {error}.")
+        }
+        ScanEvent::CheckpointFailed { x, z, error } => {
+            soft_warn!("Failed to add region ({x}, {z}) to the checkpoint: {error}.")
+        }
+        ScanEvent::Progress {
+            completed,
+            total,
+            elapsed,
+        } => {
+            let remaining = elapsed.as_secs_f32() / completed as f32 * (total - completed) as f32;
+            info!(
+                "Processed {}/{} regions ({:.1}%) in {:.0}s, estimated time remaining: {:.0}s.",
+                completed,
+                total,
+                completed as f32 / total as f32 * 100.,
+                elapsed.as_secs_f32(),
+                remaining
+            );
+        }
+        ScanEvent::MemoryCapNear { counts, limit_mb } => soft_warn!(
+            "The block counts of {} take up an estimated {:.1} MB ({} distinct blocks on {} \
+             block-level pairs), close to the cap of {} MB. Consider counting only the blocks \
+             you need with --known-blocks, or scanning a smaller area with --zone or \
+             --sample-fraction.",
+            counts.dimension,
+            counts.estimated_memory() as f64 / (1024.0 * 1024.0),
+            counts.counts.len(),
+            counts.entry_count(),
+            limit_mb,
+        ),
+        ScanEvent::DimensionCounted {
+            frequencies,
+            regions_seen,
+            regions_scanned,
+            regions_failed,
+            elapsed,
+        } => {
+            info!(
+                "Tried to scan {} regions. Succeeded in scanning {}, {} failed to load.",
+                regions_seen, regions_scanned, regions_failed
+            );
+            log_dimension_statistics(frequencies, regions_seen, zone, options);
+            let elapsed_time = elapsed.as_secs_f32();
+            info!(
+                "Elapsed: {:.2}s, average of {:.2}s per scanned region, or {:.2}s per 1024 \
+                 scanned chunks.",
+                elapsed_time,
+                elapsed_time / regions_scanned as f32,
+                elapsed_time / (frequencies.chunks_counted as f32) * 1024.0
+            );
+        }
+    }
+}
+
+/// Logs how much of the area of a dimension was scanned, and what the chunks
+/// found were like.
+fn log_dimension_statistics(
+    freqs: &BlockFrequencies,
+    regions_seen: usize,
+    zone: Option<Zone>,
+    options: &ScanOptions,
+) {
+    let (regions, of_what) = if options.sampling.is_active() {
+        (regions_seen, "the area of the sampled regions")
+    } else if let Some(zone) = zone {
+        (zone.size(), "the zone specified")
+    } else {
+        (regions_seen, "the area of the regions found")
+    };
+    // An empty zone has no area to take a percentage of.
+    if regions == 0 {
+        info!("Chunks scanned: {}.", freqs.chunks_counted);
+    } else {
+        info!(
+            "Chunks scanned: {}, around {:.2}% of {}.",
+            freqs.chunks_counted,
+            (freqs.chunks_counted as f64 / (regions * 1024) as f64) * 100.0,
+            of_what
+        );
+    }
+
+    match options.proto {
+        // With --status, the chunk statuses logged below say it all.
+        _ if options.statuses.is_some() => {}
+        ProtoOption::Skip => info!("{} protochunks were skipped.", freqs.protochunks_seen),
+        ProtoOption::Include => {
+            info!(
+                "{} of the scanned chunks were protochunks.",
+                freqs.protochunks_seen
+            )
+        }
+        ProtoOption::OnlyProto => info!("All of the scanned chunks were protochunks"),
+    }
+    info!(
+        "Chunk statuses: {}.",
+        freqs
+            .statuses
+            .iter()
+            .map(|(status, count)| format!("{status}: {count}"))
+            .join(", ")
+    );
+    if freqs.chunks_failed > 0 {
+        soft_warn!(
+            "{} chunks of {} were skipped, as reading them failed. Their data is probably corrupt.",
+            freqs.chunks_failed,
+            freqs.dimension
+        );
+    }
+    info!("Area on each layer: {}", freqs.area);
+    info!("Blocks counted: {}", freqs.blocks_counted);
+}
+
+/// Logs a warning for every dimension in which nothing could be counted.
+fn log_outcomes(results: &ScanResults) {
+    for outcome in &results.outcomes {
+        let (dim, path) = (&outcome.dimension, outcome.folder.display());
+        match &outcome.status {
+            DimensionStatus::Scanned { .. } => {}
+            DimensionStatus::NoRegionsPresent => soft_warn!(
+                "No regions were found in dimension {} located at '{}'. The zone specified has \
+                 no regions, or the dimension isn't generated at all.",
+                dim,
+                path
+            ),
+            DimensionStatus::NoChunksFound => soft_warn!(
+                "Zero scannable chunks found in dimension {} located at '{}', despite regions \
+                 being found. This might be caused by the world being of a minecraft version \
+                 that's not supported, or it might be that the existing regions in the zone are \
+                 all chunkless.",
+                dim,
+                path
+            ),
+            DimensionStatus::ListingFailed(e) => log_listing_failed(dim, &outcome.folder, e),
+            DimensionStatus::Failed(e) => error!(
+                "Failed to scan dimension {} located at '{}', skipping it: {}",
+                dim, path, e
+            ),
+        }
+    }
+}
+
+/// Logs which regions failed to load across all the dimensions, so that they
+/// don't get lost among the logs of the scan.
+fn log_failed_regions(results: &ScanResults) {
+    let failed: Vec<_> = results.failed_regions().collect();
+    if failed.is_empty() {
+        return;
+    }
+    soft_warn!(
+        "{} region(s) failed to load, and weren't counted:",
+        failed.len()
+    );
+    for (dim, folder, region) in failed {
+        soft_warn!(
+            "  {} ({}, {}) in '{}': {}",
+            dim,
+            region.x,
+            region.z,
+            folder.display(),
+            region.error
+        );
+    }
+}
+
+/// Lists the regions of a dimension to scan, or logs why the region folder at
+/// `path` couldn't be listed.
+fn regions_to_scan<L: RegionSource + ?Sized>(
+    source: &L,
+    path: &Path,
+    dimension: &str,
+    zone: Option<Zone>,
+    options: &ScanOptions,
+) -> Option<Vec<(RCoord, RCoord)>> {
+    match region_coords(source, zone) {
+        Ok(coords) => Some(select_regions(coords, options)),
+        Err(e) => {
+            log_listing_failed(dimension, path, &e.to_string());
+            None
+        }
+    }
+}
+
+fn log_listing_failed(dimension: &str, path: &Path, error: &str) {
+    error!(
+        "Failed to list the region files of dimension {} in '{}', skipping it: {}. If the folder \
+         isn't readable by the current user, check its permissions.",
+        dimension,
+        path.display(),
+        error
+    );
+}
+
+/// Sums up the InhabitedTime per region of every dimension.
+fn scan_inhabited_time(
+    dim_paths: &[(&str, std::path::PathBuf)],
+    zone: Option<Zone>,
+    options: &ScanOptions,
+) -> Vec<RegionActivity> {
+    let mut activity = vec![];
+    for (dim, path) in dim_paths {
+        info!("Summing up the InhabitedTime of dimension {}.", dim);
+        let loader = RegionFolder::new(path.clone());
+        let Some(coords) = regions_to_scan(&loader, path, dim, zone, options) else {
+            continue;
+        };
+        let dim_activity: Vec<RegionActivity> = coords
+            .par_iter()
+            .filter_map(|&(x, z)| {
+                let mut region = match load_region(path, x, z, options) {
+                    Ok(Some(region)) => region,
+                    Ok(None) => return None,
+                    Err(e) => {
+                        soft_warn!("Region ({}, {}) failed to load! Error: {e:?}.", x.0, z.0);
+                        return None;
+                    }
+                };
+                let (inhabited_ticks, chunks_counted) =
+                    region_inhabited_time(&mut region, options.proto);
+                Some(RegionActivity {
+                    dimension: dim.to_string(),
+                    x: x.0,
+                    z: z.0,
+                    inhabited_ticks,
+                    chunks_counted,
+                })
+            })
+            .collect();
+        info!(
+            "Read the InhabitedTime of {} regions, {} ticks in total.",
+            dim_activity.len(),
+            dim_activity.iter().map(|r| r.inhabited_ticks).sum::<u64>()
+        );
+        activity.extend(dim_activity);
+    }
+    activity
+}
+
+/// Counts the entities of every dimension. With `per_region`, the counts of
+/// every region are returned too, keyed by its coordinates.
+fn scan_entities(
+    dim_paths: &[(&str, std::path::PathBuf)],
+    zone: Option<Zone>,
+    per_region: bool,
+    options: &ScanOptions,
+) -> (Vec<EntityCounts>, Vec<RegionEntityCounts>) {
+    let mut counts = vec![];
+    let mut region_counts = vec![];
+    for (dim, path) in dim_paths {
+        info!("Counting the entities of dimension {}.", dim);
+        let loader = RegionFolder::new(path.clone());
+        let Some(coords) = regions_to_scan(&loader, path, dim, zone, options) else {
+            continue;
+        };
+        let regions: Vec<_> = coords
+            .par_iter()
+            .filter_map(|&(x, z)| match load_region(path, x, z, options) {
+                Ok(Some(mut region)) => Some(((x.0, z.0), count_entities(&mut region, dim))),
+                Ok(None) => None,
+                Err(e) => {
+                    soft_warn!("Region ({}, {}) failed to load! Error: {e:?}.", x.0, z.0);
+                    None
+                }
+            })
+            .collect();
+        let mut dim_counts = EntityCounts::empty(dim.to_string());
+        for (coords, counts) in regions {
+            if per_region {
+                region_counts.push((coords, counts.clone()));
+            }
+            dim_counts.merge(counts);
+        }
+        info!(
+            "Counted {} entities in {} chunks.",
+            dim_counts.counts.values().sum::<u64>(),
+            dim_counts.chunks_counted
+        );
+        counts.push(dim_counts);
+    }
+    (counts, region_counts)
+}
+
+/// Counts the block entities of every dimension, locating the ones in `locate`
+/// (all of them if it's empty).
+fn scan_block_entities(
+    dim_paths: &[(&str, std::path::PathBuf)],
+    zone: Option<Zone>,
+    locate: Option<&[String]>,
+    options: &ScanOptions,
+) -> Vec<BlockEntityCounts> {
+    let should_locate = |id: &str| match locate {
+        Some([]) => true,
+        Some(ids) => ids.iter().any(|wanted| wanted == id),
+        None => false,
+    };
+    let mut counts = vec![];
+    for (dim, path) in dim_paths {
+        info!("Counting the block entities of dimension {}.", dim);
+        let loader = RegionFolder::new(path.clone());
+        let Some(coords) = regions_to_scan(&loader, path, dim, zone, options) else {
+            continue;
+        };
+        let dim_counts = coords
+            .par_iter()
+            .filter_map(|&(x, z)| match load_region(path, x, z, options) {
+                Ok(Some(mut region)) => Some(count_block_entities(
+                    &mut region,
+                    dim,
+                    options.proto,
+                    should_locate,
+                )),
+                Ok(None) => None,
+                Err(e) => {
+                    soft_warn!("Region ({}, {}) failed to load! Error: {e:?}.", x.0, z.0);
+                    None
+                }
+            })
+            .reduce(
+                || BlockEntityCounts::empty(dim.to_string()),
+                |mut main, other| {
+                    main.merge(other);
+                    main
+                },
+            );
+        info!(
+            "Counted {} block entities in {} chunks.",
+            dim_counts.counts.values().sum::<u64>(),
+            dim_counts.chunks_counted
+        );
+        counts.push(dim_counts);
+    }
+    counts
+}
+
+/// Counts the columns of every biome in every dimension.
+fn scan_biomes(
+    dim_paths: &[(&str, std::path::PathBuf)],
+    zone: Option<Zone>,
+    options: &ScanOptions,
+) -> Vec<(String, BiomeCounts)> {
+    let mut counts = vec![];
+    for (dim, path) in dim_paths {
+        info!("Counting the biomes of dimension {}.", dim);
+        let loader = RegionFolder::new(path.clone());
+        let Some(coords) = regions_to_scan(&loader, path, dim, zone, options) else {
+            continue;
+        };
+        let dim_counts = coords
+            .par_iter()
+            .filter_map(|&(x, z)| match load_region(path, x, z, options) {
+                Ok(Some(mut region)) => Some(count_biomes(&mut region, options.proto)),
+                Ok(None) => None,
+                Err(e) => {
+                    soft_warn!("Region ({}, {}) failed to load! Error: {e:?}.", x.0, z.0);
+                    None
+                }
+            })
+            .reduce(BiomeCounts::default, |mut main, other| {
+                main.merge(other);
+                main
+            });
+        info!(
+            "Found {} biomes in {} columns.",
+            dim_counts.columns.len(),
+            dim_counts.columns_counted
+        );
+        if dim_counts.chunks_failed > 0 {
+            soft_warn!(
+                "The biomes of {} chunks of {} couldn't be read, they were skipped.",
+                dim_counts.chunks_failed,
+                dim
+            );
+        }
+        counts.push((dim.to_string(), dim_counts));
+    }
+    counts
+}
+
+/// Counts a block in every chunk of every dimension.
+fn scan_chunk_histogram(
+    dim_paths: &[(&str, std::path::PathBuf)],
+    zone: Option<Zone>,
+    block: &str,
+    options: &ScanOptions,
+) -> Vec<ChunkHistogram> {
+    let mut histograms = vec![];
+    for (dim, path) in dim_paths {
+        info!("Counting {} per chunk in dimension {}.", block, dim);
+        let loader = RegionFolder::new(path.clone());
+        let Some(coords) = regions_to_scan(&loader, path, dim, zone, options) else {
+            continue;
+        };
+        let dim_histogram = coords
+            .par_iter()
+            .filter_map(|&(x, z)| match load_region(path, x, z, options) {
+                Ok(Some(mut region)) => {
+                    Some(region_chunk_histogram(&mut region, block, dim, options))
                 }
+                Ok(None) => None,
                 Err(e) => {
-                    warn!("Region ({reg_x}, {reg_z}) failed to load! Error: {e:?}.");
-                    (RegionResult::Ignore, 0, 1)
+                    soft_warn!("Region ({}, {}) failed to load! Error: {e:?}.", x.0, z.0);
+                    None
                 }
+            })
+            .reduce(
+                || ChunkHistogram::empty(dim.to_string(), block.to_owned()),
+                |mut main, other| {
+                    main.merge(other);
+                    main
+                },
+            );
+        info!("Counted {} chunks.", dim_histogram.chunks_counted);
+        if dim_histogram.chunks_counted == 0 {
+            soft_warn!("No chunks were counted in dimension {}.", dim);
+            continue;
+        }
+        histograms.push(dim_histogram);
+    }
+    histograms
+}
+
+/// Counts a block in every chunk of a dimension.
+fn scan_heatmap(
+    dim: &str,
+    path: &Path,
+    zone: Option<Zone>,
+    block: &str,
+    options: &ScanOptions,
+) -> BlockHeatmap {
+    info!("Mapping {} in dimension {}.", block, dim);
+    let loader = RegionFolder::new(path.to_path_buf());
+    let Some(coords) = regions_to_scan(&loader, path, dim, zone, options) else {
+        return BlockHeatmap::empty(dim.to_owned(), block.to_owned());
+    };
+    let heatmap = coords
+        .par_iter()
+        .filter_map(|&(x, z)| match load_region(path, x, z, options) {
+            Ok(Some(mut region)) => Some(region_block_heatmap(
+                &mut region,
+                (x.0, z.0),
+                block,
+                dim,
+                options,
+            )),
+            Ok(None) => None,
+            Err(e) => {
+                soft_warn!("Region ({}, {}) failed to load! Error: {e:?}.", x.0, z.0);
+                None
             }
         })
         .reduce(
-            || (RegionResult::Ignore, 0, 0),
-            |(main, main_count, main_seen), (other, other_count, other_seen)| {
-                let sum = match (main, other) {
-                    (RegionResult::Ok(mut freqs1), RegionResult::Ok(freqs2)) => {
-                        merge_frequencies_into(&mut freqs1, freqs2);
-                        RegionResult::Ok(freqs1)
-                    }
-                    (RegionResult::Ok(freqs1), RegionResult::Ignore) => RegionResult::Ok(freqs1),
-                    (RegionResult::Ignore, RegionResult::Ok(freqs2)) => RegionResult::Ok(freqs2),
-                    (RegionResult::Ignore, RegionResult::Ignore) => RegionResult::Ignore,
-                };
-                (sum, main_count + other_count, main_seen + other_seen)
+            || BlockHeatmap::empty(dim.to_owned(), block.to_owned()),
+            |mut main, other| {
+                main.merge(other);
+                main
             },
         );
-    let total_freqs = match total_freqs {
-        RegionResult::Ok(freqs) => freqs,
-        RegionResult::Ignore => return DimensionScanResult::NoRegionsPresent,
-    };
-    let elapsed_time = start.elapsed().as_secs_f32();
-    // print_results(&total_freqs);
     info!(
-        "Tried to scan {} regions. Succeeded in scanning {}.",
-        zone.map(|z| z.size()).unwrap_or(seen_regions),
-        valid_regions
+        "Counted {} chunks, with up to {} of the block in one.",
+        heatmap.counts.len(),
+        heatmap.max_count()
     );
-    if let Some(zone) = zone {
+    if heatmap.counts.is_empty() {
+        soft_warn!("No chunks were counted in dimension {}.", dim);
+    }
+    heatmap
+}
+
+fn scan_veins(
+    dim_paths: &[(&str, std::path::PathBuf)],
+    zone: Option<Zone>,
+    blocks: &[String],
+    options: &ScanOptions,
+) -> Vec<VeinStats> {
+    let mut stats = vec![];
+    for (dim, path) in dim_paths {
         info!(
-            "Chunks scanned: {}, around {:.2}% of the zone specified.",
-            total_freqs.chunks_counted,
-            (total_freqs.chunks_counted as f64 / (zone.size() * 1024) as f64) * 100.0
+            "Finding the veins of {} in dimension {}.",
+            blocks.join(", "),
+            dim
         );
-    } else {
+        let loader = RegionFolder::new(path.clone());
+        let Some(coords) = regions_to_scan(&loader, path, dim, zone, options) else {
+            continue;
+        };
+        let dim_stats = coords
+            .par_iter()
+            .filter_map(|&(x, z)| match load_region(path, x, z, options) {
+                Ok(Some(mut region)) => Some(count_veins(&mut region, blocks, dim, options)),
+                Ok(None) => None,
+                Err(e) => {
+                    soft_warn!("Region ({}, {}) failed to load! Error: {e:?}.", x.0, z.0);
+                    None
+                }
+            })
+            .reduce(
+                || VeinStats::empty(dim.to_string()),
+                |mut main, other| {
+                    main.merge(other);
+                    main
+                },
+            );
         info!(
-            "Chunks scanned: {}, around {:.2}% of the area of the regions found.",
-            total_freqs.chunks_counted,
-            (total_freqs.chunks_counted as f64 / (seen_regions * 1024) as f64) * 100.0
+            "Found {} veins in {} chunks.",
+            dim_stats
+                .veins
+                .values()
+                .flat_map(|sizes| sizes.values())
+                .sum::<usize>(),
+            dim_stats.chunks_counted
         );
+        if !dim_stats.too_common.is_empty() {
+            soft_warn!(
+                "{} had more than {MAX_VEIN_BLOCKS_PER_REGION} blocks in some regions of \
+                 dimension {}, too many to find veins of. Those regions were left out for them.",
+                dim_stats.too_common.iter().join(", "),
+                dim
+            );
+        }
+        if dim_stats.chunks_counted == 0 {
+            soft_warn!("No chunks were counted in dimension {}.", dim);
+            continue;
+        }
+        stats.push(dim_stats);
     }
+    stats
+}
 
-    match proto {
-        ProtoOption::Skip => info!("{} protochunks were skipped.", total_freqs.protochunks_seen),
-        ProtoOption::Include => {
-            info!(
-                "{} of the scanned chunks were protochunks.",
-                total_freqs.protochunks_seen
-            )
+/// Reads the distribution of surface heights of every dimension.
+fn scan_surface_heights(
+    dim_paths: &[(&str, std::path::PathBuf)],
+    zone: Option<Zone>,
+    options: &ScanOptions,
+) -> Vec<SurfaceHeights> {
+    let mut heights = vec![];
+    for (dim, path) in dim_paths {
+        info!("Reading the surface heights of dimension {}.", dim);
+        let loader = RegionFolder::new(path.clone());
+        let Some(coords) = regions_to_scan(&loader, path, dim, zone, options) else {
+            continue;
+        };
+        let dim_heights = coords
+            .par_iter()
+            .filter_map(|&(x, z)| match load_region(path, x, z, options) {
+                Ok(Some(mut region)) => {
+                    Some(region_surface_heights(&mut region, options.proto, dim))
+                }
+                Ok(None) => None,
+                Err(e) => {
+                    soft_warn!("Region ({}, {}) failed to load! Error: {e:?}.", x.0, z.0);
+                    None
+                }
+            })
+            .reduce(
+                || SurfaceHeights::empty(dim.to_string()),
+                |mut main, other| {
+                    main.merge(other);
+                    main
+                },
+            );
+        info!(
+            "Read the surface heights of {} chunks.",
+            dim_heights.chunks_counted
+        );
+        if dim_heights.chunks_counted == 0 {
+            soft_warn!(
+                "No chunks with a MOTION_BLOCKING heightmap were found in dimension {}.",
+                dim
+            );
+            continue;
         }
-        ProtoOption::OnlyProto => info!("All of the scanned chunks were protochunks"),
+        heights.push(dim_heights);
     }
-    info!("Area on each layer: {}", total_freqs.area);
-    info!("Blocks counted: {}", total_freqs.blocks_counted);
-    info!(
-        "Elapsed: {:.2}s, average of {:.2}s per scanned region, or {:.2}s per 1024 scanned chunks.",
-        elapsed_time,
-        elapsed_time / valid_regions as f32,
-        elapsed_time / (total_freqs.chunks_counted as f32) * 1024.0
+    heights
+}
+
+/// Writes the region manifest of every dimension into a subfolder of `folder`,
+/// for --region-manifest.
+fn write_manifests(manifests: &[(String, Vec<RegionManifest>)], folder: &Path) -> Result<()> {
+    for (dim, dim_manifests) in manifests {
+        let dim_folder = folder.join(sanitize_file_name(dim));
+        create_output_folder(&dim_folder)?;
+        let data = serde_json::to_string_pretty(dim_manifests)?;
+        write_output(&dim_folder, "scanned_regions.json", &data)?;
+    }
+    Ok(())
+}
+
+/// The number of existing regions of a source that a scan would go through,
+/// leaving out the regions of the zone that don't exist. Only lists the
+/// regions, without reading any of them.
+fn regions_found(
+    source: &impl RegionSource,
+    zone: Option<Zone>,
+    options: &ScanOptions,
+) -> std::io::Result<usize> {
+    let mut coords = region_coords(source, None)?;
+    if let Some(zone) = zone {
+        coords.retain(|(x, z)| {
+            (zone.from_x..zone.to_x).contains(&x.0) && (zone.from_z..zone.to_z).contains(&z.0)
+        });
+    }
+    Ok(select_regions(coords, options).len())
+}
+
+/// Asks on the terminal whether to go ahead with scanning every one of the
+/// `regions` found, if there are more than [`CONFIRM_ABOVE_REGIONS`] of them.
+/// Without a terminal to ask on, the scan goes ahead.
+fn confirm_whole_world_scan(regions: usize) -> Result<()> {
+    if regions <= CONFIRM_ABOVE_REGIONS || !std::io::stdin().is_terminal() {
+        return Ok(());
+    }
+    eprint!(
+        "No --zone was given, so all {regions} regions found will be scanned, which can take \
+         hours. Continue? [y/N] "
+    );
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .context("Failed to read the answer")?;
+    ensure!(
+        matches!(answer.trim(), "y" | "Y" | "yes"),
+        "Scan cancelled. Pass --zone to scan part of the world, or --yes to skip this question."
     );
-    if total_freqs.chunks_counted == 0 {
-        return DimensionScanResult::NoChunksFound;
+    Ok(())
+}
+
+/// The first line of a --checkpoint file.
+#[derive(Serialize, Deserialize)]
+struct CheckpointHeader {
+    /// See [`ScanOptions::counting_fingerprint`].
+    fingerprint: String,
+}
+
+/// The counts of the regions scanned before a scan was interrupted, read from
+/// the --checkpoint file, which the counts of every region scanned since are
+/// appended to as a JSON line. The file starts with a [`CheckpointHeader`], so
+/// that counts made with other options aren't mixed with the new ones.
+struct Checkpoint {
+    /// By region folder and coordinates. Taken out once used.
+    done: Mutex<HashMap<(PathBuf, isize, isize), BlockCounts>>,
+    file: Mutex<std::fs::File>,
+}
+
+impl Checkpoint {
+    fn open(path: &Path, fingerprint: String) -> Result<Self> {
+        let mut done = HashMap::new();
+        let mut has_header = false;
+        if path.exists() {
+            let mut contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the checkpoint {:?}", path))?;
+            // A line cut off by the interruption, whose region is scanned
+            // again. It's removed so that the next lines start on their own.
+            let complete = contents.rfind('\n').map_or(0, |end| end + 1);
+            if complete < contents.len() {
+                soft_warn!(
+                    "The last line of the checkpoint {:?} is incomplete, ignoring it.",
+                    path
+                );
+                contents.truncate(complete);
+                std::fs::write(path, &contents)
+                    .with_context(|| format!("Failed to repair the checkpoint {:?}", path))?;
+            }
+            let mut lines = contents.lines().enumerate();
+            if let Some((_, header)) = lines.next() {
+                let header: CheckpointHeader = serde_json::from_str(header).with_context(|| {
+                    format!(
+                        "The checkpoint {:?} has no header, it may be from an older version. \
+                         Delete it to start over.",
+                        path
+                    )
+                })?;
+                ensure!(
+                    header.fingerprint == fingerprint,
+                    "The checkpoint {:?} was made by a scan with another zone or other options \
+                     changing what's counted, so its counts can't be combined with the ones of \
+                     this scan. Run the same scan to resume it, or delete the checkpoint to \
+                     start over.",
+                    path
+                );
+                has_header = true;
+            }
+            for (i, line) in lines {
+                let (folder, x, z, counts): (PathBuf, isize, isize, BlockCounts) =
+                    serde_json::from_str(line).with_context(|| {
+                        format!("Line {} of the checkpoint {:?} is malformed", i + 1, path)
+                    })?;
+                done.insert((folder, x, z), counts);
+            }
+            info!(
+                "Resuming from the checkpoint {:?}, with {} regions already scanned.",
+                path,
+                done.len()
+            );
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open the checkpoint {:?}", path))?;
+        if !has_header {
+            let mut line = serde_json::to_string(&CheckpointHeader { fingerprint })?;
+            line.push('\n');
+            file.write_all(line.as_bytes())
+                .with_context(|| format!("Failed to write to the checkpoint {:?}", path))?;
+        }
+        Ok(Self {
+            done: Mutex::new(done),
+            file: Mutex::new(file),
+        })
     }
-    DimensionScanResult::Ok((total_freqs, version))
 }
 
-enum RegionResult {
-    Ok(BlockFrequencies),
-    Ignore,
+impl RegionCheckpoint for Checkpoint {
+    fn take(&self, folder: &Path, x: isize, z: isize) -> Option<BlockCounts> {
+        let key = (folder.to_path_buf(), x, z);
+        self.done.lock().unwrap().remove(&key)
+    }
+
+    fn record(
+        &self,
+        folder: &Path,
+        x: isize,
+        z: isize,
+        counts: &BlockCounts,
+    ) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(&(folder, x, z, counts))?;
+        line.push('\n');
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
 }
 
 #[allow(dead_code)]
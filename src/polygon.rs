@@ -0,0 +1,108 @@
+//! Restricting a scan to an irregular area, given as a polygon.
+
+use crate::Zone;
+
+/// A polygon in block coordinates (x, z). The vertices are at the corners of
+/// blocks, so `(0,0) (16,0) (16,16) (0,16)` covers exactly the chunk at (0,0).
+/// A column is inside if its center is.
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    vertices: Vec<(isize, isize)>,
+}
+
+impl Polygon {
+    /// Returns `None` unless the polygon has at least 3 vertices, and they
+    /// don't all lie on one line.
+    pub fn new(vertices: Vec<(isize, isize)>) -> Option<Self> {
+        let polygon = Self { vertices };
+        (polygon.vertices.len() >= 3 && !polygon.is_collinear()).then_some(polygon)
+    }
+
+    /// Whether all the vertices lie on one line, in any direction. They do if
+    /// the offset of each from the first vertex has a zero cross product with
+    /// the first nonzero one.
+    fn is_collinear(&self) -> bool {
+        let Some(&(x0, z0)) = self.vertices.first() else {
+            return true;
+        };
+        let mut offsets = self.vertices.iter().map(|&(x, z)| (x - x0, z - z0));
+        let Some((dx, dz)) = offsets.find(|&offset| offset != (0, 0)) else {
+            return true;
+        };
+        offsets.all(|(x, z)| dx * z - dz * x == 0)
+    }
+
+    fn bounds(&self) -> Option<((isize, isize), (isize, isize))> {
+        let xs = self.vertices.iter().map(|&(x, _)| x);
+        let zs = self.vertices.iter().map(|&(_, z)| z);
+        Some((
+            (xs.clone().min()?, xs.max()?),
+            (zs.clone().min()?, zs.max()?),
+        ))
+    }
+
+    /// The smallest zone of regions containing the polygon.
+    pub fn bounding_zone(&self) -> Zone {
+        let ((min_x, max_x), (min_z, max_z)) = self.bounds().expect("checked in new");
+        Zone {
+            from_x: min_x.div_euclid(512),
+            to_x: (max_x - 1).div_euclid(512) + 1,
+            from_z: min_z.div_euclid(512),
+            to_z: (max_z - 1).div_euclid(512) + 1,
+        }
+    }
+
+    /// Whether the column at block coordinates (x, z) is inside, by casting a
+    /// ray from its center along x and counting the edges crossed.
+    pub fn contains(&self, x: isize, z: isize) -> bool {
+        // Doubled, so that the center of the column is at integer coordinates.
+        let (px, pz) = (2 * x + 1, 2 * z + 1);
+        let mut inside = false;
+        for (i, &(x1, z1)) in self.vertices.iter().enumerate() {
+            let (x2, z2) = self.vertices[(i + 1) % self.vertices.len()];
+            let (x1, z1, x2, z2) = (2 * x1, 2 * z1, 2 * x2, 2 * z2);
+            // The center is never on a vertex or edge, as those are at even
+            // coordinates.
+            if (z1 > pz) != (z2 > pz) {
+                let crossing_x = x1 as f64 + (pz - z1) as f64 * (x2 - x1) as f64 / (z2 - z1) as f64;
+                if (px as f64) < crossing_x {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+}
+
+#[test]
+fn test_polygon() {
+    let chunk = Polygon::new(vec![(0, 0), (16, 0), (16, 16), (0, 16)]).unwrap();
+    let inside = itertools::iproduct!(-5..20, -5..20)
+        .filter(|&(x, z)| chunk.contains(x, z))
+        .count();
+    assert_eq!(inside, 256);
+    assert!(chunk.contains(0, 15) && !chunk.contains(16, 0) && !chunk.contains(-1, 3));
+    let zone = chunk.bounding_zone();
+    assert_eq!(
+        (zone.from_x, zone.to_x, zone.from_z, zone.to_z),
+        (0, 1, 0, 1)
+    );
+
+    // A triangle whose hypotenuse cuts through the diagonal of the square.
+    let triangle = Polygon::new(vec![(0, 0), (10, 0), (0, 10)]).unwrap();
+    assert!(triangle.contains(0, 8) && triangle.contains(4, 4) && !triangle.contains(5, 5));
+    let zone = Polygon::new(vec![(-1, 0), (513, 0), (0, 600)])
+        .unwrap()
+        .bounding_zone();
+    assert_eq!(
+        (zone.from_x, zone.to_x, zone.from_z, zone.to_z),
+        (-1, 2, 0, 2)
+    );
+
+    assert!(Polygon::new(vec![(0, 0), (10, 0)]).is_none());
+    assert!(Polygon::new(vec![(0, 0), (10, 0), (5, 0)]).is_none());
+    // Collinear along a diagonal, which has an extent along both axes.
+    assert!(Polygon::new(vec![(0, 0), (10, 10), (-4, -4), (3, 3)]).is_none());
+    assert!(Polygon::new(vec![(2, 1), (2, 1), (8, 4), (-2, -1)]).is_none());
+    assert!(Polygon::new(vec![(0, 0), (10, 10), (10, 11)]).is_some());
+}
@@ -0,0 +1,138 @@
+//! A self-contained HTML report of the frequencies, to share the results with
+//! people who won't open a CSV.
+
+use std::{collections::HashMap, fmt::Write};
+
+use itertools::Itertools;
+
+use crate::{BlockFrequencies, RegionVersion};
+
+const CHART_WIDTH: f64 = 480.;
+const CHART_HEIGHT: f64 = 160.;
+/// Room left of and below the plot for the axis labels.
+const CHART_MARGIN: f64 = 40.;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+summary{font-size:1.3em;font-weight:bold;cursor:pointer;margin:.5em 0}\
+.blocks{display:flex;flex-wrap:wrap;gap:1em}\
+figure{margin:0;border:1px solid #ddd;padding:.5em}\
+figcaption{font-family:monospace}\
+svg text{font-size:11px;fill:#555}";
+
+/// Escapes the characters with a meaning in HTML, for the names of blocks and
+/// dimensions.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders an inline SVG line chart of the frequency of a block by level, from
+/// the lowest level it was found on to the highest.
+fn level_chart(freqs: &HashMap<isize, f64>) -> Option<String> {
+    let (&min_y, &max_y) = freqs.keys().minmax().into_option()?;
+    let max_freq = freqs.values().copied().fold(0., f64::max);
+    let plot_width = CHART_WIDTH - CHART_MARGIN;
+    let plot_height = CHART_HEIGHT - CHART_MARGIN;
+    let x_of =
+        |y: isize| CHART_MARGIN + (y - min_y) as f64 / (max_y - min_y).max(1) as f64 * plot_width;
+    let y_of = |freq: f64| {
+        if max_freq > 0. {
+            plot_height * (1. - freq / max_freq)
+        } else {
+            plot_height
+        }
+    };
+    let points = (min_y..=max_y)
+        .map(|y| {
+            let freq = freqs.get(&y).copied().unwrap_or(0.);
+            format!("{:.1},{:.1}", x_of(y), y_of(freq))
+        })
+        .join(" ");
+    let mut svg = String::new();
+    write!(
+        svg,
+        "<svg width=\"{CHART_WIDTH}\" height=\"{CHART_HEIGHT}\" \
+         xmlns=\"http://www.w3.org/2000/svg\">\
+         <path d=\"M{CHART_MARGIN},0V{plot_height}H{CHART_WIDTH}\" \
+         fill=\"none\" stroke=\"#999\"/>\
+         <polyline points=\"{points}\" fill=\"none\" stroke=\"#2a6fdb\" \
+         stroke-width=\"1.5\"/>\
+         <text x=\"{CHART_MARGIN}\" y=\"{}\">y={min_y}</text>\
+         <text x=\"{CHART_WIDTH}\" y=\"{}\" text-anchor=\"end\">y={max_y}</text>\
+         <text x=\"{}\" y=\"10\" text-anchor=\"end\">{max_freq:.1e}</text>\
+         <text x=\"{}\" y=\"{plot_height}\" text-anchor=\"end\">0</text>\
+         </svg>",
+        plot_height + 15.,
+        plot_height + 15.,
+        CHART_MARGIN - 4.,
+        CHART_MARGIN - 4.,
+    )
+    .unwrap();
+    Some(svg)
+}
+
+/// Generates a single HTML file, with no external resources so that it can be
+/// emailed, showing a chart of the frequency by level of every block in a
+/// collapsible section per dimension. Blocks are ordered from the most common.
+pub fn generate_html_report(frequency_data: &[(BlockFrequencies, RegionVersion)]) -> String {
+    let mut res = String::new();
+    write!(
+        res,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>RegionScanner report</title>\n<style>{STYLE}</style>\n</head>\n<body>\n\
+         <h1>RegionScanner report</h1>\n"
+    )
+    .unwrap();
+    for (freq_data, _version) in frequency_data {
+        let blocks = freq_data
+            .sorted_blocks()
+            .into_iter()
+            .map(|(name, freqs)| (name, freqs, freqs.values().sum::<f64>()))
+            .sorted_by(|a, b| b.2.total_cmp(&a.2))
+            .collect_vec();
+        writeln!(
+            res,
+            "<details open>\n<summary>{}</summary>\n<p>{} blocks in {} chunks ({} columns).</p>\n<div class=\"blocks\">",
+            escape(&freq_data.dimension),
+            blocks.len(),
+            freq_data.chunks_counted,
+            freq_data.area
+        )
+        .unwrap();
+        for (name, freqs, _total) in blocks {
+            let Some(chart) = level_chart(freqs) else {
+                continue;
+            };
+            writeln!(
+                res,
+                "<figure><figcaption>{}</figcaption>{chart}</figure>",
+                escape(name)
+            )
+            .unwrap();
+        }
+        res.write_str("</div>\n</details>\n").unwrap();
+    }
+    res.write_str("</body>\n</html>\n").unwrap();
+    res
+}
+
+#[test]
+fn test_generate_html_report() {
+    let mut freqs = BlockFrequencies::empty("test:<dim>".to_owned());
+    freqs.frequencies = HashMap::from([
+        (
+            "minecraft:stone".to_owned(),
+            HashMap::from([(0, 0.5), (2, 1.)]),
+        ),
+        ("minecraft:iron_ore".to_owned(), HashMap::from([(1, 0.01)])),
+    ]);
+    let report = generate_html_report(&[(freqs, RegionVersion::AtLeast118)]);
+    assert!(report.contains("<summary>test:&lt;dim&gt;</summary>"));
+    // The most common block comes first, with the missing level as 0.
+    let stone = report.find("minecraft:stone").unwrap();
+    assert!(stone < report.find("minecraft:iron_ore").unwrap());
+    assert!(report.contains("points=\"40.0,60.0 260.0,120.0 480.0,0.0\""));
+    assert_eq!(report.matches("<svg").count(), 2);
+}
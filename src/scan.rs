@@ -0,0 +1,897 @@
+//! The pipeline scanning the blocks of whole dimensions, in parallel over
+//! their regions, for embedding the scanner without going through the CLI.
+
+use std::{
+    fmt::{Debug, Write},
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use fastanvil::{RCoord, Region};
+use rayon::prelude::*;
+use serde::Serialize;
+
+#[cfg(feature = "mmap")]
+use crate::MmappedRegionFolder;
+use crate::{
+    count_blocks, determine_version, filter_regions_by_glob, frequencies_from_counts,
+    merge_counts_into, open_region, region_coords, sample_regions, BlockCounts, BlockFrequencies,
+    RegionFolder, RegionScannerError, RegionSource, RegionStream, RegionVersion, ScanOptions, Zone,
+};
+#[cfg(feature = "linear")]
+use crate::{LinearRegionFolder, RegionFormat};
+
+/// Where the counts of already scanned regions are kept, so that an
+/// interrupted scan can be resumed without scanning them again.
+pub trait RegionCheckpoint: Sync {
+    /// The counts of the region at `(x, z)` in `folder`, if it was already
+    /// scanned. They're only handed out once.
+    fn take(&self, folder: &Path, x: isize, z: isize) -> Option<BlockCounts>;
+
+    /// Keeps the counts of a region that was just scanned.
+    fn record(
+        &self,
+        folder: &Path,
+        x: isize,
+        z: isize,
+        counts: &BlockCounts,
+    ) -> std::io::Result<()>;
+}
+
+/// What the caller of a scan hooks into it.
+#[derive(Clone, Copy, Default)]
+pub struct ScanHooks<'a> {
+    /// Where the counts of already scanned regions are kept, see
+    /// [`RegionCheckpoint`].
+    pub checkpoint: Option<&'a dyn RegionCheckpoint>,
+    /// Told what happens during the scan as it goes, from the rayon workers.
+    /// Nothing is logged by the scan itself, so this is how its progress can
+    /// be shown.
+    pub on_event: Option<&'a (dyn Fn(ScanEvent) + Sync)>,
+}
+
+impl ScanHooks<'_> {
+    fn emit(&self, event: ScanEvent) {
+        if let Some(on_event) = self.on_event {
+            on_event(event);
+        }
+    }
+}
+
+/// Something that happened during a scan, see [`ScanHooks::on_event`].
+pub enum ScanEvent<'a> {
+    /// The scan of a dimension in a region folder is starting.
+    DimensionStarted {
+        dimension: &'a str,
+        folder: &'a Path,
+    },
+    /// The version of the world was detected from its chunks.
+    VersionDetected(RegionVersion),
+    /// A region was loaded, and its blocks are about to be counted.
+    RegionStarted { x: isize, z: isize },
+    /// The counts of a region were taken from the checkpoint instead.
+    RegionFromCheckpoint { x: isize, z: isize },
+    /// A region to scan doesn't exist.
+    RegionNotFound { x: isize, z: isize },
+    /// A region failed to load, and is skipped.
+    RegionFailed { x: isize, z: isize, error: &'a str },
+    /// The counts of a region couldn't be added to the checkpoint.
+    CheckpointFailed {
+        x: isize,
+        z: isize,
+        error: &'a std::io::Error,
+    },
+    /// How many of the regions of a dimension were processed, sent every 10
+    /// seconds or so.
+    Progress {
+        completed: usize,
+        total: usize,
+        elapsed: Duration,
+    },
+    /// The counts of a dimension take up close to [`ScanOptions::max_memory_mb`].
+    /// Sent once per dimension.
+    MemoryCapNear {
+        counts: &'a BlockCounts,
+        limit_mb: u64,
+    },
+    /// The regions of a dimension were scanned, whether or not any chunk was
+    /// counted in them.
+    DimensionCounted {
+        frequencies: &'a BlockFrequencies,
+        regions_seen: usize,
+        regions_scanned: usize,
+        regions_failed: usize,
+        elapsed: Duration,
+    },
+}
+
+/// The frequencies of the dimensions scanned by [`scan_dimensions`], with the
+/// manifests of the region folders scanned for each and how the scan of each
+/// went.
+pub struct ScanResults {
+    /// The dimensions in which blocks were counted, in the order scanned.
+    pub frequencies: Vec<(BlockFrequencies, RegionVersion)>,
+    /// By dimension, as a dimension is scanned in several folders when several
+    /// worlds are.
+    pub manifests: Vec<(String, Vec<RegionManifest>)>,
+    /// How the scan of each dimension in each region folder went, in the order
+    /// scanned.
+    pub outcomes: Vec<DimensionOutcome>,
+}
+
+/// How the scan of a dimension in one region folder went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DimensionOutcome {
+    pub dimension: String,
+    pub folder: PathBuf,
+    pub status: DimensionStatus,
+}
+
+/// Whether blocks were counted in a dimension, and if not, why.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DimensionStatus {
+    /// Blocks were counted, and their frequencies are in
+    /// [`ScanResults::frequencies`].
+    Scanned {
+        chunks_counted: usize,
+        regions_scanned: usize,
+        regions_failed: usize,
+    },
+    /// None of the regions to scan exist.
+    NoRegionsPresent,
+    /// Regions were found, but no chunk in them could be counted.
+    NoChunksFound,
+    /// The region folder couldn't be listed, with the error.
+    ListingFailed(String),
+    /// The counts of the regions couldn't be merged, like when the checkpoint
+    /// holds those of another dimension.
+    Failed(RegionScannerError),
+}
+
+impl ScanResults {
+    /// The regions that failed to load, with their dimension and the folder
+    /// they're in, in the order scanned.
+    pub fn failed_regions(&self) -> impl Iterator<Item = (&str, &Path, &FailedRegion)> {
+        self.manifests.iter().flat_map(|(dim, manifests)| {
+            manifests.iter().flat_map(move |manifest| {
+                manifest
+                    .failed
+                    .iter()
+                    .map(move |region| (dim.as_str(), manifest.folder.as_path(), region))
+            })
+        })
+    }
+}
+
+/// Quotes a CSV field if it has commas, quotes or line breaks in it.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Generates a CSV of the regions that failed to load, with a
+/// `dim,folder,region_x,region_z,error` row for each.
+pub fn generate_failed_regions_csv(results: &ScanResults) -> String {
+    let mut res = String::from("dim,folder,region_x,region_z,error\n");
+    for (dim, folder, region) in results.failed_regions() {
+        writeln!(
+            res,
+            "{},{},{},{},{}",
+            dim,
+            csv_field(&folder.to_string_lossy()),
+            region.x,
+            region.z,
+            csv_field(&region.error)
+        )
+        .unwrap();
+    }
+    res
+}
+
+/// Scans every dimension in `dim_paths` (pairs of the dimension ID and its
+/// region folder) with [`scan_dimension`]. Dimensions in which nothing could be
+/// counted are left out of the frequencies, with the reason in the outcomes.
+pub fn scan_dimensions(
+    dim_paths: &[(&str, PathBuf)],
+    zone: Option<Zone>,
+    options: &ScanOptions,
+    hooks: &ScanHooks,
+) -> ScanResults {
+    scan_each_dimension(dim_paths, hooks, |dim, path, manifest| {
+        scan_dimension(path, zone, dim, options, manifest, hooks)
+    })
+}
+
+/// Scans every dimension with `scan`, collecting the results and how the scan
+/// of each went.
+pub(crate) fn scan_each_dimension(
+    dim_paths: &[(&str, PathBuf)],
+    hooks: &ScanHooks,
+    scan: impl Fn(&str, &Path, &Mutex<RegionManifest>) -> DimensionScanResult,
+) -> ScanResults {
+    let mut results = ScanResults {
+        frequencies: vec![],
+        manifests: vec![],
+        outcomes: vec![],
+    };
+    for (dim, path) in dim_paths {
+        let manifest = Mutex::new(RegionManifest::new(path.clone()));
+        hooks.emit(ScanEvent::DimensionStarted {
+            dimension: dim,
+            folder: path,
+        });
+        let result = scan(dim, path, &manifest);
+        let mut manifest = manifest.into_inner().unwrap();
+        manifest.sort();
+        let status = match result {
+            DimensionScanResult::Ok(res) => {
+                let status = DimensionStatus::Scanned {
+                    chunks_counted: res.0.chunks_counted,
+                    regions_scanned: manifest.scanned.len(),
+                    regions_failed: manifest.failed.len(),
+                };
+                results.frequencies.push(res);
+                status
+            }
+            DimensionScanResult::NoRegionsPresent => DimensionStatus::NoRegionsPresent,
+            DimensionScanResult::NoChunksFound => DimensionStatus::NoChunksFound,
+            DimensionScanResult::ListingFailed(e) => DimensionStatus::ListingFailed(e),
+            DimensionScanResult::Failed(e) => DimensionStatus::Failed(e),
+        };
+        results.outcomes.push(DimensionOutcome {
+            dimension: dim.to_string(),
+            folder: path.clone(),
+            status,
+        });
+        match results.manifests.iter_mut().find(|(d, _)| d == dim) {
+            Some((_, dim_manifests)) => dim_manifests.push(manifest),
+            None => results.manifests.push((dim.to_string(), vec![manifest])),
+        }
+    }
+    results
+}
+
+/// Sleeps for the pause set with [`ScanOptions::throttle`], if any.
+pub fn pause_if_throttled(options: &ScanOptions) {
+    if let Some(pause) = options.throttle {
+        std::thread::sleep(pause);
+    }
+}
+
+/// Loads a region for one of the scans that don't count blocks, after the
+/// pause set with [`ScanOptions::throttle`].
+pub fn load_region(
+    path: &Path,
+    x: RCoord,
+    z: RCoord,
+    options: &ScanOptions,
+) -> fastanvil::Result<Option<Region<RegionStream<std::fs::File>>>> {
+    pause_if_throttled(options);
+    open_region(path, x, z)
+}
+
+/// Which regions of a region folder were scanned.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RegionManifest {
+    pub folder: PathBuf,
+    pub scanned: Vec<(isize, isize)>,
+    pub not_found: Vec<(isize, isize)>,
+    pub failed: Vec<FailedRegion>,
+}
+
+/// A region that couldn't be loaded, with the error.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FailedRegion {
+    pub x: isize,
+    pub z: isize,
+    pub error: String,
+}
+
+/// What happened to a region in a scan.
+pub enum RegionOutcome {
+    Scanned,
+    NotFound,
+    Failed(String),
+}
+
+impl RegionManifest {
+    pub fn new(folder: PathBuf) -> Self {
+        Self {
+            folder,
+            scanned: vec![],
+            not_found: vec![],
+            failed: vec![],
+        }
+    }
+
+    pub fn record(&mut self, x: isize, z: isize, outcome: RegionOutcome) {
+        match outcome {
+            RegionOutcome::Scanned => self.scanned.push((x, z)),
+            RegionOutcome::NotFound => self.not_found.push((x, z)),
+            RegionOutcome::Failed(error) => self.failed.push(FailedRegion { x, z, error }),
+        }
+    }
+
+    /// Sorts the regions, as they're recorded in whatever order they finish
+    /// being scanned.
+    pub fn sort(&mut self) {
+        self.scanned.sort_unstable();
+        self.not_found.sort_unstable();
+        self.failed
+            .sort_unstable_by_key(|region| (region.x, region.z));
+    }
+}
+
+/// Applies the region glob and sampling to the regions found.
+pub fn select_regions(
+    mut coords: Vec<(RCoord, RCoord)>,
+    options: &ScanOptions,
+) -> Vec<(RCoord, RCoord)> {
+    if let Some(pattern) = &options.region_glob {
+        coords = filter_regions_by_glob(coords, pattern);
+    }
+    sample_regions(coords, &options.sampling)
+}
+
+/// The outcome of scanning the blocks of a dimension.
+pub enum DimensionScanResult {
+    Ok((BlockFrequencies, RegionVersion)),
+    /// None of the regions to scan exist.
+    NoRegionsPresent,
+    /// Regions were found, but no chunk in them could be counted.
+    NoChunksFound,
+    /// The region folder couldn't be listed, with the error.
+    ListingFailed(String),
+    /// The counts of the regions couldn't be merged.
+    Failed(RegionScannerError),
+}
+
+/// Scans the regions of a dimension in the region folder at `path` with
+/// [`scan_source`], memory-mapping them if [`ScanOptions::mmap`] is set.
+pub fn scan_dimension(
+    path: &Path,
+    zone: Option<Zone>,
+    dimension: &str,
+    options: &ScanOptions,
+    manifest: &Mutex<RegionManifest>,
+    hooks: &ScanHooks,
+) -> DimensionScanResult {
+    let dir = path.to_path_buf();
+    #[cfg(feature = "linear")]
+    if options.region_format == RegionFormat::Linear {
+        let source = LinearRegionFolder { dir };
+        return scan_source(&source, path, zone, dimension, options, manifest, hooks);
+    }
+    #[cfg(feature = "mmap")]
+    if options.mmap {
+        let source = MmappedRegionFolder { dir };
+        return scan_source(&source, path, zone, dimension, options, manifest, hooks);
+    }
+    let source = RegionFolder { dir };
+    scan_source(&source, path, zone, dimension, options, manifest, hooks)
+}
+
+/// Counts the blocks of the regions of a dimension (or the ones in `zone`)
+/// read from `source`, in parallel on the rayon thread pool, and turns the
+/// counts into frequencies. `path` is where the regions are, to tell them apart
+/// from those of other dimensions in the checkpoint of `hooks`. What happened
+/// to every region is recorded in `manifest`. With a checkpoint, the regions it
+/// has the counts of aren't scanned again, and the newly scanned ones are added
+/// to it. The progress and the statistics of the scan are sent to `hooks` as
+/// [`ScanEvent`]s.
+pub fn scan_source<L: RegionSource>(
+    source: &L,
+    path: &Path,
+    zone: Option<Zone>,
+    dimension: &str,
+    options: &ScanOptions,
+    manifest: &Mutex<RegionManifest>,
+    hooks: &ScanHooks,
+) -> DimensionScanResult {
+    let coords = match region_coords(source, zone) {
+        Ok(coords) => select_regions(coords, options),
+        Err(e) => return DimensionScanResult::ListingFailed(e.to_string()),
+    };
+
+    let start = Instant::now();
+    let verbose = options.verbose;
+
+    let version = if options.single_pass {
+        None
+    } else {
+        let Ok(version) = determine_version(source, zone) else {
+            // Tells a folder without any of the regions, like a missing one
+            // with a zone given, from regions without a readable chunk.
+            let any_present = coords
+                .iter()
+                .any(|&(x, z)| !matches!(source.region(x, z), Ok(None)));
+            if !any_present {
+                return DimensionScanResult::NoRegionsPresent;
+            }
+            return DimensionScanResult::NoChunksFound;
+        };
+        hooks.emit(ScanEvent::VersionDetected(version));
+        Some(version)
+    };
+
+    let progress = Progress::new(coords.len());
+    let memory_cap = options.max_memory_mb.map(MemoryCap::new);
+    let scan_region = |(reg_x, reg_z): (isize, isize)| {
+        let scan = || {
+            let loaded = source.region(RCoord(reg_x), RCoord(reg_z));
+            scan_loaded_region(loaded, reg_x, reg_z, verbose, dimension, options, hooks)
+        };
+        if let Some(counts) = hooks.checkpoint.and_then(|c| c.take(path, reg_x, reg_z)) {
+            hooks.emit(ScanEvent::RegionFromCheckpoint { x: reg_x, z: reg_z });
+            progress.region_done(hooks);
+            manifest
+                .lock()
+                .unwrap()
+                .record(reg_x, reg_z, RegionOutcome::Scanned);
+            return (RegionResult::Ok(counts), 1, 1);
+        }
+        pause_if_throttled(options);
+        let (result, outcome) = scan();
+        progress.region_done(hooks);
+        if let (Some(checkpoint), RegionResult::Ok(counts)) = (hooks.checkpoint, &result) {
+            if let Err(error) = checkpoint.record(path, reg_x, reg_z, counts) {
+                hooks.emit(ScanEvent::CheckpointFailed {
+                    x: reg_x,
+                    z: reg_z,
+                    error: &error,
+                });
+            }
+        }
+        let valid = usize::from(matches!(outcome, RegionOutcome::Scanned));
+        manifest.lock().unwrap().record(reg_x, reg_z, outcome);
+        (result, valid, 1)
+    };
+    let combine =
+        |(main, main_count, main_seen): (RegionResult, usize, usize),
+         (other, other_count, other_seen): (RegionResult, usize, usize)| {
+            let sum = match (main, other) {
+                (RegionResult::Ok(mut counts1), RegionResult::Ok(counts2)) => {
+                    match merge_counts_into(&mut counts1, counts2) {
+                        Ok(()) => {
+                            if let Some(cap) = &memory_cap {
+                                cap.check(&counts1, hooks);
+                            }
+                            RegionResult::Ok(counts1)
+                        }
+                        Err(e) => RegionResult::Failed(e),
+                    }
+                }
+                (RegionResult::Failed(e), _) | (_, RegionResult::Failed(e)) => {
+                    RegionResult::Failed(e)
+                }
+                (RegionResult::Ok(counts1), RegionResult::Ignore) => RegionResult::Ok(counts1),
+                (RegionResult::Ignore, RegionResult::Ok(counts2)) => RegionResult::Ok(counts2),
+                (RegionResult::Ignore, RegionResult::Ignore) => RegionResult::Ignore,
+            };
+            (sum, main_count + other_count, main_seen + other_seen)
+        };
+    let scan_batch = |batch: &[(RCoord, RCoord)]| {
+        batch
+            .par_iter()
+            .map(|(x, z)| scan_region((x.0, z.0)))
+            .reduce(|| (RegionResult::Ignore, 0, 0), combine)
+    };
+    // In bounded mode, the regions are scanned a batch at a time, each batch
+    // being merged into the total before the next one starts, so that at most
+    // `max_in_flight` regions have counts of their own at once.
+    let (total_freqs, valid_regions, seen_regions) = match options.max_regions_in_flight {
+        Some(max_in_flight) => coords
+            .chunks(max_in_flight.get())
+            .map(scan_batch)
+            .fold((RegionResult::Ignore, 0, 0), combine),
+        None => scan_batch(&coords),
+    };
+    let total_freqs = match total_freqs {
+        RegionResult::Ok(counts) => frequencies_from_counts(counts),
+        RegionResult::Ignore => return DimensionScanResult::NoRegionsPresent,
+        RegionResult::Failed(e) => return DimensionScanResult::Failed(e),
+    };
+    hooks.emit(ScanEvent::DimensionCounted {
+        frequencies: &total_freqs,
+        regions_seen: seen_regions,
+        regions_scanned: valid_regions,
+        regions_failed: manifest.lock().unwrap().failed.len(),
+        elapsed: start.elapsed(),
+    });
+    if total_freqs.chunks_counted == 0 {
+        return DimensionScanResult::NoChunksFound;
+    }
+    let version = match version {
+        Some(version) => version,
+        None => {
+            let version = total_freqs.version.expect("counted chunks have a version");
+            hooks.emit(ScanEvent::VersionDetected(version));
+            version
+        }
+    };
+    DimensionScanResult::Ok((total_freqs, version))
+}
+
+/// Share of the `--max-memory-mb` cap at which [`ScanEvent::MemoryCapNear`]
+/// is sent.
+const MEMORY_WARNING_SHARE: f64 = 0.8;
+
+/// Tells once when the estimated memory used by the frequencies of a dimension
+/// approaches the cap.
+struct MemoryCap {
+    limit_bytes: u64,
+    warned: AtomicBool,
+}
+
+impl MemoryCap {
+    fn new(limit_mb: u64) -> Self {
+        Self {
+            limit_bytes: limit_mb * 1024 * 1024,
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    fn check(&self, counts: &BlockCounts, hooks: &ScanHooks) {
+        let estimate = counts.estimated_memory();
+        if (estimate as f64) < self.limit_bytes as f64 * MEMORY_WARNING_SHARE
+            || self.warned.swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+        hooks.emit(ScanEvent::MemoryCapNear {
+            counts,
+            limit_mb: self.limit_bytes / (1024 * 1024),
+        });
+    }
+}
+
+/// How often [`ScanEvent::Progress`] is sent.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Tracks how many regions of a dimension were processed, shared across the
+/// rayon workers, and periodically tells about it.
+struct Progress {
+    total: usize,
+    start: Instant,
+    completed: AtomicUsize,
+    /// Milliseconds since `start` at the time of the last report.
+    last_report_ms: AtomicU64,
+}
+
+impl Progress {
+    fn new(total: usize) -> Self {
+        Self {
+            total,
+            start: Instant::now(),
+            completed: AtomicUsize::new(0),
+            last_report_ms: AtomicU64::new(0),
+        }
+    }
+
+    fn region_done(&self, hooks: &ScanHooks) {
+        let completed = self.completed.fetch_add(1, Ordering::Relaxed) + 1;
+        let elapsed = self.start.elapsed();
+        let elapsed_ms = elapsed.as_millis() as u64;
+        let last_report_ms = self.last_report_ms.load(Ordering::Relaxed);
+        if elapsed_ms.saturating_sub(last_report_ms) < PROGRESS_INTERVAL.as_millis() as u64
+            || completed == self.total
+        {
+            return;
+        }
+        // Only one of the workers gets to report.
+        if self
+            .last_report_ms
+            .compare_exchange(
+                last_report_ms,
+                elapsed_ms,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return;
+        }
+        hooks.emit(ScanEvent::Progress {
+            completed,
+            total: self.total,
+            elapsed,
+        });
+    }
+}
+
+/// Counts the frequencies in a region that was just loaded. Returns the result
+/// along with the number of regions scanned successfully and seen (0 or 1).
+fn scan_loaded_region<R: Read + Seek, E: Debug>(
+    loaded: Result<Option<Region<R>>, E>,
+    reg_x: isize,
+    reg_z: isize,
+    verbose: bool,
+    dimension: &str,
+    options: &ScanOptions,
+    hooks: &ScanHooks,
+) -> (RegionResult, RegionOutcome) {
+    match loaded {
+        Ok(Some(mut region)) => {
+            hooks.emit(ScanEvent::RegionStarted { x: reg_x, z: reg_z });
+            (
+                RegionResult::Ok(count_blocks(
+                    &mut region,
+                    (reg_x, reg_z),
+                    verbose,
+                    dimension,
+                    options,
+                )),
+                RegionOutcome::Scanned,
+            )
+        }
+        Ok(None) => {
+            hooks.emit(ScanEvent::RegionNotFound { x: reg_x, z: reg_z });
+            (RegionResult::Ignore, RegionOutcome::NotFound)
+        }
+        Err(e) => {
+            let error = format!("{e:?}");
+            hooks.emit(ScanEvent::RegionFailed {
+                x: reg_x,
+                z: reg_z,
+                error: &error,
+            });
+            (RegionResult::Ignore, RegionOutcome::Failed(error))
+        }
+    }
+}
+
+enum RegionResult {
+    Ok(BlockCounts),
+    Ignore,
+    Failed(RegionScannerError),
+}
+
+#[test]
+fn test_scan_dimensions() {
+    use std::collections::HashMap;
+
+    use crate::fixtures::*;
+
+    /// Hands out made-up counts for region (1, 0), to check that it isn't
+    /// scanned, and keeps what was recorded.
+    struct FakeCheckpoint {
+        recorded: Mutex<Vec<(isize, isize)>>,
+    }
+    impl RegionCheckpoint for FakeCheckpoint {
+        fn take(&self, _folder: &Path, x: isize, z: isize) -> Option<BlockCounts> {
+            let mut counts = BlockCounts::empty("test:dim".to_owned());
+            counts.chunks_counted = 1;
+            counts.columns_counted = 256;
+            counts.version = Some(RegionVersion::AtLeast118);
+            counts
+                .counts
+                .insert("minecraft:gold_block".to_owned(), HashMap::from([(0, 256)]));
+            ((x, z) == (1, 0)).then_some(counts)
+        }
+        fn record(
+            &self,
+            _folder: &Path,
+            x: isize,
+            z: isize,
+            _counts: &BlockCounts,
+        ) -> std::io::Result<()> {
+            self.recorded.lock().unwrap().push((x, z));
+            Ok(())
+        }
+    }
+
+    let dir = TempDir::new("scan");
+    std::fs::write(dir.join("r.0.0.mca"), uniform_region("minecraft:stone")).unwrap();
+    std::fs::write(dir.join("r.1.0.mca"), b"").unwrap();
+
+    let checkpoint = FakeCheckpoint {
+        recorded: Mutex::new(vec![]),
+    };
+    let missing = dir.join("missing");
+    let unreadable = TempDir::new("scan_unreadable");
+    let region = region_with(&[((0, 0), b"not nbt".to_vec())]);
+    std::fs::write(
+        unreadable.join("r.0.0.mca"),
+        region.into_inner().unwrap().into_inner(),
+    )
+    .unwrap();
+    let results = scan_dimensions(
+        &[
+            ("test:dim", dir.to_path_buf()),
+            ("test:missing", missing.clone()),
+            ("test:unreadable", unreadable.to_path_buf()),
+        ],
+        // Listing the folder would skip the empty region file.
+        Some(Zone::try_from(vec![0, 2, 0, 1]).unwrap()),
+        &ScanOptions::default(),
+        &ScanHooks {
+            checkpoint: Some(&checkpoint),
+            ..Default::default()
+        },
+    );
+    // Without a zone, the missing folder has to be listed.
+    let unlisted = scan_dimensions(
+        &[("test:missing", missing.clone())],
+        None,
+        &ScanOptions::default(),
+        &ScanHooks::default(),
+    );
+
+    assert_eq!(*checkpoint.recorded.lock().unwrap(), [(0, 0)]);
+    let [(freqs, version)] = &results.frequencies[..] else {
+        panic!("expected a single dimension");
+    };
+    assert_eq!(*version, RegionVersion::AtLeast118);
+    assert_eq!(freqs.chunks_counted, 2);
+    assert_eq!(freqs.frequencies["minecraft:stone"][&0], 0.5);
+    assert_eq!(freqs.frequencies["minecraft:gold_block"][&0], 0.5);
+    let [(dim, manifests), _, _] = &results.manifests[..] else {
+        panic!("expected three dimensions");
+    };
+    assert_eq!(dim, "test:dim");
+    assert_eq!(manifests[0].scanned, [(0, 0), (1, 0)]);
+    assert_eq!(
+        results.outcomes,
+        [
+            DimensionOutcome {
+                dimension: "test:dim".to_owned(),
+                folder: dir.to_path_buf(),
+                status: DimensionStatus::Scanned {
+                    chunks_counted: 2,
+                    regions_scanned: 2,
+                    regions_failed: 0
+                }
+            },
+            DimensionOutcome {
+                dimension: "test:missing".to_owned(),
+                folder: missing,
+                status: DimensionStatus::NoRegionsPresent
+            },
+            DimensionOutcome {
+                dimension: "test:unreadable".to_owned(),
+                folder: unreadable.to_path_buf(),
+                status: DimensionStatus::NoChunksFound
+            }
+        ]
+    );
+    assert!(unlisted.frequencies.is_empty());
+    assert!(matches!(
+        unlisted.outcomes[0].status,
+        DimensionStatus::ListingFailed(_)
+    ));
+}
+
+#[test]
+fn test_scan_events_and_failure() {
+    use crate::fixtures::*;
+
+    /// Hands out the counts of another dimension for region (1, 0).
+    struct OtherDimension;
+    impl RegionCheckpoint for OtherDimension {
+        fn take(&self, _folder: &Path, x: isize, z: isize) -> Option<BlockCounts> {
+            ((x, z) == (1, 0)).then(|| BlockCounts::empty("test:other".to_owned()))
+        }
+        fn record(&self, _: &Path, _: isize, _: isize, _: &BlockCounts) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let dir = TempDir::new("events");
+    std::fs::write(dir.join("r.0.0.mca"), uniform_region("minecraft:stone")).unwrap();
+    let events = Mutex::new(vec![]);
+    let on_event = |event: ScanEvent| {
+        let event = match event {
+            ScanEvent::DimensionStarted { dimension, .. } => format!("start {dimension}"),
+            ScanEvent::RegionStarted { x, z } => format!("region {x} {z}"),
+            ScanEvent::RegionFromCheckpoint { x, z } => format!("checkpoint {x} {z}"),
+            ScanEvent::RegionNotFound { x, z } => format!("not found {x} {z}"),
+            _ => return,
+        };
+        events.lock().unwrap().push(event);
+    };
+    let mut hooks = ScanHooks {
+        on_event: Some(&on_event),
+        ..Default::default()
+    };
+    let zone = Some(Zone::try_from(vec![0, 3, 0, 1]).unwrap());
+    let dim_paths = [("test:dim", dir.to_path_buf())];
+    let options = ScanOptions {
+        max_regions_in_flight: std::num::NonZeroUsize::new(1),
+        ..Default::default()
+    };
+    let results = scan_dimensions(&dim_paths, zone, &options, &hooks);
+    assert_eq!(results.frequencies.len(), 1);
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "start test:dim",
+            "region 0 0",
+            "not found 1 0",
+            "not found 2 0"
+        ]
+    );
+
+    // The counts of another dimension can't be merged in, which fails the
+    // dimension rather than the whole scan.
+    hooks.checkpoint = Some(&OtherDimension);
+    let results = scan_dimensions(&dim_paths, zone, &options, &hooks);
+    assert!(results.frequencies.is_empty());
+    assert_eq!(
+        results.outcomes[0].status,
+        DimensionStatus::Failed(RegionScannerError::DimensionMismatch {
+            main: "test:dim".to_owned(),
+            other: "test:other".to_owned()
+        })
+    );
+}
+
+#[test]
+fn test_scan_bounded() {
+    use std::num::NonZeroUsize;
+
+    use crate::fixtures::*;
+
+    let dir = TempDir::new("bounded");
+    for (x, block) in ["minecraft:stone", "minecraft:dirt", "minecraft:stone"]
+        .into_iter()
+        .enumerate()
+    {
+        std::fs::write(dir.join(format!("r.{x}.0.mca")), uniform_region(block)).unwrap();
+    }
+
+    let scan = |max_regions_in_flight| {
+        let options = ScanOptions {
+            max_regions_in_flight,
+            ..ScanOptions::default()
+        };
+        let mut results = scan_dimensions(
+            &[("test:dim", dir.to_path_buf())],
+            None,
+            &options,
+            &ScanHooks::default(),
+        );
+        results.frequencies.pop().unwrap().0
+    };
+    let bounded = scan(NonZeroUsize::new(2));
+    let unbounded = scan(None);
+
+    assert_eq!(bounded.chunks_counted, 3);
+    assert_eq!(bounded.frequencies, unbounded.frequencies);
+    assert_eq!(bounded.frequencies["minecraft:dirt"][&0], 1. / 3.);
+}
+
+#[test]
+fn test_failed_regions_csv() {
+    use crate::fixtures::*;
+
+    let dir = TempDir::new("failed");
+    std::fs::write(dir.join("r.0.0.mca"), uniform_region("minecraft:stone")).unwrap();
+    std::fs::write(dir.join("r.1.-1.mca"), b"not a region").unwrap();
+
+    let results = scan_dimensions(
+        &[("test:dim", dir.to_path_buf())],
+        None,
+        &ScanOptions::default(),
+        &ScanHooks::default(),
+    );
+
+    let failed: Vec<_> = results
+        .failed_regions()
+        .map(|(dim, _, region)| (dim, region.x, region.z))
+        .collect();
+    assert_eq!(failed, [("test:dim", 1, -1)]);
+    let csv = generate_failed_regions_csv(&results);
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("dim,folder,region_x,region_z,error"));
+    assert!(lines.next().unwrap().starts_with("test:dim,"));
+    assert_eq!(lines.next(), None);
+    assert_eq!(csv_field("a, \"b\""), "\"a, \"\"b\"\"\"");
+}
@@ -0,0 +1,227 @@
+//! Where the regions of a dimension are read from, so that worlds can be
+//! scanned from elsewhere than a region folder on disk.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek},
+    marker::PhantomData,
+    path::PathBuf,
+};
+
+use fastanvil::{RCoord, Region, RegionFileLoader, RegionLoader};
+
+#[cfg(feature = "mmap")]
+use crate::mmap_region;
+use crate::{open_region, RegionStream};
+
+/// A source of the regions of a dimension. Unlike fastanvil's
+/// [`RegionLoader`], the regions are only read, and implementations outside
+/// of fastanvil can report their own errors. Any [`RegionLoader`] can be used
+/// through [`LoaderSource`].
+pub trait RegionSource: Sync {
+    type Stream: Read + Seek;
+
+    /// The region at `(x, z)`, or `Ok(None)` if there's no such region.
+    fn region(&self, x: RCoord, z: RCoord) -> fastanvil::Result<Option<Region<Self::Stream>>>;
+
+    /// The coordinates of all the regions there are, in any order.
+    fn list(&self) -> io::Result<Vec<(RCoord, RCoord)>>;
+}
+
+/// A region folder on disk, like `region` in the save folder. LZ4-compressed
+/// chunks are read too, see [`open_region`].
+pub struct RegionFolder {
+    pub dir: PathBuf,
+}
+
+impl RegionFolder {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+/// Lists the `.mca` files of a region folder, leaving out the empty ones.
+fn list_region_files(dir: &std::path::Path) -> io::Result<Vec<(RCoord, RCoord)>> {
+    RegionFileLoader::new(dir.to_path_buf())
+        .list()
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+impl RegionSource for RegionFolder {
+    type Stream = RegionStream<File>;
+
+    fn region(&self, x: RCoord, z: RCoord) -> fastanvil::Result<Option<Region<Self::Stream>>> {
+        open_region(&self.dir, x, z)
+    }
+
+    fn list(&self) -> io::Result<Vec<(RCoord, RCoord)>> {
+        list_region_files(&self.dir)
+    }
+}
+
+/// A region folder on disk whose region files are memory-mapped instead of
+/// read through a `File`, see [`mmap_region`].
+#[cfg(feature = "mmap")]
+pub struct MmappedRegionFolder {
+    pub dir: PathBuf,
+}
+
+#[cfg(feature = "mmap")]
+impl RegionSource for MmappedRegionFolder {
+    type Stream = RegionStream<io::Cursor<memmap2::Mmap>>;
+
+    fn region(&self, x: RCoord, z: RCoord) -> fastanvil::Result<Option<Region<Self::Stream>>> {
+        mmap_region(&self.dir, x, z)
+    }
+
+    fn list(&self) -> io::Result<Vec<(RCoord, RCoord)>> {
+        list_region_files(&self.dir)
+    }
+}
+
+/// Reads the regions of a fastanvil [`RegionLoader`], such as one loading them
+/// from memory or over the network. Its errors are turned into I/O errors.
+pub struct LoaderSource<L, S> {
+    pub loader: L,
+    stream: PhantomData<fn() -> S>,
+}
+
+impl<L, S> LoaderSource<L, S> {
+    pub fn new(loader: L) -> Self {
+        Self {
+            loader,
+            stream: PhantomData,
+        }
+    }
+}
+
+impl<L, S> RegionSource for LoaderSource<L, S>
+where
+    L: RegionLoader<S> + Sync,
+    S: Read + Seek + io::Write,
+{
+    type Stream = S;
+
+    fn region(&self, x: RCoord, z: RCoord) -> fastanvil::Result<Option<Region<S>>> {
+        self.loader
+            .region(x, z)
+            .map_err(|e| io::Error::other(e.to_string()).into())
+    }
+
+    fn list(&self) -> io::Result<Vec<(RCoord, RCoord)>> {
+        self.loader
+            .list()
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+}
+
+#[test]
+fn test_scan_in_memory_loader() {
+    use std::{collections::HashMap, io::Cursor, path::Path, sync::Mutex};
+
+    use fastanvil::LoaderResult;
+
+    use crate::{
+        fixtures::*, scan_source, DimensionScanResult, RegionManifest, ScanHooks, ScanOptions,
+    };
+
+    struct MemoryLoader(HashMap<(isize, isize), Vec<u8>>);
+    impl RegionLoader<Cursor<Vec<u8>>> for MemoryLoader {
+        fn region(&self, x: RCoord, z: RCoord) -> LoaderResult<Option<Region<Cursor<Vec<u8>>>>> {
+            Ok(self
+                .0
+                .get(&(x.0, z.0))
+                .map(|data| Region::from_stream(Cursor::new(data.clone())).unwrap()))
+        }
+        fn list(&self) -> LoaderResult<Vec<(RCoord, RCoord)>> {
+            Ok(self
+                .0
+                .keys()
+                .map(|&(x, z)| (RCoord(x), RCoord(z)))
+                .collect())
+        }
+    }
+
+    let source = LoaderSource::new(MemoryLoader(HashMap::from([
+        ((-1, 0), uniform_region("minecraft:stone")),
+        ((0, 0), uniform_region("minecraft:dirt")),
+    ])));
+    let path = Path::new("memory");
+    let manifest = Mutex::new(RegionManifest::new(path.to_path_buf()));
+    let result = scan_source(
+        &source,
+        path,
+        None,
+        "test:dim",
+        &ScanOptions::default(),
+        &manifest,
+        &ScanHooks::default(),
+    );
+    let DimensionScanResult::Ok((freqs, _version)) = result else {
+        panic!("expected the regions to be scanned");
+    };
+    assert_eq!(freqs.chunks_counted, 2);
+    assert_eq!(freqs.frequencies["minecraft:stone"][&0], 0.5);
+    assert_eq!(freqs.frequencies["minecraft:dirt"][&0], 0.5);
+    assert_eq!(manifest.into_inner().unwrap().scanned.len(), 2);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn test_mmapped_region_folder() {
+    use std::sync::Mutex;
+
+    use crate::{
+        fixtures::*, scan_source, DimensionScanResult, RegionManifest, ScanHooks, ScanOptions,
+    };
+
+    let dir = TempDir::new("mmap");
+    let chunk = |blocks: &[&str]| {
+        ChunkNbt::full(vec![SectionNbt::with_blocks(0, blocks, |x, y, _| {
+            usize::from(x < y)
+        })])
+        .to_bytes()
+    };
+    // A chunk with each compression scheme, LZ4 included.
+    let data = raw_region_with(&[
+        ((0, 0), 1, chunk(&["minecraft:stone", "minecraft:iron_ore"])),
+        ((5, 3), 2, chunk(&["minecraft:stone", "minecraft:coal_ore"])),
+        ((31, 31), 3, chunk(&["minecraft:dirt", "minecraft:stone"])),
+        (
+            (7, 0),
+            4,
+            chunk(&["minecraft:deepslate", "minecraft:gold_ore"]),
+        ),
+    ]);
+    std::fs::write(dir.join("r.0.-1.mca"), data).unwrap();
+
+    fn scan(source: &impl RegionSource, dir: &std::path::Path) -> crate::BlockFrequencies {
+        let manifest = Mutex::new(RegionManifest::new(dir.to_path_buf()));
+        let options = ScanOptions::default();
+        match scan_source(
+            source,
+            dir,
+            None,
+            "test:dim",
+            &options,
+            &manifest,
+            &ScanHooks::default(),
+        ) {
+            DimensionScanResult::Ok((freqs, _version)) => freqs,
+            _ => panic!("expected the region to be scanned"),
+        }
+    }
+    let streamed = scan(&RegionFolder::new(dir.to_path_buf()), &dir);
+    let mmapped = scan(
+        &MmappedRegionFolder {
+            dir: dir.to_path_buf(),
+        },
+        &dir,
+    );
+
+    assert_eq!(streamed.chunks_counted, 4);
+    assert_eq!(mmapped.chunks_counted, streamed.chunks_counted);
+    assert_eq!(mmapped.blocks_counted, streamed.blocks_counted);
+    assert_eq!(mmapped.frequencies, streamed.frequencies);
+    assert!(mmapped.frequencies.contains_key("minecraft:gold_ore"));
+}
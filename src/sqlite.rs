@@ -0,0 +1,114 @@
+//! Exporting the results as an SQLite database, for querying them with SQL.
+
+use rusqlite::{params, Connection, MAIN_DB};
+
+use crate::{BlockFrequencies, RegionVersion};
+
+const SCHEMA: &str = "
+CREATE TABLE dimensions (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    region_version TEXT NOT NULL,
+    area INTEGER NOT NULL,
+    blocks_counted INTEGER NOT NULL,
+    chunks_counted INTEGER NOT NULL,
+    protochunks_seen INTEGER NOT NULL,
+    min_y INTEGER
+);
+CREATE TABLE blocks (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE frequencies (
+    dim_id INTEGER NOT NULL REFERENCES dimensions(id),
+    block_id INTEGER NOT NULL REFERENCES blocks(id),
+    y INTEGER NOT NULL,
+    freq REAL NOT NULL,
+    PRIMARY KEY (dim_id, block_id, y)
+);
+";
+
+/// Renders the results as an SQLite database file, which is built in memory.
+/// The `dimensions` table holds the metadata of every dimension,
+/// `blocks` the names of the blocks, and `frequencies(dim_id, block_id, y,
+/// freq)` the frequencies, like the rows of the tall CSV.
+pub fn generate_sqlite(
+    frequency_data: &[(BlockFrequencies, RegionVersion)],
+) -> rusqlite::Result<Vec<u8>> {
+    let mut conn = Connection::open_in_memory()?;
+    conn.execute_batch(SCHEMA)?;
+    let tx = conn.transaction()?;
+    {
+        let mut insert_dim = tx.prepare(
+            "INSERT INTO dimensions (name, region_version, area, blocks_counted, chunks_counted, \
+             protochunks_seen, min_y) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        let mut insert_block = tx.prepare("INSERT OR IGNORE INTO blocks (name) VALUES (?1)")?;
+        let mut block_id = tx.prepare("SELECT id FROM blocks WHERE name = ?1")?;
+        let mut insert_freq = tx.prepare(
+            "INSERT INTO frequencies (dim_id, block_id, y, freq) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (freq_data, version) in frequency_data {
+            insert_dim.execute(params![
+                freq_data.dimension,
+                format!("{version:?}"),
+                freq_data.area,
+                freq_data.blocks_counted,
+                freq_data.chunks_counted,
+                freq_data.protochunks_seen,
+                freq_data.min_y.map(|y| y as i64),
+            ])?;
+            let dim_id = tx.last_insert_rowid();
+            for (name, freqs) in freq_data.sorted_blocks() {
+                insert_block.execute([name])?;
+                let block_id: i64 = block_id.query_row([name], |row| row.get(0))?;
+                for (y, freq) in freqs {
+                    insert_freq.execute(params![dim_id, block_id, *y as i64, freq])?;
+                }
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(conn.serialize(MAIN_DB)?.to_vec())
+}
+
+#[test]
+fn test_generate_sqlite() {
+    use std::collections::HashMap;
+
+    let mut freqs = BlockFrequencies::empty("minecraft:overworld".to_owned());
+    freqs.area = 256;
+    freqs.frequencies.insert(
+        "minecraft:diamond_ore".to_owned(),
+        HashMap::from([(-59, 0.25), (-58, 0.125)]),
+    );
+    let mut nether = BlockFrequencies::empty("minecraft:the_nether".to_owned());
+    nether.frequencies.insert(
+        "minecraft:diamond_ore".to_owned(),
+        HashMap::from([(10, 0.5)]),
+    );
+    let data = generate_sqlite(&[
+        (freqs, RegionVersion::AtLeast118),
+        (nether, RegionVersion::AtLeast118),
+    ])
+    .unwrap();
+    // It's a regular database file.
+    assert!(data.starts_with(b"SQLite format 3\0"));
+
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.deserialize_read_exact(MAIN_DB, data.as_slice(), data.len(), true)
+        .unwrap();
+    let blocks: i64 = conn
+        .query_row("SELECT COUNT(*) FROM blocks", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(blocks, 1);
+    let (y, freq): (i64, f64) = conn
+        .query_row(
+            "SELECT y, freq FROM frequencies JOIN dimensions ON dim_id = dimensions.id \
+             WHERE dimensions.name = 'minecraft:overworld' ORDER BY freq DESC LIMIT 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((y, freq), (-59, 0.25));
+}
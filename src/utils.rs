@@ -1,19 +1,477 @@
-use std::fs::File;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, ErrorKind, Read, Seek, SeekFrom},
+    path::Path,
+};
 
-use fastanvil::{ChunkData, Region};
-use itertools::iproduct;
+use fastanvil::{ChunkData, CompressionScheme, RCoord, Region};
+use itertools::{iproduct, Itertools};
+use log::debug;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
 
-/// Used instead of Region.iter(), which skips over missing chunks
-pub fn chunks(region: &mut Region<File>) -> impl Iterator<Item = Option<ChunkData>> + '_ {
+/// A chunk that the header of its region points to, but that couldn't be
+/// read from the region file.
+#[derive(Debug)]
+pub struct UnreadableChunk {
+    pub x: usize,
+    pub z: usize,
+    pub error: fastanvil::Error,
+}
+
+impl UnreadableChunk {
+    /// Logs that the chunk is skipped, and why.
+    pub fn warn(&self) {
+        let (x, z) = (self.x, self.z);
+        match &self.error {
+            // The header claims the chunk is in sectors past the end of the
+            // file, which happens with truncated region files.
+            fastanvil::Error::IO(e) if e.kind() == ErrorKind::UnexpectedEof => soft_warn!(
+                "Chunk ({x}, {z}) lies beyond the end of the region file, which is probably \
+                 truncated. Skipping it."
+            ),
+            e => soft_warn!("Failed to read chunk ({x}, {z}), skipping it. Error: {e}."),
+        }
+    }
+}
+
+/// Used instead of Region.iter(), which skips over missing chunks. Yields
+/// `None` for the chunks missing from the region, and an error for the ones
+/// that fail to be read, so that a partially corrupt region still yields its
+/// readable chunks. See [`readable_chunks`] for skipping the unreadable ones.
+pub fn chunks<R: Read + Seek>(
+    region: &mut Region<R>,
+) -> impl Iterator<Item = Option<Result<ChunkData, UnreadableChunk>>> + '_ {
     // x should be the first-changing index - see header_pos in fastanvil
-    iproduct!(0..32, 0..32).map(|(chunk_z, chunk_x)| {
-        region
-            .read_chunk(chunk_x, chunk_z)
-            .unwrap()
-            .map(|data| ChunkData {
+    iproduct!(0..32, 0..32).map(
+        |(chunk_z, chunk_x)| match region.read_chunk(chunk_x, chunk_z) {
+            Ok(data) => data.map(|data| {
+                Ok(ChunkData {
+                    x: chunk_x,
+                    z: chunk_z,
+                    data,
+                })
+            }),
+            Err(error) => Some(Err(UnreadableChunk {
                 x: chunk_x,
                 z: chunk_z,
-                data,
-            })
-    })
+                error,
+            })),
+        },
+    )
+}
+
+/// The chunks of a region that can be read, skipping the others with a
+/// warning.
+pub fn readable_chunks<R: Read + Seek>(
+    region: &mut Region<R>,
+) -> impl Iterator<Item = ChunkData> + '_ {
+    chunks(region)
+        .flatten()
+        .filter_map(|chunk| chunk.inspect_err(UnreadableChunk::warn).ok())
+}
+
+/// Loads a region like `RegionFileLoader::region` does, but through
+/// [`readable_region`], so that LZ4-compressed chunks can be read too.
+/// `Ok(None)` means that the region file doesn't exist.
+pub fn open_region(
+    region_dir: &Path,
+    x: RCoord,
+    z: RCoord,
+) -> fastanvil::Result<Option<Region<RegionStream<File>>>> {
+    let path = region_dir.join(format!("r.{}.{}.mca", x.0, z.0));
+    match File::open(path) {
+        Ok(file) => readable_region(file, (x.0, z.0)).map(Some),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Loads a region like [`open_region`] does, but memory-maps the file instead
+/// of reading it through a `File` handle. `Ok(None)` means that the region file
+/// doesn't exist.
+#[cfg(feature = "mmap")]
+pub fn mmap_region(
+    region_dir: &Path,
+    x: RCoord,
+    z: RCoord,
+) -> fastanvil::Result<Option<Region<RegionStream<io::Cursor<Mmap>>>>> {
+    let path = region_dir.join(format!("r.{}.{}.mca", x.0, z.0));
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    // SAFETY: the mapping is only valid as long as nobody truncates the file
+    // while we're reading it. The same caveat applies to a running server
+    // writing the region while we scan it, which isn't supported anyway.
+    let map = unsafe { Mmap::map(&file)? };
+    readable_region(io::Cursor::new(map), (x.0, z.0)).map(Some)
+}
+
+/// The compression scheme of chunks written by Minecraft 1.20.5+ with
+/// `region-file-compression=lz4`, which fastanvil can't decompress.
+pub const LZ4_COMPRESSION: u8 = 4;
+
+/// The stream a region is read from. fastanvil can't decompress LZ4-compressed
+/// chunks, so when the header of one is read, the chunk is decompressed into
+/// memory, and read from there as if it were stored uncompressed.
+pub struct RegionStream<S> {
+    stream: S,
+    /// The size of the sectors given to each chunk, by the offset of the chunk.
+    chunk_sizes: BTreeMap<u64, u64>,
+    /// The length of `stream`, which the sectors of the chunks are checked
+    /// against.
+    len: u64,
+    /// The offset of the last LZ4-compressed chunk read, and that chunk
+    /// rewritten with its header as an uncompressed one.
+    decompressed: Option<(u64, Vec<u8>)>,
+    pos: u64,
+    /// Whether the position of `stream` is `pos`, which it isn't after reading
+    /// from `decompressed`.
+    in_sync: bool,
+}
+
+impl<S: Read + Seek> RegionStream<S> {
+    fn new(mut stream: S) -> io::Result<Self> {
+        let chunk_sizes = chunk_offsets(&mut stream)?
+            .into_iter()
+            .map(|(_, offset, size)| (offset, size))
+            .collect();
+        let len = stream.seek(SeekFrom::End(0))?;
+        stream.rewind()?;
+        Ok(Self {
+            stream,
+            chunk_sizes,
+            len,
+            decompressed: None,
+            pos: 0,
+            in_sync: true,
+        })
+    }
+
+    /// Reads the LZ4-compressed chunk whose 5-byte header has just been read
+    /// at `offset`, and decompresses it.
+    fn decompress_chunk(&mut self, offset: u64, meta: [u8; 5]) -> io::Result<Vec<u8>> {
+        let invalid = |message: String| io::Error::new(ErrorKind::InvalidData, message);
+        let len = u32::from_be_bytes(meta[..4].try_into().unwrap()).saturating_sub(1) as u64;
+        // The length comes from the file, so it's checked against the sectors
+        // the header gives the chunk before allocating anything.
+        let size = self.chunk_sizes[&offset];
+        if len + 5 > size {
+            return Err(invalid(format!(
+                "the chunk claims to be {len} bytes long, but is given {size} bytes"
+            )));
+        }
+        let mut data = vec![0; len as usize];
+        self.stream.read_exact(&mut data)?;
+        let data = decompress_lz4_chunk(&data)
+            .map_err(|e| invalid(format!("failed to decompress the LZ4 chunk: {e}")))?;
+        let mut chunk = Vec::with_capacity(data.len() + 5);
+        chunk.extend((data.len() as u32 + 1).to_be_bytes());
+        chunk.push(CompressionScheme::Uncompressed as u8);
+        chunk.extend(data);
+        Ok(chunk)
+    }
+}
+
+impl<S: Read + Seek> Read for RegionStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some((start, chunk)) = &self.decompressed {
+            if let Some(rest) = self
+                .pos
+                .checked_sub(*start)
+                .and_then(|at| chunk.get(at as usize..))
+            {
+                if !rest.is_empty() {
+                    let n = rest.len().min(buf.len());
+                    buf[..n].copy_from_slice(&rest[..n]);
+                    self.pos += n as u64;
+                    self.in_sync = false;
+                    return Ok(n);
+                }
+            }
+        }
+        // The sectors the header gives a chunk are checked before seeking to
+        // it, since truncated region files point past their end. The last
+        // sector isn't always padded to its full size.
+        if let Some(&size) = self.chunk_sizes.get(&self.pos) {
+            if self.pos + size > self.len.next_multiple_of(4096) {
+                return Err(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!(
+                        "the chunk at {} is given {size} bytes, past the end of the {} byte file",
+                        self.pos, self.len
+                    ),
+                ));
+            }
+        }
+        if !self.in_sync {
+            self.stream.seek(SeekFrom::Start(self.pos))?;
+            self.in_sync = true;
+        }
+        let offset = self.pos;
+        if buf.len() < 5 || !self.chunk_sizes.contains_key(&offset) {
+            let n = self.stream.read(buf)?;
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        // The header of a chunk, which says how it's compressed.
+        let mut meta = [0u8; 5];
+        self.stream.read_exact(&mut meta)?;
+        if meta[4] != LZ4_COMPRESSION {
+            buf[..5].copy_from_slice(&meta);
+            self.pos += 5;
+            return Ok(5);
+        }
+        let chunk = self.decompress_chunk(offset, meta)?;
+        self.decompressed = Some((offset, chunk));
+        self.read(buf)
+    }
+}
+
+impl<S: Seek> Seek for RegionStream<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::Current(by) => self
+                .pos
+                .checked_add_signed(by)
+                .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "seek before the start"))?,
+            SeekFrom::End(_) => self.stream.seek(pos)?,
+        };
+        self.in_sync = false;
+        Ok(self.pos)
+    }
+}
+
+/// A readable name for the compression scheme byte of a chunk.
+pub fn compression_name(scheme: u8) -> String {
+    match scheme {
+        1 => "gzip".to_owned(),
+        2 => "zlib".to_owned(),
+        3 => "uncompressed".to_owned(),
+        LZ4_COMPRESSION => "lz4".to_owned(),
+        // The chunk is too big for the region and stored in a .mcc file.
+        scheme if scheme & 128 != 0 => format!("external {}", compression_name(scheme & 127)),
+        scheme => format!("unknown ({scheme})"),
+    }
+}
+
+/// The position of a chunk in its region, the offset of its first sector, and
+/// the size of the sectors it's given.
+type ChunkLocation = ((usize, usize), u64, u64);
+
+/// Where each chunk of a region file is stored, read from the header. Ordered
+/// by z, then x, like [`chunks`].
+fn chunk_offsets<S: Read + Seek>(stream: &mut S) -> io::Result<Vec<ChunkLocation>> {
+    let mut header = [0u8; 4096];
+    stream.rewind()?;
+    stream.read_exact(&mut header)?;
+    Ok(header
+        .chunks(4)
+        .enumerate()
+        .filter(|(_, entry)| *entry != [0; 4])
+        .map(|(i, entry)| {
+            let sector = u64::from_be_bytes([0, 0, 0, 0, 0, entry[0], entry[1], entry[2]]);
+            ((i % 32, i / 32), sector * 4096, entry[3] as u64 * 4096)
+        })
+        .collect())
+}
+
+/// The number of chunks stored with each compression scheme (see
+/// [`compression_name`]) in a region file. Chunks past the end of a truncated
+/// file are left out.
+pub fn compression_types<S: Read + Seek>(stream: &mut S) -> io::Result<BTreeMap<u8, usize>> {
+    let mut types = BTreeMap::new();
+    for (_, offset, _) in chunk_offsets(stream)? {
+        stream.seek(SeekFrom::Start(offset + 4))?;
+        let mut scheme = [0u8];
+        match stream.read_exact(&mut scheme) {
+            Ok(()) => *types.entry(scheme[0]).or_default() += 1,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(types)
+}
+
+/// Decompresses a chunk in the LZ4 block stream format of lz4-java, which
+/// Minecraft uses: a series of blocks with a header each, ending with an empty
+/// one. The checksums of the blocks aren't verified.
+pub fn decompress_lz4_chunk(mut data: &[u8]) -> io::Result<Vec<u8>> {
+    const HEADER_LEN: usize = 21;
+    let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_owned());
+    let mut res = vec![];
+    while !data.is_empty() {
+        if data.len() < HEADER_LEN || !data.starts_with(b"LZ4Block") {
+            return Err(invalid("not an LZ4 block stream"));
+        }
+        let (header, rest) = data.split_at(HEADER_LEN);
+        let method = header[8] & 0xF0;
+        let compressed_len = u32::from_le_bytes(header[9..13].try_into().unwrap()) as usize;
+        let decompressed_len = u32::from_le_bytes(header[13..17].try_into().unwrap()) as usize;
+        if decompressed_len == 0 {
+            break;
+        }
+        let block = rest
+            .get(..compressed_len)
+            .ok_or_else(|| invalid("LZ4 block past the end of the chunk"))?;
+        match method {
+            // Stored as is, as it didn't compress.
+            0x10 => res.extend_from_slice(block),
+            0x20 => res.extend(
+                lz4_flex::block::decompress(block, decompressed_len)
+                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            ),
+            _ => return Err(invalid("unknown LZ4 block compression method")),
+        }
+        data = &rest[compressed_len..];
+    }
+    Ok(res)
+}
+
+/// Opens a region from a stream, so that its LZ4-compressed chunks can be read
+/// too (see [`RegionStream`]). The compression schemes of its chunks are logged
+/// at the debug level.
+pub fn readable_region<S: Read + Seek>(
+    mut stream: S,
+    (reg_x, reg_z): (isize, isize),
+) -> fastanvil::Result<Region<RegionStream<S>>> {
+    // Finding them takes a seek per chunk, so it's only done when logged.
+    if log::log_enabled!(log::Level::Debug) {
+        let types = compression_types(&mut stream)?;
+        debug!(
+            "Region ({reg_x}, {reg_z}) chunk compression: {}.",
+            types
+                .iter()
+                .map(|(&scheme, count)| format!("{}: {count}", compression_name(scheme)))
+                .join(", ")
+        );
+    }
+    Region::from_stream(RegionStream::new(stream)?)
+}
+
+#[test]
+fn test_truncated_region() {
+    use crate::fixtures::*;
+    let chunk = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]).to_bytes();
+    let region = region_with(&[((0, 0), chunk.clone()), ((5, 3), chunk)]);
+    let mut data = region.into_inner().unwrap().into_inner();
+    // The chunk written last is at the end of the file - cut it off.
+    data.truncate(data.len() - 4096);
+    let mut region = Region::from_stream(std::io::Cursor::new(data)).unwrap();
+    let found: Vec<(usize, usize)> = readable_chunks(&mut region).map(|c| (c.x, c.z)).collect();
+    assert_eq!(found, [(0, 0)]);
+    let unreadable: Vec<_> = chunks(&mut region)
+        .flatten()
+        .filter_map(Result::err)
+        .collect();
+    assert_eq!(unreadable.len(), 1);
+    assert_eq!((unreadable[0].x, unreadable[0].z), (5, 3));
+    assert!(
+        matches!(&unreadable[0].error, fastanvil::Error::IO(e) if e.kind() == ErrorKind::UnexpectedEof)
+    );
+}
+
+#[test]
+fn test_chunk_sectors_past_end() {
+    use crate::fixtures::*;
+    let chunk = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]).to_bytes();
+    let region = region_with(&[((0, 0), chunk.clone()), ((5, 3), chunk.clone())]);
+    let mut data = region.into_inner().unwrap().into_inner();
+    // The chunk written last is intact, but its header entry claims far more
+    // sectors than are left in the file.
+    let entry = 4 * (3 * 32 + 5);
+    data[entry + 3] = 200;
+    let mut region = readable_region(std::io::Cursor::new(data), (0, 0)).unwrap();
+    let found: Vec<_> = chunks(&mut region).flatten().collect();
+    assert_eq!(found.len(), 2);
+    assert!(matches!(&found[0], Ok(c) if (c.x, c.z) == (0, 0) && c.data == chunk));
+    let Err(unreadable) = &found[1] else {
+        panic!("expected the second chunk to be unreadable");
+    };
+    assert_eq!((unreadable.x, unreadable.z), (5, 3));
+    assert!(
+        matches!(&unreadable.error, fastanvil::Error::IO(e) if e.kind() == ErrorKind::UnexpectedEof)
+    );
+}
+
+#[test]
+fn test_chunks_positions() {
+    use crate::{chunk_inhabited_time, fixtures::*};
+    // Each chunk records its own position in its InhabitedTime, and x and z
+    // are never equal so that swapping them would be caught.
+    let positions = [(3, 17), (17, 3), (31, 0), (0, 31), (30, 29)];
+    let chunk_at = |(x, z): (usize, usize)| {
+        ChunkNbt {
+            inhabited_time: (x * 100 + z) as i64,
+            ..ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")])
+        }
+        .to_bytes()
+    };
+    let region: Vec<_> = positions.iter().map(|&pos| (pos, chunk_at(pos))).collect();
+    let data = region_with(&region).into_inner().unwrap().into_inner();
+    // The header has an entry of 4 bytes per chunk, with x changing fastest.
+    for (i, entry) in data[..4096].chunks(4).enumerate() {
+        let pos = (i % 32, i / 32);
+        assert_eq!(entry != [0; 4], positions.contains(&pos), "{pos:?}");
+    }
+    let mut region = Region::from_stream(std::io::Cursor::new(data)).unwrap();
+    let found: Vec<(usize, usize, i64)> = readable_chunks(&mut region)
+        .map(|c| (c.x, c.z, chunk_inhabited_time(&c.data).unwrap().0))
+        .collect();
+    // Ordered by z, then x.
+    assert_eq!(
+        found,
+        [
+            (31, 0, 3100),
+            (17, 3, 1703),
+            (3, 17, 317),
+            (30, 29, 3029),
+            (0, 31, 31)
+        ]
+    );
+}
+
+#[test]
+fn test_compression_schemes() {
+    use crate::fixtures::*;
+    let chunk = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]).to_bytes();
+    for scheme in 1..=4 {
+        let data = raw_region_with(&[((2, 1), scheme, chunk.clone()), ((4, 9), 2, chunk.clone())]);
+        let mut stream = std::io::Cursor::new(data);
+        let types = compression_types(&mut stream).unwrap();
+        let expected = if scheme == 2 {
+            BTreeMap::from([(2, 2)])
+        } else {
+            BTreeMap::from([(scheme, 1), (2, 1)])
+        };
+        assert_eq!(types, expected, "{}", compression_name(scheme));
+        let mut region = readable_region(stream, (0, 0)).unwrap();
+        let found: Vec<_> = readable_chunks(&mut region).collect();
+        assert_eq!(found.len(), 2, "{}", compression_name(scheme));
+        assert!(found.iter().all(|c| c.data == chunk));
+    }
+}
+
+#[test]
+fn test_lz4_chunk_too_long() {
+    use crate::fixtures::*;
+    let chunk = ChunkNbt::full(vec![SectionNbt::uniform(0, "minecraft:stone")]).to_bytes();
+    let mut data = raw_region_with(&[((2, 1), LZ4_COMPRESSION, chunk.clone()), ((4, 9), 2, chunk)]);
+    // The first chunk claims to be far longer than the sectors it's given.
+    data[8192..8196].copy_from_slice(&u32::MAX.to_be_bytes());
+    let mut region = readable_region(std::io::Cursor::new(data), (0, 0)).unwrap();
+    let found: Vec<_> = chunks(&mut region).flatten().collect();
+    assert_eq!(found.len(), 2);
+    let Err(unreadable) = &found[0] else {
+        panic!("expected the first chunk to be unreadable");
+    };
+    assert_eq!((unreadable.x, unreadable.z), (2, 1));
+    assert!(
+        matches!(&unreadable.error, fastanvil::Error::IO(e) if e.kind() == ErrorKind::InvalidData)
+    );
+    assert!(matches!(&found[1], Ok(c) if (c.x, c.z) == (4, 9)));
 }
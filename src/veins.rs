@@ -0,0 +1,258 @@
+//! How clumped blocks are into veins, which the frequencies by level don't
+//! show.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+    io::{Read, Seek},
+};
+
+use fastanvil::{Chunk, Region};
+
+use crate::{
+    catch_chunk_panic, for_each_chunk, normalize_block_name, ScanOptions, MAX_WORLD_HEIGHT,
+};
+
+/// The number of veins of each size of some blocks in a dimension. A vein is a
+/// group of blocks of the same kind touching each other by their faces.
+pub struct VeinStats {
+    pub dimension: String,
+    /// For every block, the number of veins of each size (in blocks).
+    pub veins: BTreeMap<String, BTreeMap<usize, usize>>,
+    /// The blocks left out of some regions for being too common there, see
+    /// [`MAX_VEIN_BLOCKS_PER_REGION`].
+    pub too_common: BTreeSet<String>,
+    pub chunks_counted: usize,
+}
+
+/// The most blocks of one kind that are followed into veins in a region. Every
+/// one of them is kept in memory, so a block more common than that (like stone)
+/// is left out of the region instead of using gigabytes of memory. That's a
+/// block in every 64 of a region 384 blocks tall.
+pub const MAX_VEIN_BLOCKS_PER_REGION: usize = 1 << 20;
+
+impl VeinStats {
+    pub fn empty(dimension: String) -> Self {
+        Self {
+            dimension,
+            veins: BTreeMap::new(),
+            too_common: BTreeSet::new(),
+            chunks_counted: 0,
+        }
+    }
+
+    pub fn merge(&mut self, other: VeinStats) {
+        for (block, sizes) in other.veins {
+            let block_sizes = self.veins.entry(block).or_default();
+            for (size, count) in sizes {
+                *block_sizes.entry(size).or_default() += count;
+            }
+        }
+        self.too_common.extend(other.too_common);
+        self.chunks_counted += other.chunks_counted;
+    }
+}
+
+/// Finds the veins of the given blocks in a region, following them across the
+/// chunks of the region. Veins that cross into a neighbouring region are cut at
+/// its border, and counted as a vein on each side. Only the chunks that pass
+/// the status and InhabitedTime filters of `options` are looked at, and only
+/// the levels it doesn't exclude.
+pub fn count_veins<R: Read + Seek>(
+    region: &mut Region<R>,
+    block_names: &[String],
+    dimension: &str,
+    options: &ScanOptions,
+) -> VeinStats {
+    let mut stats = VeinStats::empty(dimension.to_owned());
+    // The blocks of interest by their position in the region, as the index of
+    // their name in `block_names`.
+    let mut found: HashMap<(isize, isize, isize), usize> = HashMap::new();
+    let mut found_per_block = vec![0; block_names.len()];
+    for_each_chunk(region, options, |chunk, data, _| {
+        let (xpos, zpos) = (data.x, data.z);
+        if chunk.y_range().len() > MAX_WORLD_HEIGHT {
+            return;
+        }
+        // The chunk is read on its own first, so that if fastanvil panics on
+        // its data, none of it ends up in the veins.
+        let chunk_found = catch_chunk_panic(|| {
+            let mut chunk_found = vec![];
+            let levels = options.clamp_levels(chunk.y_range());
+            for (y, z, x) in itertools::iproduct!(levels, 0..16, 0..16) {
+                if options.is_level_excluded(dimension, y) {
+                    continue;
+                }
+                let Some(block) = chunk.block(x, y, z) else {
+                    continue;
+                };
+                let name = if options.normalize_names {
+                    normalize_block_name(block.name())
+                } else {
+                    block.name().into()
+                };
+                if let Some(index) = block_names.iter().position(|wanted| *wanted == name) {
+                    let pos = ((xpos * 16 + x) as isize, y, (zpos * 16 + z) as isize);
+                    chunk_found.push((pos, index));
+                }
+            }
+            chunk_found
+        });
+        let Some(chunk_found) = chunk_found else {
+            soft_warn!(
+                "Reading chunk ({xpos}, {zpos}) of a region in {dimension} failed, its data is \
+                 probably corrupt. Skipping it."
+            );
+            return;
+        };
+        for (pos, index) in chunk_found {
+            if stats.too_common.contains(&block_names[index]) {
+                continue;
+            }
+            found_per_block[index] += 1;
+            if found_per_block[index] > MAX_VEIN_BLOCKS_PER_REGION {
+                stats.too_common.insert(block_names[index].clone());
+                found.retain(|_, found_index| *found_index != index);
+                continue;
+            }
+            found.insert(pos, index);
+        }
+        stats.chunks_counted += 1;
+    });
+
+    let starts: Vec<_> = found.keys().copied().collect();
+    for start in starts {
+        // Already part of a vein found from another of its blocks.
+        let Some(index) = found.remove(&start) else {
+            continue;
+        };
+        let mut size = 0;
+        let mut to_visit = vec![start];
+        while let Some((x, y, z)) = to_visit.pop() {
+            size += 1;
+            for neighbour in [
+                (x - 1, y, z),
+                (x + 1, y, z),
+                (x, y - 1, z),
+                (x, y + 1, z),
+                (x, y, z - 1),
+                (x, y, z + 1),
+            ] {
+                if found.get(&neighbour) == Some(&index) {
+                    found.remove(&neighbour);
+                    to_visit.push(neighbour);
+                }
+            }
+        }
+        *stats
+            .veins
+            .entry(block_names[index].clone())
+            .or_default()
+            .entry(size)
+            .or_default() += 1;
+    }
+    stats
+}
+
+/// Generates a CSV with a row per dimension, block and size of its veins that
+/// occurs, with the number of veins of that size.
+pub fn generate_veins_csv(stats: &[VeinStats]) -> String {
+    let mut res = String::new();
+    res.write_str("dim,block,vein_size,count\n").unwrap();
+    for dim_stats in stats {
+        for (block, sizes) in &dim_stats.veins {
+            for (size, count) in sizes {
+                writeln!(res, "{},{},{},{}", dim_stats.dimension, block, size, count)
+                    .expect("Error when assembling CSV");
+            }
+        }
+    }
+    res
+}
+
+#[test]
+fn test_count_veins() {
+    use crate::fixtures::*;
+    use itertools::Itertools;
+    let blocks = [
+        "minecraft:stone",
+        "minecraft:iron_ore",
+        "minecraft:coal_ore",
+    ];
+    let chunk = |ore_at: fn(usize, usize, usize) -> usize| {
+        ChunkNbt::full(vec![SectionNbt::with_blocks(0, &blocks, ore_at)]).to_bytes()
+    };
+    let mut region = region_with(&[
+        (
+            (0, 0),
+            chunk(|x, y, z| match (x, y, z) {
+                // A vein of 3 iron ores, next to a single coal ore that
+                // isn't part of it.
+                (3, 4, 5) | (4, 4, 5) | (4, 5, 5) => 1,
+                (3, 5, 5) => 2,
+                // A vein of 2 iron ores touching only by an edge, so two
+                // veins of 1.
+                (8, 8, 8) | (9, 9, 8) => 1,
+                // Half of a vein crossing into the next chunk.
+                (15, 0, 0) => 1,
+                _ => 0,
+            }),
+        ),
+        (
+            (1, 0),
+            chunk(|x, y, z| usize::from((x, y, z) == (0, 0, 0) || (x, y, z) == (1, 0, 0))),
+        ),
+    ]);
+    let names = blocks[1..]
+        .iter()
+        .map(|&name| name.to_owned())
+        .collect_vec();
+    let stats = count_veins(&mut region, &names, "test:dim", &ScanOptions::default());
+    assert_eq!(stats.chunks_counted, 2);
+    assert_eq!(
+        stats.veins,
+        BTreeMap::from([
+            (
+                "minecraft:iron_ore".to_owned(),
+                BTreeMap::from([(1, 2), (3, 2)])
+            ),
+            ("minecraft:coal_ore".to_owned(), BTreeMap::from([(1, 1)])),
+        ])
+    );
+    assert_eq!(
+        generate_veins_csv(&[stats]).lines().nth(1),
+        Some("test:dim,minecraft:coal_ore,1,1")
+    );
+}
+
+#[test]
+fn test_veins_excluded_and_too_common() {
+    use crate::fixtures::*;
+    let blocks = ["minecraft:stone", "minecraft:iron_ore"];
+    // Iron ore on the bottom level of every chunk, under stone.
+    let chunk = ChunkNbt::full(vec![SectionNbt::with_blocks(0, &blocks, |_, y, _| {
+        usize::from(y == 0)
+    })])
+    .to_bytes();
+    let chunk_count = MAX_VEIN_BLOCKS_PER_REGION / (16 * 16 * 15) + 1;
+    let chunks: Vec<_> = (0..chunk_count)
+        .map(|i| ((i % 32, i / 32), chunk.clone()))
+        .collect();
+    let mut region = region_with(&chunks);
+    let names = blocks.map(str::to_owned);
+    let stats = count_veins(&mut region, &names, "test:dim", &ScanOptions::default());
+    assert_eq!(stats.chunks_counted, chunk_count);
+    assert_eq!(stats.too_common, BTreeSet::from([names[0].clone()]));
+    // The iron ore forms one layer touching across the chunks.
+    assert_eq!(
+        stats.veins,
+        BTreeMap::from([(names[1].clone(), BTreeMap::from([(chunk_count * 256, 1)]))])
+    );
+
+    let options = ScanOptions {
+        y_range: Some(1..=15),
+        ..ScanOptions::default()
+    };
+    let stats = count_veins(&mut region, &names[1..], "test:dim", &options);
+    assert!(stats.veins.is_empty());
+}